@@ -102,12 +102,20 @@ async fn radio_receive(mut radio: esp_ieee802154::Ieee802154<'static>, mut tx: u
                         // Hacky way to get the radio to actually change channel
                         let _ = radio.transmit_raw(&[0x02, 0x00, 0x04]);
                     }
-                    wire_format::Packet::Power(_) => (),
+                    wire_format::Packet::Power(power) => {
+                        configuration.txpower = power.clamp(i32::from(i8::MIN), i32::from(i8::MAX)) as i8;
+                        defmt::info!("CTL: Set power {}", configuration.txpower);
+                        radio.set_config(configuration);
+                    }
                     wire_format::Packet::Probe(magic) => {
                         if magic == wire_format::PROBE_HOST {
                             let tx_packet = wire_format::Packet::Probe(wire_format::PROBE_DEVICE);
                             let uart_data = defmt::unwrap!(tx_packet.encode(&mut utx_buffer));
                             defmt::unwrap!(embedded_io_async::Write::write_all(&mut tx, uart_data).await);
+                            let state = wire_format::State { channel: configuration.channel, capturing: capture_enable };
+                            let tx_packet = wire_format::Packet::State(state);
+                            let uart_data = defmt::unwrap!(tx_packet.encode(&mut utx_buffer));
+                            defmt::unwrap!(embedded_io_async::Write::write_all(&mut tx, uart_data).await);
                         }
                     },
                     wire_format::Packet::CaptureStart => {
@@ -119,7 +127,21 @@ async fn radio_receive(mut radio: esp_ieee802154::Ieee802154<'static>, mut tx: u
                         capture_enable = false;
                         defmt::info!("CTL: Stop capture");
                     }
-                    wire_format::Packet::NoOperation | wire_format::Packet::Reset | wire_format::Packet::CaptureFrame(_) => (),
+                    wire_format::Packet::Transmit(transmit) => {
+                        let status = if transmit.payload.len() > wire_format::MAX_TRANSMIT_LENGTH {
+                            wire_format::TransmitStatus::Failed
+                        } else {
+                            match radio.transmit_raw(&transmit.payload) {
+                                Ok(()) => wire_format::TransmitStatus::Sent,
+                                Err(_) => wire_format::TransmitStatus::Failed,
+                            }
+                        };
+                        defmt::info!("CTL: Transmit {} {}", transmit.id, status);
+                        let tx_packet = wire_format::Packet::TransmitResult(wire_format::TransmitResult { id: transmit.id, status });
+                        let uart_data = defmt::unwrap!(tx_packet.encode(&mut utx_buffer));
+                        defmt::unwrap!(embedded_io_async::Write::write_all(&mut tx, uart_data).await);
+                    }
+                    wire_format::Packet::NoOperation | wire_format::Packet::Reset | wire_format::Packet::CaptureFrame(_) | wire_format::Packet::TransmitResult(_) | wire_format::Packet::State(_) => (),
                 }
             }
         }
@@ -140,7 +162,7 @@ async fn main(spawner: Spawner) {
 
     embassy::init(&clocks, timer_group0);
 
-    let uart_config = uart::config::Config { baudrate: 250_000, ..uart::config::Config::default() };
+    let uart_config = uart::config::Config { baudrate: wire_format::BAUD_RATE, ..uart::config::Config::default() };
     type Pins<'a> = uart::TxRxPins<'a, gpio::GpioPin<gpio::Output<gpio::PushPull>, 2>, gpio::GpioPin<gpio::Input<gpio::Floating>, 0>>;
     let mut uart0 = Uart::new_with_config(peripherals.UART0, uart_config, None::<Pins<'_>>, &clocks);
     uart0
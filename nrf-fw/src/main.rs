@@ -19,6 +19,14 @@ bind_interrupts!(struct Irqs {
 
 static CHANNEL: Channel<ThreadModeRawMutex, wire_format::Packet, 4> = Channel::new();
 
+/// Transmit power levels of the nRF52833 radio in dBm, the radio rejects other values
+const TX_POWER_LEVELS: [i8; 14] = [-40, -20, -16, -12, -8, -4, 0, 2, 3, 4, 5, 6, 7, 8];
+
+/// Highest supported transmit power not above the requested one, or the lowest supported
+fn tx_power(power: i32) -> i8 {
+    TX_POWER_LEVELS.iter().copied().filter(|&level| i32::from(level) <= power).last().unwrap_or(TX_POWER_LEVELS[0])
+}
+
 #[embassy_executor::task]
 async fn uart_reader(mut rx: uarte::UarteRx<'static, peripherals::UARTE0>) {
 
@@ -92,17 +100,41 @@ async fn main(spawner: Spawner) {
                     defmt::info!("RRX: Received {}", packet);
                     match packet {
                         wire_format::Packet::Channel(channel) => { configured_channel = *channel; radio.set_channel(*channel); }
-                        wire_format::Packet::Power(_power) => (),
+                        wire_format::Packet::Power(power) => {
+                            let power = tx_power(*power);
+                            defmt::info!("RRX: Set power {}", power);
+                            radio.set_transmission_power(power);
+                        }
                         wire_format::Packet::Probe(magic) => {
                             if *magic == wire_format::PROBE_HOST {
                                 let tx_packet = wire_format::Packet::Probe(wire_format::PROBE_DEVICE);
                                 let uart_data = defmt::unwrap!(tx_packet.encode(&mut utx_buffer));
                                 defmt::unwrap!(tx.write(uart_data).await);
+                                let state = wire_format::State { channel: configured_channel, capturing: capture_enable };
+                                let tx_packet = wire_format::Packet::State(state);
+                                let uart_data = defmt::unwrap!(tx_packet.encode(&mut utx_buffer));
+                                defmt::unwrap!(tx.write(uart_data).await);
                             }
                         },
                         wire_format::Packet::CaptureStart => { capture_enable = true; }
                         wire_format::Packet::CaptureStop => { capture_enable = false; }
-                        wire_format::Packet::NoOperation | wire_format::Packet::Reset | wire_format::Packet::CaptureFrame(_) => (),
+                        wire_format::Packet::Transmit(transmit) => {
+                            let status = if transmit.payload.len() > wire_format::MAX_TRANSMIT_LENGTH {
+                                wire_format::TransmitStatus::Failed
+                            } else {
+                                let mut tx_frame = radio::ieee802154::Packet::new();
+                                tx_frame.copy_from_slice(&transmit.payload);
+                                match radio.try_send(&mut tx_frame).await {
+                                    Ok(()) => wire_format::TransmitStatus::Sent,
+                                    Err(radio::Error::ChannelInUse) => wire_format::TransmitStatus::ChannelBusy,
+                                    Err(_) => wire_format::TransmitStatus::Failed,
+                                }
+                            };
+                            let tx_packet = wire_format::Packet::TransmitResult(wire_format::TransmitResult { id: transmit.id, status });
+                            let uart_data = defmt::unwrap!(tx_packet.encode(&mut utx_buffer));
+                            defmt::unwrap!(tx.write(uart_data).await);
+                        }
+                        wire_format::Packet::NoOperation | wire_format::Packet::Reset | wire_format::Packet::CaptureFrame(_) | wire_format::Packet::TransmitResult(_) | wire_format::Packet::State(_) => (),
                     }
                 }
             }
@@ -0,0 +1,382 @@
+//! Capture pipeline from a sniffer session to the pcapng output
+//!
+//! The capture runs in three threads. The reader thread owns the sniffer session, it blocks
+//! reading the device and queues received frames. The writer thread writes queued frames to
+//! the capture and to ZEP. The control thread applies toolbar changes from Wireshark and
+//! passes device commands to the reader thread. All threads stop when the stop flag is set,
+//! either by a signal or by a thread that failed.
+use crate::{filter::Filter, toolbar, Error};
+use ieee802154_sniffer_host::{
+    link_type::LinkType, pcapng, rotation, security, session, tap, wire_format, zep,
+};
+use r_extcap::controls::synchronous::ChannelExtcapControlReader;
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender, TrySendError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Number of frames queued between the reader and the writer
+const QUEUE_LENGTH: usize = 1024;
+
+/// Time between checks of the stop flag while waiting for control packets
+const CONTROL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Device command from the control thread
+enum Command {
+    Channel(u8),
+    Power(i32),
+    StartCapture,
+    StopCapture,
+}
+
+/// Frame queued for the writer
+struct Record {
+    timestamp: Duration,
+    frame: wire_format::Frame,
+    /// Frames lost before this frame, corrupted or not fitting in the queue
+    dropped: u64,
+    /// The device was lost for this long before this frame
+    reconnected: Option<Duration>,
+}
+
+/// Capture ready to run
+pub(crate) struct Capture<W: Write> {
+    pub(crate) session: session::Session,
+    pub(crate) writer: pcapng::CaptureWriter<W>,
+    pub(crate) interface_id: u32,
+    /// Link type the frames are written with
+    pub(crate) link_type: LinkType,
+    pub(crate) toolbar: toolbar::Toolbar,
+    pub(crate) control_reader: Option<ChannelExtcapControlReader>,
+    pub(crate) zep_sender: Option<zep::ZepSender>,
+    /// Frames are only sent as ZEP, not written to the capture
+    pub(crate) zep_only: bool,
+    /// Frames passed on, other frames are discarded by the reader
+    pub(crate) filter: Filter,
+    /// Frames are also written to rotating files
+    pub(crate) files: Option<rotation::RotatingWriter>,
+    /// The capture stops when a limit is reached
+    pub(crate) limits: rotation::Limits,
+    /// Secured frames verified by a key are passed on decrypted
+    pub(crate) keys: Option<security::KeyTable>,
+}
+
+/// Set the stop flag if the thread failed
+fn stop_on_error(stop: &AtomicBool, result: Result<(), Error>) -> Result<(), Error> {
+    if result.is_err() {
+        stop.store(true, Ordering::Relaxed);
+    }
+    result
+}
+
+/// Reader thread, receive frames until stopped or a limit is reached
+fn read(
+    session: &mut session::Session,
+    filter: &Filter,
+    limits: rotation::Limits,
+    records: SyncSender<Record>,
+    commands: Receiver<Command>,
+    stop: &AtomicBool,
+) -> Result<(), Error> {
+    let mut dropped = 0;
+    let mut reconnected = None;
+    let mut frames = 0;
+    let start = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        if limits.reached(frames, start.elapsed()) {
+            stop.store(true, Ordering::Relaxed);
+            break;
+        }
+        for command in commands.try_iter() {
+            match command {
+                Command::Channel(channel) => session.set_channel(channel)?,
+                Command::Power(power) => session.set_power(power)?,
+                Command::StartCapture => session.start_capture()?,
+                Command::StopCapture => session.stop_capture()?,
+            }
+        }
+        match session.receive()? {
+            Some(session::Event::Packet(wire_format::Packet::CaptureFrame(frame)))
+                if filter.matches(&frame.payload) =>
+            {
+                let record = Record {
+                    timestamp: pcapng::now(),
+                    frame,
+                    dropped,
+                    reconnected,
+                };
+                match records.try_send(record) {
+                    Ok(()) => {
+                        dropped = 0;
+                        reconnected = None;
+                        frames += 1;
+                    }
+                    Err(TrySendError::Full(_)) => dropped += 1,
+                    Err(TrySendError::Disconnected(_)) => break,
+                }
+            }
+            Some(session::Event::Corrupted) => {
+                // A packet that failed to decode is a lost frame
+                dropped += 1;
+            }
+            Some(session::Event::Lost(error)) => {
+                eprintln!("Device {} lost, {}", session.uri(), error);
+            }
+            Some(session::Event::Reconnected(gap)) => {
+                eprintln!("Device reconnected after {:.1} s", gap.as_secs_f64());
+                reconnected = Some(gap);
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Writer thread, write frames until the reader stops
+#[allow(clippy::too_many_arguments)]
+fn write<W: Write>(
+    writer: &mut pcapng::CaptureWriter<W>,
+    interface_id: u32,
+    link_type: LinkType,
+    mut zep_sender: Option<zep::ZepSender>,
+    zep_only: bool,
+    mut files: Option<&mut rotation::RotatingWriter>,
+    keys: Option<&security::KeyTable>,
+    records: Receiver<Record>,
+) -> Result<(), Error> {
+    let mut data = [0u8; tap::BUFFER_SIZE];
+    for mut record in records {
+        let decrypted = keys
+            .and_then(|keys| keys.decrypt(&record.frame.payload).ok())
+            .and_then(|frame| wire_format::Payload::from_slice(&frame).ok());
+        let decrypted = match decrypted {
+            Some(payload) => {
+                record.frame.payload = payload;
+                true
+            }
+            None => false,
+        };
+        if let Some(zep_sender) = zep_sender.as_mut() {
+            if let Err(error) = zep_sender.send(&record.frame, record.timestamp) {
+                eprintln!("Failed to send ZEP, {}", error);
+            }
+        }
+        let length = link_type.encode(&record.frame, &mut data);
+        let annotation = pcapng::PacketAnnotation {
+            channel: record.frame.channel,
+            dropped: record.dropped,
+            reconnected: record.reconnected,
+            decrypted,
+            ..Default::default()
+        };
+        if let Some(files) = files.as_mut() {
+            files.write_packet(record.timestamp, &data[..length], &annotation)?;
+        }
+        if !zep_only {
+            writer.write_packet(interface_id, record.timestamp, &data[..length], &annotation)?;
+        }
+    }
+    Ok(())
+}
+
+/// Control thread, apply toolbar changes until stopped
+fn control(
+    toolbar: &mut toolbar::Toolbar,
+    control_reader: Option<ChannelExtcapControlReader>,
+    commands: Sender<Command>,
+    stop: &AtomicBool,
+) -> Result<(), Error> {
+    let control_reader = match control_reader {
+        Some(control_reader) => control_reader,
+        None => return Ok(()),
+    };
+    while !stop.load(Ordering::Relaxed) {
+        let packet = match control_reader
+            .read_channel
+            .recv_timeout(CONTROL_POLL_INTERVAL)
+        {
+            Ok(packet) => packet,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            // Wireshark closed the control pipe
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        let command = match toolbar::parse(&packet) {
+            Some(toolbar::Action::Channel(channel)) => {
+                toolbar.set_channel(channel)?;
+                Command::Channel(channel)
+            }
+            Some(toolbar::Action::Power(power)) => {
+                toolbar.set_power(power)?;
+                Command::Power(power)
+            }
+            Some(toolbar::Action::TogglePause) => {
+                let paused = !toolbar.paused();
+                toolbar.set_paused(paused)?;
+                if paused {
+                    Command::StopCapture
+                } else {
+                    Command::StartCapture
+                }
+            }
+            None => continue,
+        };
+        if commands.send(command).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl<W: Write + Send> Capture<W> {
+    /// Capture until `stop` is set or an error occurs, then stop the device and complete
+    /// the capture
+    ///
+    /// Wireshark closing the FIFO is a normal way for a capture to end and is not reported
+    /// as an error.
+    pub(crate) fn run(self, stop: &AtomicBool) -> Result<(), Error> {
+        let Capture {
+            mut session,
+            mut writer,
+            interface_id,
+            link_type,
+            mut toolbar,
+            control_reader,
+            zep_sender,
+            zep_only,
+            filter,
+            mut files,
+            limits,
+            keys,
+        } = self;
+        let (record_sender, record_receiver) = mpsc::sync_channel(QUEUE_LENGTH);
+        let (command_sender, command_receiver) = mpsc::channel();
+
+        let result = thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                let result = read(
+                    &mut session,
+                    &filter,
+                    limits,
+                    record_sender,
+                    command_receiver,
+                    stop,
+                );
+                stop_on_error(stop, result)
+            });
+            let writer = scope.spawn(|| {
+                let result = write(
+                    &mut writer,
+                    interface_id,
+                    link_type,
+                    zep_sender,
+                    zep_only,
+                    files.as_mut(),
+                    keys.as_ref(),
+                    record_receiver,
+                );
+                stop_on_error(stop, result)
+            });
+            let control = scope.spawn(|| {
+                let result = control(&mut toolbar, control_reader, command_sender, stop);
+                stop_on_error(stop, result)
+            });
+            let written = writer.join().expect("Writer thread panicked");
+            let read = reader.join().expect("Reader thread panicked");
+            let controlled = control.join().expect("Control thread panicked");
+            written.and(read).and(controlled)
+        });
+
+        let stopped = session.stop_capture().map_err(Error::from);
+        let finished = files.map_or(Ok(()), |files| files.finish().map_err(Error::from));
+        let result = match result.and_then(|()| writer.finish().map(drop).map_err(Error::from)) {
+            Err(error) if error.is_broken_pipe() => Ok(()),
+            result => result,
+        };
+        result.and(finished).and(stopped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pcap_file::pcapng::blocks::enhanced_packet::EnhancedPacketOption;
+
+    fn frame(sequence: u8, channel: u8) -> wire_format::Frame {
+        wire_format::Frame {
+            channel,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, sequence]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn write_records() {
+        let mut writer = pcapng::CaptureWriter::new(Vec::new()).unwrap();
+        let interface = pcapng::InterfaceDescription {
+            name: String::from("test"),
+            channel: 15,
+            serial_number: None,
+        };
+        let interface_id = writer
+            .add_interface(pcap_file::DataLink::IEEE802_15_4_TAP, &interface)
+            .unwrap();
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_LENGTH);
+        // The channel was changed from the toolbar before the second frame
+        for (sequence, channel, dropped) in [(1, 15, 0), (2, 20, 3)] {
+            sender
+                .send(Record {
+                    timestamp: Duration::from_secs(1),
+                    frame: frame(sequence, channel),
+                    dropped,
+                    reconnected: None,
+                })
+                .unwrap();
+        }
+        drop(sender);
+        write(
+            &mut writer,
+            interface_id,
+            LinkType::Tap,
+            None,
+            false,
+            None,
+            None,
+            receiver,
+        )
+        .unwrap();
+        let data = writer.finish().unwrap();
+
+        let mut reader = pcap_file::pcapng::PcapNgReader::new(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        let mut comments = Vec::new();
+        while let Some(block) = reader.next_block() {
+            if let pcap_file::pcapng::Block::EnhancedPacket(packet) = block.unwrap() {
+                frames.push(packet.data.last().copied());
+                comments.extend(packet.options.iter().filter_map(|option| match option {
+                    EnhancedPacketOption::Comment(comment) => Some(comment.to_string()),
+                    _ => None,
+                }));
+            }
+        }
+        assert_eq!(frames, vec![Some(1), Some(2)]);
+        assert_eq!(
+            comments,
+            ["Channel 15", "Channel 20, Gap detected, 3 packet(s) lost"]
+        );
+    }
+
+    #[test]
+    fn stop_flag_on_error() {
+        let stop = AtomicBool::new(false);
+        assert!(stop_on_error(&stop, Ok(())).is_ok());
+        assert!(!stop.load(Ordering::Relaxed));
+        assert!(stop_on_error(&stop, Err(Error::MissingInterface)).is_err());
+        assert!(stop.load(Ordering::Relaxed));
+    }
+}
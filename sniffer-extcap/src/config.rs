@@ -0,0 +1,511 @@
+//! Configuration file with the defaults for captures and discovery
+//!
+//! The file is TOML, found as described in [`config_file`] with the path given with
+//! `--config`.
+//!
+//! ```toml
+//! [capture]
+//! channel = 15
+//! link_type = "fcs"
+//!
+//! [capture.zep]
+//! destination = "192.168.1.20"
+//!
+//! [capture.filter]
+//! frame_types = ["data", "command"]
+//!
+//! [capture.files]
+//! directory = "/var/lib/ieee802154-sniffer"
+//! file_duration = 3600
+//! max_files = 24
+//!
+//! [discovery]
+//! usb_ids = ["0403:6001"]
+//! remotes = ["lab:4000"]
+//!
+//! [devices."E1A2B3C4D5E6F708"]
+//! channel = 20
+//! baud_rate = 115200
+//!
+//! [[security.keys]]
+//! key = "c0c1c2c3c4c5c6c7c8c9cacbcccdcecf"
+//! pan_id = 0x4321
+//!
+//! [[security.addresses]]
+//! pan_id = 0x4321
+//! short = 0x0001
+//! extended = "ac:de:48:00:00:00:00:01"
+//!
+//! [[sixlowpan.contexts]]
+//! id = 0
+//! prefix = "fd11:22::/64"
+//! ```
+//!
+//! The `[capture]` settings apply to every device, a `[devices."<id>"]` section overrides
+//! them for one device, identified by USB serial number or by interface name. Command line
+//! arguments override both. The `[security]` keys decrypt secured frames from every device. The
+//! `[sixlowpan]` section is for `ieee802154-sniffer-tool ipv6`, which reads the same file, and
+//! is not used by the extcap.
+use crate::{filter::Filter, toolbar, Error};
+use ieee802154_sniffer_host::{
+    config_file, discovery, link_type::LinkType, mac, security, transport,
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Capture settings, every setting is optional so that sections can be merged
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Settings {
+    /// IEEE 802.15.4 channel
+    pub(crate) channel: Option<u8>,
+    /// Serial port baud rate
+    pub(crate) baud_rate: Option<u32>,
+    /// Link type of the capture, see [`LinkType::name`]
+    pub(crate) link_type: Option<String>,
+    pub(crate) zep: ZepSettings,
+    pub(crate) filter: FilterSettings,
+    pub(crate) files: FileSettings,
+    pub(crate) limits: LimitSettings,
+}
+
+/// ZEP output settings
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ZepSettings {
+    /// UDP destination, `host` or `host:port`
+    pub(crate) destination: Option<String>,
+    pub(crate) device_id: Option<u16>,
+    /// Only send captured frames as ZEP
+    pub(crate) only: Option<bool>,
+}
+
+/// Frames passed on, frames not matching are not written nor sent
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FilterSettings {
+    /// MAC frame types, see [`mac::FrameType::name`]
+    pub(crate) frame_types: Option<Vec<String>>,
+    /// PAN identifiers
+    pub(crate) pan_ids: Option<Vec<u16>>,
+}
+
+/// Ring buffer of capture files, written in addition to the Wireshark FIFO
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FileSettings {
+    /// Directory of the capture files, no files are written when unset
+    pub(crate) directory: Option<PathBuf>,
+    /// Start a new file at this size in bytes
+    pub(crate) file_size: Option<u64>,
+    /// Start a new file after this many seconds
+    pub(crate) file_duration: Option<u64>,
+    /// Keep this many files, removing the oldest
+    pub(crate) max_files: Option<usize>,
+}
+
+/// When to stop the capture
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LimitSettings {
+    /// Stop after this many frames
+    pub(crate) frames: Option<u64>,
+    /// Stop after this many seconds
+    pub(crate) duration: Option<u64>,
+}
+
+/// Discovery settings, added to those from the environment
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DiscoverySettings {
+    /// Extra USB identifiers, as hexadecimal `vid:pid`
+    pub(crate) usb_ids: Vec<String>,
+    /// Serial ports or port patterns
+    pub(crate) ports: Vec<String>,
+    pub(crate) probe_all: bool,
+    /// Remote sniffer servers, as `host:port`
+    pub(crate) remotes: Vec<String>,
+}
+
+/// Key for decrypting secured frames, the optional fields limit the frames it is used for
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KeySettings {
+    /// AES-128 key as 32 hexadecimal digits
+    pub(crate) key: String,
+    pub(crate) pan_id: Option<u16>,
+    pub(crate) key_index: Option<u8>,
+    /// Source address, `0x1234` or `01:23:45:67:89:ab:cd:ef`
+    pub(crate) source: Option<String>,
+}
+
+/// Extended address of a device sending with a short address, needed for the nonce
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AddressSettings {
+    pub(crate) pan_id: u16,
+    pub(crate) short: u16,
+    /// Extended address, `01:23:45:67:89:ab:cd:ef`
+    pub(crate) extended: String,
+}
+
+/// Decryption of secured frames, frames are written decrypted when a key verifies them
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SecuritySettings {
+    pub(crate) keys: Vec<KeySettings>,
+    pub(crate) addresses: Vec<AddressSettings>,
+}
+
+/// Contents of the configuration file
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub(crate) capture: Settings,
+    pub(crate) discovery: DiscoverySettings,
+    pub(crate) security: SecuritySettings,
+    /// 6LoWPAN contexts, read and checked by the tool
+    pub(crate) sixlowpan: toml::Table,
+    /// Settings per device, by USB serial number or interface name
+    pub(crate) devices: BTreeMap<String, Settings>,
+}
+
+impl Settings {
+    /// These settings, with the unset ones taken from `defaults`
+    fn or(self, defaults: &Settings) -> Settings {
+        Settings {
+            channel: self.channel.or(defaults.channel),
+            baud_rate: self.baud_rate.or(defaults.baud_rate),
+            link_type: self.link_type.or(defaults.link_type.clone()),
+            zep: ZepSettings {
+                destination: self.zep.destination.or(defaults.zep.destination.clone()),
+                device_id: self.zep.device_id.or(defaults.zep.device_id),
+                only: self.zep.only.or(defaults.zep.only),
+            },
+            filter: FilterSettings {
+                frame_types: self
+                    .filter
+                    .frame_types
+                    .or(defaults.filter.frame_types.clone()),
+                pan_ids: self.filter.pan_ids.or(defaults.filter.pan_ids.clone()),
+            },
+            files: FileSettings {
+                directory: self.files.directory.or(defaults.files.directory.clone()),
+                file_size: self.files.file_size.or(defaults.files.file_size),
+                file_duration: self.files.file_duration.or(defaults.files.file_duration),
+                max_files: self.files.max_files.or(defaults.files.max_files),
+            },
+            limits: LimitSettings {
+                frames: self.limits.frames.or(defaults.limits.frames),
+                duration: self.limits.duration.or(defaults.limits.duration),
+            },
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(channel) = self.channel {
+            if !toolbar::CHANNELS.contains(&channel) {
+                return Err(format!(
+                    "invalid channel {}, expected a channel between 11 and 26",
+                    channel
+                ));
+            }
+        }
+        self.link_type()?;
+        self.filter()?;
+        Ok(())
+    }
+
+    /// Link type of the capture, IEEE 802.15.4 TAP by default
+    pub(crate) fn link_type(&self) -> Result<LinkType, String> {
+        match self.link_type.as_deref() {
+            Some(name) => LinkType::from_name(name).ok_or_else(|| {
+                format!("unknown link type \"{}\", expected tap, fcs or nofcs", name)
+            }),
+            None => Ok(LinkType::Tap),
+        }
+    }
+
+    /// Frame filter from the filter settings
+    pub(crate) fn filter(&self) -> Result<Filter, String> {
+        let frame_types = self
+            .filter
+            .frame_types
+            .iter()
+            .flatten()
+            .map(|name| {
+                mac::FrameType::from_name(name)
+                    .ok_or_else(|| format!("unknown frame type \"{}\"", name))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Filter {
+            frame_types,
+            pan_ids: self.filter.pan_ids.clone().unwrap_or_default(),
+        })
+    }
+}
+
+impl Config {
+    /// Parse and check a configuration
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let config: Config = toml::from_str(text).map_err(|error| error.message().to_string())?;
+        config.capture.validate()?;
+        for (device, settings) in &config.devices {
+            settings
+                .validate()
+                .map_err(|error| format!("device {}, {}", device, error))?;
+        }
+        config.discovery()?;
+        config.key_table()?;
+        Ok(config)
+    }
+
+    /// Load the configuration file, `path` overrides the environment and per-user default
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self, Error> {
+        match config_file::read(path) {
+            Ok(Some((path, text))) => {
+                Self::parse(&text).map_err(|error| Error::Config(path, error))
+            }
+            Ok(None) => Ok(Config::default()),
+            Err((path, error)) => Err(Error::Config(path, error.to_string())),
+        }
+    }
+
+    /// Settings of a device, its own section merged with the capture settings
+    pub(crate) fn settings(&self, uri: &str, serial_number: Option<&str>) -> Settings {
+        let device = serial_number
+            .and_then(|serial_number| self.devices.get(serial_number))
+            .or_else(|| self.devices.get(uri))
+            .cloned()
+            .unwrap_or_default();
+        device.or(&self.capture)
+    }
+
+    /// Settings of the device at an interface, see [`Config::settings`]
+    pub(crate) fn interface_settings(&self, interface: &str) -> Settings {
+        let serial_number = match self.devices.is_empty() {
+            true => None,
+            false => transport::usb_serial_number(interface),
+        };
+        self.settings(interface, serial_number.as_deref())
+    }
+
+    /// Discovery configuration from the file
+    pub(crate) fn discovery(&self) -> Result<discovery::Config, String> {
+        let usb_ids = self
+            .discovery
+            .usb_ids
+            .iter()
+            .map(|usb_id| usb_id.parse())
+            .collect::<Result<_, _>>()?;
+        Ok(discovery::Config {
+            usb_ids,
+            ports: self.discovery.ports.clone(),
+            probe_all: self.discovery.probe_all,
+        })
+    }
+
+    /// Key table from the security settings
+    pub(crate) fn key_table(&self) -> Result<security::KeyTable, String> {
+        let mut table = security::KeyTable::new();
+        for key in &self.security.keys {
+            let source = key
+                .source
+                .as_deref()
+                .map(str::parse::<mac::Address>)
+                .transpose()?;
+            table.add_key(security::Key {
+                key: parse_key(&key.key)?,
+                pan_id: key.pan_id,
+                key_index: key.key_index,
+                source,
+            });
+        }
+        for address in &self.security.addresses {
+            match address.extended.parse::<mac::Address>()? {
+                mac::Address::Extended(extended) => {
+                    table.add_address(address.pan_id, address.short, extended)
+                }
+                mac::Address::Short(_) => {
+                    return Err(format!(
+                        "expected an extended address, \"{}\"",
+                        address.extended
+                    ))
+                }
+            }
+        }
+        Ok(table)
+    }
+}
+
+/// AES-128 key from hexadecimal digits
+fn parse_key(text: &str) -> Result<[u8; security::KEY_LENGTH], String> {
+    let invalid = || format!("invalid key \"{}\", expected 32 hexadecimal digits", text);
+    if text.len() != 2 * security::KEY_LENGTH || !text.is_ascii() {
+        return Err(invalid());
+    }
+    let mut key = [0u8; security::KEY_LENGTH];
+    for (n, octet) in key.iter_mut().enumerate() {
+        *octet = u8::from_str_radix(&text[2 * n..2 * n + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[capture]
+channel = 15
+
+[capture.zep]
+destination = "192.168.1.20"
+device_id = 3
+
+[capture.filter]
+frame_types = ["data", "command"]
+
+[discovery]
+usb_ids = ["0403:6001"]
+ports = ["/dev/ttyUSB*"]
+remotes = ["lab:4000"]
+
+[capture.files]
+directory = "/tmp/captures"
+max_files = 10
+
+[devices."E1A2B3C4"]
+channel = 20
+baud_rate = 115200
+link_type = "nofcs"
+
+[devices."E1A2B3C4".files]
+file_size = 1000000
+
+[devices."/dev/ttyACM0".filter]
+pan_ids = [0x1a62]
+"#;
+
+    #[test]
+    fn merge_device_settings() {
+        let config = Config::parse(CONFIG).unwrap();
+        let settings = config.settings("/dev/ttyUSB3", Some("E1A2B3C4"));
+        assert_eq!(settings.channel, Some(20));
+        assert_eq!(settings.baud_rate, Some(115200));
+        assert_eq!(settings.link_type(), Ok(LinkType::NoFcs));
+        assert_eq!(settings.zep.destination.as_deref(), Some("192.168.1.20"));
+        assert_eq!(settings.zep.device_id, Some(3));
+        let filter = settings.filter().unwrap();
+        assert_eq!(
+            filter.frame_types,
+            [mac::FrameType::Data, mac::FrameType::Command]
+        );
+        assert!(filter.pan_ids.is_empty());
+        assert_eq!(
+            settings.files,
+            FileSettings {
+                directory: Some(PathBuf::from("/tmp/captures")),
+                file_size: Some(1000000),
+                file_duration: None,
+                max_files: Some(10),
+            }
+        );
+
+        let settings = config.settings("/dev/ttyACM0", None);
+        assert_eq!(settings.channel, Some(15));
+        assert_eq!(settings.baud_rate, None);
+        assert_eq!(settings.link_type(), Ok(LinkType::Tap));
+        assert_eq!(settings.filter().unwrap().pan_ids, [0x1a62]);
+
+        let settings = config.settings("tcp://lab:4000", None);
+        assert_eq!(settings, config.capture);
+    }
+
+    #[test]
+    fn discovery_settings() {
+        let config = Config::parse(CONFIG).unwrap();
+        let discovery = config.discovery().unwrap();
+        assert_eq!(
+            discovery.usb_ids,
+            [discovery::UsbId {
+                vid: 0x0403,
+                pid: 0x6001
+            }]
+        );
+        assert_eq!(discovery.ports, ["/dev/ttyUSB*"]);
+        assert!(!discovery.probe_all);
+        assert_eq!(config.discovery.remotes, ["lab:4000"]);
+    }
+
+    #[test]
+    fn security_settings() {
+        let config = Config::parse(
+            r#"
+[[security.keys]]
+key = "c0c1c2c3c4c5c6c7c8c9cacbcccdcecf"
+
+[[security.keys]]
+key = "000102030405060708090a0b0c0d0e0f"
+pan_id = 0x4321
+key_index = 1
+source = "0x0001"
+
+[[security.addresses]]
+pan_id = 0x4321
+short = 0x0001
+extended = "ac:de:48:00:00:00:00:01"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.security.keys.len(), 2);
+        assert!(!config.key_table().unwrap().is_empty());
+        assert_eq!(
+            parse_key("c0c1c2c3c4c5c6c7c8c9cacbcccdcecf").unwrap()[15],
+            0xcf
+        );
+        assert!(Config::parse("[[security.keys]]\nkey = \"c0c1\"\n")
+            .unwrap_err()
+            .contains("invalid key"));
+        assert!(Config::parse(
+            "[[security.addresses]]\npan_id = 1\nshort = 2\nextended = \"0x0003\"\n"
+        )
+        .unwrap_err()
+        .contains("expected an extended address"));
+    }
+
+    #[test]
+    fn sixlowpan_section() {
+        // Read by the tool, the extcap only accepts it
+        let config =
+            Config::parse("[[sixlowpan.contexts]]\nid = 0\nprefix = \"fd11:22::/64\"\n").unwrap();
+        assert!(config.sixlowpan.contains_key("contexts"));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Config::parse("[capture]\nchannel = 27\n")
+            .unwrap_err()
+            .contains("invalid channel 27"));
+        assert!(
+            Config::parse("[devices.a.filter]\nframe_types = [\"bacon\"]\n")
+                .unwrap_err()
+                .contains("device a, unknown frame type \"bacon\"")
+        );
+        assert!(Config::parse("[capture]\nlink_type = \"ethernet\"\n")
+            .unwrap_err()
+            .contains("unknown link type \"ethernet\""));
+        assert!(Config::parse("[discovery]\nusb_ids = [\"0403\"]\n").is_err());
+        assert!(Config::parse("[capture]\nchanel = 15\n")
+            .unwrap_err()
+            .contains("unknown field"));
+    }
+}
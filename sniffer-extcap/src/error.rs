@@ -1,27 +1,154 @@
-use ieee802154_sniffer_wire_format as wire_format;
-use std::convert::From;
+use std::{convert::From, fmt};
 
 #[derive(Debug)]
 pub(crate) enum Error {
-    IoError(std::io::Error),
-    WireError(wire_format::Error),
-    SerialPortError(serialport::Error),
+    Io(std::io::Error),
+    /// Error talking to the sniffer or writing the capture
+    Host(ieee802154_sniffer_host::Error),
+    /// Wireshark invoked the extcap with unexpected arguments
+    Extcap(r_extcap::ExtcapError),
+    /// The control pipe from Wireshark closed before the capture started
+    Control,
+    /// The channel argument is not a valid channel
+    InvalidChannel(String),
+    /// The link type argument is not a known link type
+    InvalidLinkType(String),
+    /// No interface was given for the capture
+    MissingInterface,
+    /// The sniffer could not be opened
+    Open(String, ieee802154_sniffer_host::Error),
+    /// The ZEP destination could not be opened
+    Zep(String, std::io::Error),
+    /// The output directory could not be created
+    Output(std::path::PathBuf, ieee802154_sniffer_host::Error),
+    /// Only ZEP output was selected without a ZEP destination
+    MissingZepDestination,
+    /// The configuration file could not be read or is invalid
+    Config(std::path::PathBuf, String),
+}
+
+impl Error {
+    /// The reader of the capture FIFO went away, the capture was stopped by Wireshark
+    pub(crate) fn is_broken_pipe(&self) -> bool {
+        match self {
+            Error::Io(error) => error.kind() == std::io::ErrorKind::BrokenPipe,
+            Error::Host(error) => error.is_broken_pipe(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error, {}", error),
+            Error::Host(error) => write!(f, "{}", error),
+            Error::Extcap(error) => write!(f, "{}", error),
+            Error::Control => write!(f, "Wireshark control pipe closed"),
+            Error::InvalidChannel(channel) => write!(
+                f,
+                "Invalid channel \"{}\", expected a channel between 11 and 26",
+                channel
+            ),
+            Error::InvalidLinkType(link_type) => write!(
+                f,
+                "Invalid link type \"{}\", expected tap, fcs or nofcs",
+                link_type
+            ),
+            Error::MissingInterface => write!(f, "No interface specified"),
+            Error::Open(interface, error) => {
+                write!(f, "Failed to open sniffer {}, {}", interface, error)
+            }
+            Error::Zep(address, error) => {
+                write!(f, "Failed to open ZEP destination {}, {}", address, error)
+            }
+            Error::Output(directory, error) => write!(
+                f,
+                "Failed to write capture files to {}, {}",
+                directory.display(),
+                error
+            ),
+            Error::MissingZepDestination => write!(f, "ZEP only requires a ZEP destination"),
+            Error::Config(path, error) => {
+                write!(
+                    f,
+                    "Invalid configuration file {}, {}",
+                    path.display(),
+                    error
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) | Error::Zep(_, error) => Some(error),
+            Error::Host(error) => Some(error),
+            Error::Extcap(error) => Some(error),
+            Error::Open(_, error) | Error::Output(_, error) => Some(error),
+            Error::Control
+            | Error::InvalidChannel(_)
+            | Error::InvalidLinkType(_)
+            | Error::MissingInterface
+            | Error::MissingZepDestination
+            | Error::Config(_, _) => None,
+        }
+    }
 }
 
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Self {
-        Error::IoError(error)
+        Error::Io(error)
+    }
+}
+
+impl From<ieee802154_sniffer_host::Error> for Error {
+    fn from(error: ieee802154_sniffer_host::Error) -> Self {
+        Error::Host(error)
     }
 }
 
-impl From<wire_format::Error> for Error {
-    fn from(error: wire_format::Error) -> Self {
-        Error::WireError(error)
+impl From<r_extcap::ExtcapError> for Error {
+    fn from(error: r_extcap::ExtcapError) -> Self {
+        Error::Extcap(error)
     }
 }
 
-impl From<serialport::Error> for Error {
-    fn from(error: serialport::Error) -> Self {
-        Error::SerialPortError(error)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_pipe() {
+        let error = || std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+        assert!(Error::Io(error()).is_broken_pipe());
+        assert!(Error::Host(ieee802154_sniffer_host::Error::Io(error())).is_broken_pipe());
+        assert!(!Error::Io(std::io::ErrorKind::NotFound.into()).is_broken_pipe());
+        assert!(!Error::MissingInterface.is_broken_pipe());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Error::InvalidChannel(String::from("27")).to_string(),
+            "Invalid channel \"27\", expected a channel between 11 and 26"
+        );
+        assert_eq!(
+            Error::InvalidLinkType(String::from("ethernet")).to_string(),
+            "Invalid link type \"ethernet\", expected tap, fcs or nofcs"
+        );
+        let error = Error::Open(
+            String::from("/dev/ttyACM0"),
+            ieee802154_sniffer_host::Error::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "No such file or directory",
+            )),
+        );
+        assert_eq!(
+            error.to_string(),
+            "Failed to open sniffer /dev/ttyACM0, I/O error, No such file or directory"
+        );
     }
 }
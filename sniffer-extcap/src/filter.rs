@@ -0,0 +1,77 @@
+//! Selecting the captured frames passed on to Wireshark and ZEP
+use ieee802154_sniffer_host::mac;
+
+/// Frame filter, an empty list matches every frame
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Filter {
+    /// MAC frame types to pass
+    pub(crate) frame_types: Vec<mac::FrameType>,
+    /// PAN identifiers to pass, frames without PAN identifiers such as acknowledgements
+    /// always pass
+    pub(crate) pan_ids: Vec<u16>,
+}
+
+impl Filter {
+    /// The filter passes every frame
+    pub(crate) fn is_empty(&self) -> bool {
+        self.frame_types.is_empty() && self.pan_ids.is_empty()
+    }
+
+    /// Check a frame without FCS, frames with a header that cannot be parsed only pass an
+    /// empty filter
+    pub(crate) fn matches(&self, payload: &[u8]) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let header = match mac::Header::parse(payload) {
+            Some((header, _)) => header,
+            None => return false,
+        };
+        let frame_type =
+            self.frame_types.is_empty() || self.frame_types.contains(&header.frame_type);
+        let pan_id = self.pan_ids.is_empty()
+            || match (header.destination_pan, header.source_pan) {
+                (None, None) => true,
+                (destination, source) => [destination, source]
+                    .iter()
+                    .flatten()
+                    .any(|pan_id| self.pan_ids.contains(pan_id)),
+            };
+        frame_type && pan_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACK: [u8; 3] = [0x02, 0x00, 0x07];
+    const DATA: [u8; 11] = [
+        0x61, 0x88, 0x07, 0x62, 0x1a, 0x00, 0x00, 0x34, 0x12, 0xde, 0xad,
+    ];
+    const BEACON: [u8; 7] = [0x00, 0x80, 0x01, 0xcd, 0xab, 0x00, 0x00];
+
+    #[test]
+    fn frame_types() {
+        let filter = Filter {
+            frame_types: vec![mac::FrameType::Data, mac::FrameType::Acknowledgement],
+            ..Filter::default()
+        };
+        assert!(filter.matches(&DATA));
+        assert!(filter.matches(&ACK));
+        assert!(!filter.matches(&BEACON));
+        assert!(!filter.matches(&[0x41]));
+        assert!(Filter::default().matches(&[0x41]));
+    }
+
+    #[test]
+    fn pan_ids() {
+        let filter = Filter {
+            pan_ids: vec![0x1a62],
+            ..Filter::default()
+        };
+        assert!(filter.matches(&DATA));
+        assert!(filter.matches(&ACK));
+        assert!(!filter.matches(&BEACON));
+    }
+}
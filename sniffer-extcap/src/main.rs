@@ -1,45 +1,104 @@
+mod capture;
+mod config;
 mod error;
-mod serial;
+mod filter;
+mod toolbar;
+use config::Config;
 use error::Error;
+use ieee802154_sniffer_host::{
+    discovery, link_type::LinkType, pcapng, rotation, session, transport, wire_format, zep,
+};
 use std::{
+    path::PathBuf,
+    process::ExitCode,
     str::FromStr,
-    sync::{Arc, atomic::{AtomicBool, Ordering}},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
 };
-use byteorder::{ByteOrder, LittleEndian};
 use clap::Parser;
-use ieee802154_sniffer_wire_format as wire_format;
 use lazy_static::lazy_static;
-use pcap_file::{
-    pcap::{PcapHeader, PcapPacket, PcapWriter},
-    DataLink,
-};
 use r_extcap::{
-    config::{ConfigOptionValue, SelectorConfig},
+    config::{BooleanConfig, ConfigOptionValue, SelectorConfig, StringConfig, UnsignedConfig},
     controls::ControlCommand,
     interface::{Dlt, Interface, Metadata},
-    ExtcapStep,
+    CaptureStep, ExtcapStep, PrintSentence,
 };
-use signal_hook;
 
-const NXP_VID: u16 = 0x0d28;
-const NXP_CMSIS_DAP_PID: u16 = 0x0204;
+/// Comma separated list of remote sniffer servers, as `host:port`
+const REMOTES_VARIABLE: &str = "IEEE802154_SNIFFER_REMOTES";
+/// Comma separated list of extra USB identifiers to probe, as hexadecimal `vid:pid`
+const USB_IDS_VARIABLE: &str = "IEEE802154_SNIFFER_USB_IDS";
+/// Comma separated list of serial ports or port patterns to probe, such as `/dev/ttyUSB*`
+const PORTS_VARIABLE: &str = "IEEE802154_SNIFFER_PORTS";
+/// Probe every serial port when set to `1`
+const PROBE_ALL_VARIABLE: &str = "IEEE802154_SNIFFER_PROBE_ALL";
+/// Time a sniffer is given to reply to a probe, all candidates are probed in parallel
+const PROBE_TIMEOUT: Duration = Duration::from_millis(250);
 
-const SILICON_LABS_VID: u16 = 0x10c4;
-const SILICON_LABS_UART_PID: u16 = 0xea60;
+/// Channel used when neither the arguments nor the configuration file select one
+const DEFAULT_CHANNEL: u8 = 11;
+/// ZEP device identifier used when neither the arguments nor the configuration file set one
+const DEFAULT_ZEP_DEVICE_ID: u16 = 1;
 
+/// Arguments, those not given are taken from the configuration file
 #[derive(Debug, Parser)]
 pub struct AppArgs {
     #[command(flatten)]
     extcap: r_extcap::ExtcapArgs,
-    #[arg(long, default_value = "11")]
-    channel: String,
+    /// Configuration file, instead of the per-user configuration file
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// IEEE 802.15.4 channel, 11 to 26
+    #[arg(long)]
+    channel: Option<String>,
+    /// Serial port baud rate
+    #[arg(long)]
+    baud_rate: Option<u32>,
+    /// Link type of the capture, tap, fcs or nofcs
+    #[arg(long)]
+    link_type: Option<String>,
+    /// Send captured frames as ZEP to this UDP destination, `host` or `host:port`
+    #[arg(long)]
+    zep: Option<String>,
+    /// ZEP device identifier
+    #[arg(long)]
+    zep_device_id: Option<u16>,
+    /// Only send captured frames as ZEP, not to the FIFO
+    #[arg(long)]
+    zep_only: bool,
+    /// Also write captured frames to rotating files in this directory
+    #[arg(long)]
+    output_directory: Option<PathBuf>,
+    /// Start a new output file at this size in bytes
+    #[arg(long)]
+    file_size: Option<u64>,
+    /// Start a new output file after this many seconds
+    #[arg(long)]
+    file_duration: Option<u64>,
+    /// Keep this many output files, removing the oldest
+    #[arg(long)]
+    max_files: Option<usize>,
+    /// Stop the capture after this many frames
+    #[arg(long)]
+    max_frames: Option<u64>,
+    /// Stop the capture after this many seconds
+    #[arg(long)]
+    max_duration: Option<u64>,
+}
+
+/// Data link type written for a link type
+fn dlt(link_type: LinkType) -> Dlt {
+    let (name, display) = match link_type {
+        LinkType::Tap => ("IEEE802_15_4_TAP", "IEEE 802.15.4 TAP"),
+        LinkType::Fcs => ("IEEE802_15_4_WITHFCS", "IEEE 802.15.4 with FCS"),
+        LinkType::NoFcs => ("IEEE802_15_4_NOFCS", "IEEE 802.15.4 without FCS"),
+    };
+    Dlt {
+        data_link_type: link_type.datalink(),
+        name: name.into(),
+        display: display.into(),
+    }
 }
-static DLT: Dlt = Dlt {
-    data_link_type: DataLink::IEEE802_15_4_TAP,
-    name: std::borrow::Cow::Borrowed("IEEE802154_TAP"),
-    display: std::borrow::Cow::Borrowed("IEEE 802.15.4 TAP"),
-};
 
 lazy_static! {
     static ref METADATA: Metadata = Metadata {
@@ -47,292 +106,319 @@ lazy_static! {
         display_description: "IEEE 802.15.4 Sniffer".into(),
         ..r_extcap::cargo_metadata!()
     };
-    static ref CAPTURE_INTERFACE_802154_TAP: Interface = Interface {
-        value: "802.15.4".into(),
-        display: "IEEE 802.15.4 Sniffer".into(),
-        dlt: DLT.clone(),
-    };
-    static ref CONFIG_CHANNEL: SelectorConfig = SelectorConfig::builder()
+    static ref CONFIG_ZEP: StringConfig = StringConfig::builder()
+        .config_number(1)
+        .call("zep")
+        .display("ZEP destination")
+        .tooltip("Also send captured frames as ZEP over UDP, host or host:port")
+        .placeholder("127.0.0.1:17754")
+        .group("ZEP")
+        .build();
+    static ref CONFIG_ZEP_ONLY: BooleanConfig = BooleanConfig::builder()
+        .config_number(3)
+        .call("zep-only")
+        .display("ZEP only")
+        .tooltip("Only send captured frames as ZEP, Wireshark receives no frames")
+        .group("ZEP")
+        .build();
+}
+
+/// Channel selector, `default` is selected unless the user chose another channel
+fn config_channel(default: u8) -> SelectorConfig {
+    SelectorConfig::builder()
         .config_number(0)
         .call("channel")
         .display("Channel")
         .tooltip("IEEE 802.15.4 channel")
-        .default_options([
-            ConfigOptionValue::builder()
-                .value("11")
-                .display("11")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("12")
-                .display("12")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("13")
-                .display("13")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("14")
-                .display("14")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("15")
-                .display("15")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("16")
-                .display("16")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("17")
-                .display("17")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("18")
-                .display("18")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("19")
-                .display("19")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("20")
-                .display("20")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("21")
-                .display("21")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("22")
-                .display("22")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("23")
-                .display("23")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("24")
-                .display("24")
-                .build(),
-            ConfigOptionValue::builder()
-                .value("25")
-                .display("25")
-                .default(true)
-                .build(),
-            ConfigOptionValue::builder()
-                .value("26")
-                .display("26")
-                .build(),
-        ])
-        .build();
+        .default_options(
+            toolbar::CHANNELS
+                .map(|channel| {
+                    ConfigOptionValue::builder()
+                        .value(channel.to_string())
+                        .display(channel.to_string())
+                        .default(channel == default)
+                        .build()
+                })
+                .collect::<Vec<_>>(),
+        )
+        .build()
 }
 
-fn main() -> Result<(), Error> {
-    let args = AppArgs::parse();
+/// Link type selector, `default` is selected unless the user chose another link type
+fn config_link_type(default: LinkType) -> SelectorConfig {
+    SelectorConfig::builder()
+        .config_number(4)
+        .call("link-type")
+        .display("Link type")
+        .tooltip("Link-layer header of the captured frames")
+        .default_options(
+            LinkType::ALL
+                .into_iter()
+                .map(|link_type| {
+                    ConfigOptionValue::builder()
+                        .value(link_type.name())
+                        .display(dlt(link_type).display)
+                        .default(link_type == default)
+                        .build()
+                })
+                .collect::<Vec<_>>(),
+        )
+        .build()
+}
 
-    if !args.extcap.capture {
-        if let Some(_filter) = args.extcap.extcap_capture_filter {
-            std::process::exit(0);
+fn config_zep_device_id(default: u16) -> UnsignedConfig {
+    UnsignedConfig::builder()
+        .config_number(2)
+        .call("zep-device-id")
+        .display("ZEP device ID")
+        .tooltip("Device identifier in the ZEP header")
+        .range(0..=u16::MAX as u32)
+        .default_value(u32::from(default))
+        .group("ZEP")
+        .build()
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            // Wireshark shows the error output of a failing extcap to the user
+            eprintln!("{}", error);
+            ExitCode::FAILURE
         }
     }
+}
 
-    let extcap_args = match args.extcap.run() {
-        Ok(args) => args,
-        Err(_) => {
-            eprintln!("Failed to parse arguments");
-            std::process::exit(1);
-        }
+/// Discovery settings from the configuration file and the environment, invalid USB
+/// identifiers in the environment are reported and skipped
+fn discovery_config(config: &Config) -> discovery::Config {
+    let list = |variable| {
+        std::env::var(variable)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>()
     };
+    // The configuration was checked when loaded
+    let mut discovery = config.discovery().unwrap_or_default();
+    discovery
+        .usb_ids
+        .extend(list(USB_IDS_VARIABLE).iter().filter_map(|usb_id| {
+            usb_id
+                .parse::<discovery::UsbId>()
+                .map_err(|e| eprintln!("{}", e))
+                .ok()
+        }));
+    discovery.ports.extend(list(PORTS_VARIABLE));
+    discovery.probe_all |= std::env::var(PROBE_ALL_VARIABLE).is_ok_and(|value| value == "1");
+    discovery
+}
+
+/// Remote sniffer servers from the configuration file and the environment
+fn remotes(config: &Config) -> Vec<String> {
+    let variable = std::env::var(REMOTES_VARIABLE).unwrap_or_default();
+    let mut remotes = config.discovery.remotes.clone();
+    remotes.extend(
+        variable
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(String::from),
+    );
+    remotes
+}
+
+fn run() -> Result<(), Error> {
+    let args = AppArgs::parse();
 
-    match extcap_args {
+    if !args.extcap.capture && args.extcap.extcap_capture_filter.is_some() {
+        // Any capture filter is accepted
+        return Ok(());
+    }
+
+    let config = Config::load(args.config.as_deref());
+    // Listing interfaces and configurations works with a broken configuration file, the error
+    // is shown when capturing
+    let config_or_default = || {
+        config.as_ref().cloned().unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            Config::default()
+        })
+    };
+
+    match args.extcap.run()? {
         ExtcapStep::Interfaces(interfaces_step) => {
+            let config = config_or_default();
+            // Listing interfaces works without any serial ports
+            let mut devices = discovery_config(&config).candidates().unwrap_or_default();
+            for remote in remotes(&config) {
+                devices.push(discovery::DeviceInfo::remote(&remote));
+            }
+            let probe = |device: &discovery::DeviceInfo| {
+                let settings = config.settings(&device.uri, device.serial_number.as_deref());
+                let baud_rate = settings.baud_rate.unwrap_or(wire_format::BAUD_RATE);
+                device.probe_with_baud_rate(baud_rate, PROBE_TIMEOUT)
+            };
             let mut interfaces = vec![];
-            let mut channels = vec![];
-            if let Ok(ports) = serialport::available_ports() {
-                for port in ports {
-                    match port.port_type {
-                        serialport::SerialPortType::UsbPort(ref usb_port) => {
-                            let probe = match (usb_port.vid, usb_port.pid) {
-                                (NXP_VID, NXP_CMSIS_DAP_PID) => true,
-                                (SILICON_LABS_VID, SILICON_LABS_UART_PID) => true,
-                                (_vid, _pid) => false,
-                            };
-                            if probe {
-                                if let Ok(mut channel) = serial::DeviceSerial::open(
-                                    &port.port_name,
-                                    std::time::Duration::from_millis(500),
-                                ) {
-                                    match channel.probe() {
-                                        Ok(()) => {
-                                            channels.push(port.port_name);
-                                        }
-                                        Err(e) => {
-                                            eprintln!("Probe failed, {:?}", e);
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        _ => (),
-                    }
+            for (device, result) in discovery::probe_all_with(devices, probe) {
+                if let Err(e) = result {
+                    eprintln!("Probe of {} failed, {}", device.uri, e);
+                    continue;
                 }
-            }
-            for channel in channels {
+                let display = match device.uri.strip_prefix(transport::TCP_PREFIX) {
+                    Some(remote) => format!("IEEE 802.15.4 Sniffer (remote {})", remote).into(),
+                    None => "IEEE 802.15.4 Sniffer".into(),
+                };
+                // The configuration was checked when loaded
+                let link_type = config
+                    .settings(&device.uri, device.serial_number.as_deref())
+                    .link_type()
+                    .unwrap_or(LinkType::Tap);
                 interfaces.push(Interface {
-                    value: std::borrow::Cow::Owned(channel),
-                    display: "IEEE 802.15.4 Sniffer".into(),
-                    dlt: DLT.clone(),
-                })
+                    value: std::borrow::Cow::Owned(device.uri),
+                    display,
+                    dlt: dlt(link_type),
+                });
             }
-            interfaces_step.list_interfaces(&METADATA, &interfaces.iter().collect::<Vec<_>>(), &[]);
+            interfaces_step.list_interfaces(
+                &METADATA,
+                &interfaces.iter().collect::<Vec<_>>(),
+                &toolbar::controls(),
+            );
         }
         ExtcapStep::Dlts(dlts_step) => {
-            dlts_step
-                .print_from_interfaces(&[&*CAPTURE_INTERFACE_802154_TAP])
-                .unwrap();
+            // The configured link type first, the one Wireshark selects
+            let settings = config_or_default().interface_settings(dlts_step.interface);
+            let configured = settings.link_type().unwrap_or(LinkType::Tap);
+            dlt(configured).print_sentence();
+            for link_type in LinkType::ALL
+                .into_iter()
+                .filter(|&link_type| link_type != configured)
+            {
+                dlt(link_type).print_sentence();
+            }
+        }
+        ExtcapStep::Config(config_step) => {
+            let settings = config_or_default().interface_settings(config_step.interface);
+            config_step.list_configs(&[
+                &config_channel(settings.channel.unwrap_or(DEFAULT_CHANNEL)),
+                &config_link_type(settings.link_type().unwrap_or(LinkType::Tap)),
+                &*CONFIG_ZEP,
+                &config_zep_device_id(settings.zep.device_id.unwrap_or(DEFAULT_ZEP_DEVICE_ID)),
+                &*CONFIG_ZEP_ONLY,
+            ])
         }
-        ExtcapStep::Config(config_step) => config_step.list_configs(&[&*CONFIG_CHANNEL]),
         ExtcapStep::ReloadConfig(_reload_config_step) => {}
-        ExtcapStep::Capture(capture_step) => {
-            let mut controls = (
-                capture_step.spawn_channel_control_reader(),
-                capture_step.new_control_sender(),
-            );
-
-            if let (Some(control_reader), Some(_control_sender)) = &mut controls {
-                let packet = control_reader.read_packet().unwrap();
-                assert_eq!(packet.command, ControlCommand::Initialized);
-            }
-
-            let pcap_header = PcapHeader {
-                datalink: DataLink::IEEE802_15_4_TAP,
-                endianness: pcap_file::Endianness::Big,
-                ..Default::default()
-            };
-            let mut pcap_writer = PcapWriter::with_header(capture_step.fifo, pcap_header).unwrap();
-
-            let channel = match u8::from_str(&args.channel) {
-                Ok(channel) => channel,
-                Err(_) => {
-                    eprintln!("Failed to parse channel");
-                    std::process::exit(1);
-                }
-            };
-
-            if capture_step.interface.is_empty() {
-                eprintln!("No interface specified");
-                std::process::exit(1);
-            }
+        ExtcapStep::Capture(capture_step) => capture(&args, config?, capture_step)?,
+    };
+    Ok(())
+}
 
-            let mut device = serial::DeviceSerial::open(
-                &capture_step.interface,
-                std::time::Duration::from_millis(1),
-            )
-            .expect("Failed to open serial port");
+fn capture(args: &AppArgs, config: Config, capture_step: CaptureStep) -> Result<(), Error> {
+    let settings = config.interface_settings(capture_step.interface);
+    let channel = match &args.channel {
+        Some(channel) => u8::from_str(channel)
+            .ok()
+            .filter(|channel| toolbar::CHANNELS.contains(channel))
+            .ok_or_else(|| Error::InvalidChannel(channel.clone()))?,
+        None => settings.channel.unwrap_or(DEFAULT_CHANNEL),
+    };
+    let link_type = match &args.link_type {
+        Some(name) => {
+            LinkType::from_name(name).ok_or_else(|| Error::InvalidLinkType(name.clone()))?
+        }
+        None => settings.link_type().unwrap_or(LinkType::Tap),
+    };
+    let baud_rate = args
+        .baud_rate
+        .or(settings.baud_rate)
+        .unwrap_or(wire_format::BAUD_RATE);
+    let zep_destination = args.zep.clone().or(settings.zep.destination.clone());
+    let zep_device_id = args
+        .zep_device_id
+        .or(settings.zep.device_id)
+        .unwrap_or(DEFAULT_ZEP_DEVICE_ID);
+    let zep_only = args.zep_only || settings.zep.only.unwrap_or(false);
+    if zep_only && zep_destination.is_none() {
+        return Err(Error::MissingZepDestination);
+    }
+    // The settings were checked when the configuration was loaded
+    let filter = settings.filter().unwrap_or_default();
+    let keys = config.key_table().unwrap_or_default();
+    let seconds = |seconds: Option<u64>| seconds.map(Duration::from_secs);
+    let output_directory = args.output_directory.clone().or(settings.files.directory);
+    let rotation = rotation::Rotation {
+        file_size: args.file_size.or(settings.files.file_size),
+        file_duration: seconds(args.file_duration.or(settings.files.file_duration)),
+        max_files: args.max_files.or(settings.files.max_files),
+    };
+    let limits = rotation::Limits {
+        frames: args.max_frames.or(settings.limits.frames),
+        duration: seconds(args.max_duration.or(settings.limits.duration)),
+    };
 
-            device.set_channel(channel).expect("Failed to set channel");
+    if capture_step.interface.is_empty() {
+        return Err(Error::MissingInterface);
+    }
 
-            device.start_capture().expect("Failed to start capture");
+    let control_reader = capture_step.spawn_channel_control_reader();
+    let mut toolbar = toolbar::Toolbar::new(capture_step.new_control_sender(), channel);
 
-            let term = Arc::new(AtomicBool::new(false));
-            signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&term))?;
+    if let Some(control_reader) = &control_reader {
+        let packet = control_reader.read_packet().map_err(|_| Error::Control)?;
+        if packet.command != ControlCommand::Initialized {
+            eprintln!("Unexpected control command {:?}", packet.command);
+        }
+        toolbar.initialized()?;
+    }
 
-            while !term.load(Ordering::Relaxed) {
-                if let (Some(control_reader), Some(_control_sender)) = &mut controls {
-                    if let Some(_control_packet) = control_reader.try_read_packet() {}
-                }
+    let zep_sender = match &zep_destination {
+        Some(address) => Some(
+            zep::ZepSender::new(address, zep_device_id)
+                .map_err(|error| Error::Zep(address.clone(), error))?,
+        ),
+        None => None,
+    };
 
-                if let Ok(packet) = device.receive() {
-                    if let Some(packet) = packet {
-                        match packet {
-                            wire_format::Packet::CaptureFrame(ref frame) => {
-                                let mut tap_data_offset = 0;
-                                let mut tap_data = [0u8; 512];
-                                tap_data[0] = 0; // version
-                                tap_data[1] = 0; // reserved
-                                                 // header length 16-bit
-                                tap_data_offset += 4;
-                                LittleEndian::write_u16(
-                                    &mut tap_data[tap_data_offset..tap_data_offset + 2],
-                                    0,
-                                ); // FCS type
-                                LittleEndian::write_u16(
-                                    &mut tap_data[tap_data_offset + 2..tap_data_offset + 4],
-                                    1,
-                                ); // length
-                                LittleEndian::write_u32(
-                                    &mut tap_data[tap_data_offset + 4..tap_data_offset + 8],
-                                    0,
-                                ); // None
-                                tap_data_offset += 8;
-                                LittleEndian::write_u16(
-                                    &mut tap_data[tap_data_offset..tap_data_offset + 2],
-                                    3,
-                                ); // Channel plan
-                                LittleEndian::write_u16(
-                                    &mut tap_data[tap_data_offset + 2..tap_data_offset + 4],
-                                    3,
-                                ); // length
-                                LittleEndian::write_u32(
-                                    &mut tap_data[tap_data_offset + 4..tap_data_offset + 8],
-                                    u32::from(channel),
-                                ); // channel
-                                tap_data_offset += 8;
-                                if let Some(rssi) = frame.received_signal_strength_indicator {
-                                    let rssi = (rssi as f32) / 1000.0f32;
-                                    LittleEndian::write_u16(
-                                        &mut tap_data[tap_data_offset..tap_data_offset + 2],
-                                        1,
-                                    ); // RSSI
-                                    LittleEndian::write_u16(
-                                        &mut tap_data[tap_data_offset + 2..tap_data_offset + 4],
-                                        4,
-                                    ); // length
-                                    LittleEndian::write_f32(
-                                        &mut tap_data[tap_data_offset + 4..tap_data_offset + 8],
-                                        rssi,
-                                    ); // RSSI
-                                    tap_data_offset += 8;
-                                }
-                                if let Some(lqi) = frame.link_quality_index {
-                                    LittleEndian::write_u16(
-                                        &mut tap_data[tap_data_offset..tap_data_offset + 2],
-                                        10,
-                                    ); // LQI
-                                    LittleEndian::write_u16(
-                                        &mut tap_data[tap_data_offset + 2..tap_data_offset + 4],
-                                        1,
-                                    ); // length
-                                    LittleEndian::write_u32(
-                                        &mut tap_data[tap_data_offset + 4..tap_data_offset + 8],
-                                        u32::from(lqi),
-                                    ); // LQI
-                                    tap_data_offset += 8;
-                                }
-                                LittleEndian::write_u16(
-                                    &mut tap_data[2..4],
-                                    tap_data_offset as u16,
-                                ); // header length
-                                let data = &frame.payload;
-                                let length = data.len();
-                                tap_data[tap_data_offset..tap_data_offset + length]
-                                    .copy_from_slice(&data);
+    let interface = pcapng::InterfaceDescription {
+        name: capture_step.interface.to_string(),
+        channel,
+        serial_number: transport::usb_serial_number(capture_step.interface),
+    };
+    let mut writer = pcapng::CaptureWriter::new(capture_step.fifo)?;
+    let interface_id = writer.add_interface(link_type.datalink(), &interface)?;
+    let files = match &output_directory {
+        Some(directory) => Some(
+            rotation::RotatingWriter::new(directory, link_type.datalink(), interface, rotation)
+                .map_err(|error| Error::Output(directory.clone(), error))?,
+        ),
+        None => None,
+    };
 
-                                let pcap_packet = PcapPacket::new(
-                                    SystemTime::now().duration_since(UNIX_EPOCH).unwrap(),
-                                    (tap_data_offset + length) as u32,
-                                    &tap_data[..tap_data_offset + length],
-                                );
-                                pcap_writer.write_packet(&pcap_packet).unwrap();
-                            }
-                            _ => (),
-                        }
-                    }
-                }
-            }
+    let mut session =
+        session::Session::open_with_baud_rate(capture_step.interface, baud_rate, channel)
+            .map_err(|error| Error::Open(capture_step.interface.to_string(), error))?;
+    session.start_capture()?;
 
-            device.stop_capture().expect("Failed to stop capture");
-        }
+    let capture = capture::Capture {
+        session,
+        writer,
+        interface_id,
+        link_type,
+        toolbar,
+        control_reader,
+        zep_sender,
+        zep_only,
+        filter,
+        files,
+        limits,
+        keys: (!keys.is_empty()).then_some(keys),
     };
-    Ok(())
+
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&stop))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&stop))?;
+    capture.run(&stop)
 }
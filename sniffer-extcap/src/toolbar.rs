@@ -0,0 +1,176 @@
+//! Extcap interface toolbar controls
+//!
+//! The toolbar is shown in Wireshark using `View > Interface Toolbars` and allows the
+//! sniffer to be reconfigured while capturing.
+use lazy_static::lazy_static;
+use r_extcap::controls::{
+    synchronous::{ExtcapControlSender, ExtcapControlSenderTrait},
+    ButtonControl, ControlCommand, ControlPacket, ControlWithLabel, SelectorControl,
+    SelectorControlOption, StringControl, ToolbarControl,
+};
+use std::str::FromStr;
+
+const CONTROL_CHANNEL: u8 = 0;
+const CONTROL_TX_POWER: u8 = 1;
+const CONTROL_PAUSE: u8 = 2;
+const CONTROL_STATUS: u8 = 3;
+
+/// Valid IEEE 802.15.4 channels in the 2.4 GHz band
+pub(crate) const CHANNELS: std::ops::RangeInclusive<u8> = 11..=26;
+
+lazy_static! {
+    static ref CHANNEL: SelectorControl = SelectorControl::builder()
+        .control_number(CONTROL_CHANNEL)
+        .display("Channel")
+        .tooltip("IEEE 802.15.4 channel")
+        .options(
+            CHANNELS
+                .map(|channel| {
+                    SelectorControlOption::builder()
+                        .value(channel.to_string())
+                        .display(channel.to_string())
+                        .build()
+                })
+                .collect::<Vec<_>>()
+        )
+        .build();
+    static ref TX_POWER: StringControl = StringControl::builder()
+        .control_number(CONTROL_TX_POWER)
+        .display("TX power")
+        .tooltip("Transmit power in dBm")
+        .placeholder("dBm")
+        .validation(r"^-?\d+$")
+        .build();
+    static ref PAUSE: ButtonControl = ButtonControl::builder()
+        .control_number(CONTROL_PAUSE)
+        .display("Pause")
+        .tooltip("Pause or resume the capture")
+        .build();
+    static ref STATUS: StringControl = StringControl::builder()
+        .control_number(CONTROL_STATUS)
+        .display("Status")
+        .tooltip("Sniffer status")
+        .placeholder("Not capturing")
+        .validation(".*")
+        .build();
+}
+
+/// Toolbar controls to list in the interfaces step
+pub(crate) fn controls() -> [&'static dyn ToolbarControl; 4] {
+    [&*CHANNEL, &*TX_POWER, &*PAUSE, &*STATUS]
+}
+
+/// Action requested by the user through the toolbar
+#[derive(Debug, PartialEq)]
+pub(crate) enum Action {
+    /// Change channel
+    Channel(u8),
+    /// Change transmit power, in dBm
+    Power(i32),
+    /// Toggle between paused and capturing
+    TogglePause,
+}
+
+/// Parse a control packet received from Wireshark
+pub(crate) fn parse(packet: &ControlPacket) -> Option<Action> {
+    if packet.command != ControlCommand::Set {
+        return None;
+    }
+    let value = std::str::from_utf8(&packet.payload).ok()?;
+    match packet.control_number {
+        CONTROL_CHANNEL => u8::from_str(value)
+            .ok()
+            .filter(|channel| CHANNELS.contains(channel))
+            .map(Action::Channel),
+        CONTROL_TX_POWER => i32::from_str(value).ok().map(Action::Power),
+        CONTROL_PAUSE => Some(Action::TogglePause),
+        _ => None,
+    }
+}
+
+/// Toolbar state for an ongoing capture
+pub(crate) struct Toolbar {
+    sender: Option<ExtcapControlSender>,
+    channel: u8,
+    paused: bool,
+}
+
+impl Toolbar {
+    pub(crate) fn new(sender: Option<ExtcapControlSender>, channel: u8) -> Self {
+        Self {
+            sender,
+            channel,
+            paused: false,
+        }
+    }
+
+    pub(crate) fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Show the current configuration in the toolbar
+    pub(crate) fn initialized(&mut self) -> std::io::Result<()> {
+        let channel = self.channel.to_string();
+        self.sender.send(CHANNEL.set_value(&channel))?;
+        self.update_status()
+    }
+
+    /// Record that the channel was changed
+    pub(crate) fn set_channel(&mut self, channel: u8) -> std::io::Result<()> {
+        self.channel = channel;
+        self.sender
+            .status_message(&format!("Sniffer changed to channel {}", channel))?;
+        self.update_status()
+    }
+
+    /// Record that the transmit power was changed
+    pub(crate) fn set_power(&mut self, power: i32) -> std::io::Result<()> {
+        self.sender
+            .status_message(&format!("Sniffer TX power set to {} dBm", power))
+    }
+
+    /// Record that the capture was paused or resumed
+    pub(crate) fn set_paused(&mut self, paused: bool) -> std::io::Result<()> {
+        self.paused = paused;
+        let label = if paused { "Resume" } else { "Pause" };
+        self.sender.send(PAUSE.set_label(label))?;
+        self.update_status()
+    }
+
+    fn update_status(&mut self) -> std::io::Result<()> {
+        let status = if self.paused {
+            format!("Paused on channel {}", self.channel)
+        } else {
+            format!("Capturing on channel {}", self.channel)
+        };
+        self.sender.send(STATUS.set_value(&status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_packets() {
+        let packet =
+            ControlPacket::new_with_payload(CONTROL_CHANNEL, ControlCommand::Set, &b"15"[..]);
+        assert_eq!(parse(&packet), Some(Action::Channel(15)));
+        let packet =
+            ControlPacket::new_with_payload(CONTROL_CHANNEL, ControlCommand::Set, &b"27"[..]);
+        assert_eq!(parse(&packet), None);
+        let packet =
+            ControlPacket::new_with_payload(CONTROL_TX_POWER, ControlCommand::Set, &b"-4"[..]);
+        assert_eq!(parse(&packet), Some(Action::Power(-4)));
+        let packet =
+            ControlPacket::new_with_payload(CONTROL_TX_POWER, ControlCommand::Set, &b"high"[..]);
+        assert_eq!(parse(&packet), None);
+        let packet = ControlPacket::new_with_payload(CONTROL_PAUSE, ControlCommand::Set, &[][..]);
+        assert_eq!(parse(&packet), Some(Action::TogglePause));
+        let packet =
+            ControlPacket::new_with_payload(CONTROL_STATUS, ControlCommand::Set, &b"text"[..]);
+        assert_eq!(parse(&packet), None);
+        let packet = ControlPacket::new(CONTROL_CHANNEL, ControlCommand::Initialized);
+        assert_eq!(parse(&packet), None);
+    }
+}
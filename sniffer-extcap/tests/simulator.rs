@@ -0,0 +1,528 @@
+//! Run the extcap capture against the virtual sniffer
+#![cfg(unix)]
+
+use ieee802154_sniffer_host::fcs;
+use ieee802154_sniffer_simulator::{Config, Pty, Simulator, Source};
+use nix::{sys::signal, unistd::Pid};
+use pcap_file::{
+    pcapng::{
+        blocks::{
+            enhanced_packet::EnhancedPacketOption, interface_statistics::InterfaceStatisticsOption,
+        },
+        Block, PcapNgReader,
+    },
+    DataLink,
+};
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+struct Capture {
+    interfaces: Vec<DataLink>,
+    frames: Vec<Vec<u8>>,
+    dropped: u64,
+    received: Option<u64>,
+    interface_dropped: Option<u64>,
+}
+
+fn output_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-{}-{}.pcapng",
+        name,
+        std::process::id()
+    ))
+}
+
+/// Capture from a simulator for a while, returns the decoded capture and the simulator
+fn capture(name: &str, config: Config, channel: u8) -> (Capture, Simulator<Pty>) {
+    capture_with_args(name, config, &["--channel", &channel.to_string()])
+}
+
+/// Capture from a simulator with extra extcap arguments
+fn capture_with_args(name: &str, config: Config, args: &[&str]) -> (Capture, Simulator<Pty>) {
+    let pty = Pty::open().unwrap();
+    let path = pty.path().clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator_stop = Arc::clone(&stop);
+    let simulator = thread::spawn(move || {
+        let mut simulator = Simulator::new(pty, config);
+        simulator.run(&simulator_stop).unwrap();
+        // Handle the commands sent by the extcap before it exited
+        simulator.poll().unwrap();
+        simulator
+    });
+
+    let output = output_path(name);
+    let mut extcap = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-extcap"))
+        .arg("--capture")
+        .arg("--extcap-interface")
+        .arg(&path)
+        .arg("--fifo")
+        .arg(&output)
+        .args(args)
+        .env_remove("IEEE802154_SNIFFER_CONFIG")
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    signal::kill(Pid::from_raw(extcap.id() as i32), signal::Signal::SIGTERM).unwrap();
+    let status = extcap.wait().unwrap();
+    assert!(status.success());
+
+    stop.store(true, Ordering::Relaxed);
+    let simulator = simulator.join().unwrap();
+
+    let data = std::fs::read(&output).unwrap();
+    let _ = std::fs::remove_file(&output);
+    let mut reader = PcapNgReader::new(&data[..]).unwrap();
+    let mut capture = Capture {
+        interfaces: Vec::new(),
+        frames: Vec::new(),
+        dropped: 0,
+        received: None,
+        interface_dropped: None,
+    };
+    while let Some(block) = reader.next_block() {
+        match block.unwrap() {
+            Block::InterfaceDescription(interface) => capture.interfaces.push(interface.linktype),
+            Block::EnhancedPacket(packet) => {
+                for option in packet.options {
+                    if let EnhancedPacketOption::DropCount(count) = option {
+                        capture.dropped += count;
+                    }
+                }
+                capture.frames.push(packet.data.to_vec());
+            }
+            Block::InterfaceStatistics(statistics) => {
+                for option in statistics.options {
+                    match option {
+                        InterfaceStatisticsOption::IsbIfRecv(count) => {
+                            capture.received = Some(count)
+                        }
+                        InterfaceStatisticsOption::IsbIfDrop(count) => {
+                            capture.interface_dropped = Some(count)
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    (capture, simulator)
+}
+
+/// Channel from the TAP channel TLV, the second TLV written by the extcap
+fn tap_channel(data: &[u8]) -> u8 {
+    assert_eq!(&data[12..14], &[0x03, 0x00]);
+    data[16]
+}
+
+#[test]
+fn capture_synthetic_traffic() {
+    let config = Config {
+        rate: 100.0,
+        ..Config::default()
+    };
+    let (capture, simulator) = capture("synthetic", config, 15);
+    assert_eq!(simulator.channel(), 15);
+    assert!(!simulator.capturing());
+    assert_eq!(capture.interfaces, vec![DataLink::IEEE802_15_4_TAP]);
+    assert!(capture.frames.len() > 50);
+    // Frames sent while the capture is being stopped are never read
+    let unread = simulator.statistics().sent - capture.frames.len() as u64;
+    assert!(unread <= 5);
+    assert_eq!(capture.received, Some(capture.frames.len() as u64));
+    assert_eq!(capture.interface_dropped, Some(0));
+    for frame in &capture.frames {
+        assert_eq!(tap_channel(frame), 15);
+    }
+}
+
+#[test]
+fn capture_with_config_file() {
+    let frames = vec![
+        // Data frame in PAN 0x1a62
+        ieee802154_sniffer_wire_format::Payload::from_slice(&[
+            0x41, 0x88, 0x01, 0x62, 0x1a, 0xff, 0xff, 0x34, 0x12,
+        ])
+        .unwrap(),
+        // Beacon
+        ieee802154_sniffer_wire_format::Payload::from_slice(&[
+            0x00, 0x80, 0x02, 0xcd, 0xab, 0x00, 0x00,
+        ])
+        .unwrap(),
+    ];
+    let config = Config {
+        source: Source::Replay(frames.clone()),
+        rate: 50.0,
+        ..Config::default()
+    };
+    let path = std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-config-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        "[capture]\nchannel = 13\n\n[capture.filter]\nframe_types = [\"data\"]\n",
+    )
+    .unwrap();
+    let (capture, simulator) =
+        capture_with_args("config", config, &["--config", path.to_str().unwrap()]);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(simulator.channel(), 13);
+    assert!(capture.frames.len() > 5);
+    for frame in &capture.frames {
+        assert_eq!(tap_channel(frame), 13);
+        let header_length = usize::from(frame[2]);
+        assert_eq!(&frame[header_length..], &frames[0][..]);
+    }
+}
+
+#[test]
+fn capture_decrypted_frames() {
+    // IEEE 802.15.4-2006 C.2.3, association request with security level 6, ENC-MIC-64
+    let secured = [
+        0x2b, 0xdc, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0xff, 0xff,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x06, 0x05, 0x00, 0x00, 0x00, 0x01, 0xd8,
+        0x4f, 0xde, 0x52, 0x90, 0x61, 0xf9, 0xc6, 0xf1,
+    ];
+    // IEEE 802.15.4-2006 C.2.2, data frame encrypted with security level 4, without a MIC
+    let unverified = [
+        0x69, 0xdc, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x04, 0x05, 0x00, 0x00, 0x00, 0xd4, 0x3e, 0x02, 0x2b,
+    ];
+    let config = Config {
+        source: Source::Replay(vec![
+            ieee802154_sniffer_wire_format::Payload::from_slice(&secured).unwrap(),
+            ieee802154_sniffer_wire_format::Payload::from_slice(&unverified).unwrap(),
+        ]),
+        rate: 50.0,
+        ..Config::default()
+    };
+    let path = std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-security-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        "[[security.keys]]\nkey = \"c0c1c2c3c4c5c6c7c8c9cacbcccdcecf\"\n",
+    )
+    .unwrap();
+    let (capture, _simulator) =
+        capture_with_args("security", config, &["--config", path.to_str().unwrap()]);
+    let _ = std::fs::remove_file(&path);
+    let mut expected = secured[..23].to_vec();
+    expected[0] = 0x23;
+    expected.extend_from_slice(&[0x01, 0xce]);
+    assert!(capture.frames.len() > 5);
+    let frames: Vec<&[u8]> = capture
+        .frames
+        .iter()
+        .map(|frame| &frame[usize::from(frame[2])..])
+        .collect();
+    // Frames without a MIC cannot be verified and are passed on as captured
+    assert!(frames.contains(&&expected[..]));
+    assert!(frames.contains(&&unverified[..]));
+    assert!(frames
+        .iter()
+        .all(|frame| *frame == &expected[..] || *frame == &unverified[..]));
+}
+
+#[test]
+fn capture_replayed_traffic() {
+    let frames = vec![
+        ieee802154_sniffer_wire_format::Payload::from_slice(&[0x02, 0x00, 0x01]).unwrap(),
+        ieee802154_sniffer_wire_format::Payload::from_slice(&[0x02, 0x00, 0x02]).unwrap(),
+    ];
+    let config = Config {
+        source: Source::Replay(frames.clone()),
+        rate: 50.0,
+        ..Config::default()
+    };
+    let (capture, _simulator) = capture("replay", config, 26);
+    assert!(capture.frames.len() > 10);
+    for (n, frame) in capture.frames.iter().enumerate() {
+        assert_eq!(tap_channel(frame), 26);
+        let header_length = usize::from(frame[2]);
+        assert_eq!(&frame[header_length..], &frames[n % frames.len()][..]);
+    }
+}
+
+#[test]
+fn capture_with_corruption() {
+    let config = Config {
+        rate: 100.0,
+        corruption: 0.2,
+        seed: 5,
+        ..Config::default()
+    };
+    let (capture, simulator) = capture("corruption", config, 11);
+    let statistics = simulator.statistics();
+    assert!(statistics.corrupted > 0);
+    assert!(capture.frames.len() as u64 <= statistics.sent - statistics.corrupted);
+    assert!(capture.dropped > 0);
+    assert_eq!(capture.interface_dropped, Some(capture.dropped));
+}
+
+#[test]
+fn capture_with_fcs_link_type() {
+    let config = Config {
+        rate: 100.0,
+        ..Config::default()
+    };
+    let (capture, _) = capture_with_args("fcs", config, &["--channel", "20", "--link-type", "fcs"]);
+    assert_eq!(capture.interfaces, vec![DataLink::IEEE802_15_4]);
+    assert!(!capture.frames.is_empty());
+    for frame in &capture.frames {
+        let (payload, fcs) = frame.split_at(frame.len() - 2);
+        assert_eq!(fcs, &fcs::compute(payload).to_le_bytes());
+    }
+}
+
+#[test]
+fn data_link_types_listed() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-extcap"))
+        .arg("--extcap-dlts")
+        .arg("--extcap-interface")
+        .arg("/dev/ttyACM0")
+        .env_remove("IEEE802154_SNIFFER_CONFIG")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let dlts = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = dlts.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("dlt {number=283}"), "{}", dlts);
+    assert!(lines[1].starts_with("dlt {number=195}"), "{}", dlts);
+    assert!(lines[2].starts_with("dlt {number=230}"), "{}", dlts);
+}
+
+/// Number of enhanced packet blocks in a pcapng file
+fn count_frames(path: &std::path::Path) -> usize {
+    let data = std::fs::read(path).unwrap();
+    let mut reader = PcapNgReader::new(&data[..]).unwrap();
+    let mut frames = 0;
+    while let Some(block) = reader.next_block() {
+        if let Block::EnhancedPacket(_) = block.unwrap() {
+            frames += 1;
+        }
+    }
+    frames
+}
+
+#[test]
+fn frame_limit_and_file_rotation() {
+    let pty = Pty::open().unwrap();
+    let path = pty.path().clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator_stop = Arc::clone(&stop);
+    let simulator = thread::spawn(move || {
+        let config = Config {
+            rate: 100.0,
+            ..Config::default()
+        };
+        let mut simulator = Simulator::new(pty, config);
+        simulator.run(&simulator_stop).unwrap();
+        simulator.poll().unwrap();
+        simulator
+    });
+
+    let output = output_path("limit");
+    let directory =
+        std::env::temp_dir().join(format!("ieee802154-sniffer-files-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    let status = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-extcap"))
+        .arg("--capture")
+        .arg("--extcap-interface")
+        .arg(&path)
+        .arg("--fifo")
+        .arg(&output)
+        .arg("--max-frames")
+        .arg("30")
+        .arg("--output-directory")
+        .arg(&directory)
+        .arg("--file-size")
+        .arg("500")
+        .arg("--max-files")
+        .arg("3")
+        .env_remove("IEEE802154_SNIFFER_CONFIG")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    stop.store(true, Ordering::Relaxed);
+    let simulator = simulator.join().unwrap();
+    assert!(!simulator.capturing());
+
+    assert_eq!(count_frames(&output), 30);
+    let _ = std::fs::remove_file(&output);
+    let mut files: Vec<_> = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    assert_eq!(files.len(), 3);
+    let name = files[0].file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.contains("_ch11_"), "{}", name);
+    assert!(name.ends_with("Z.pcapng"), "{}", name);
+    let kept: usize = files.iter().map(|file| count_frames(file)).sum();
+    assert!(kept > 0 && kept < 30, "{} frames kept", kept);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn fifo_closed_by_wireshark() {
+    let pty = Pty::open().unwrap();
+    let path = pty.path().clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator_stop = Arc::clone(&stop);
+    let simulator = thread::spawn(move || {
+        let config = Config {
+            rate: 100.0,
+            ..Config::default()
+        };
+        let mut simulator = Simulator::new(pty, config);
+        simulator.run(&simulator_stop).unwrap();
+        simulator.poll().unwrap();
+        simulator
+    });
+
+    let fifo = output_path("fifo");
+    let _ = std::fs::remove_file(&fifo);
+    nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+    let mut extcap = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-extcap"))
+        .arg("--capture")
+        .arg("--extcap-interface")
+        .arg(&path)
+        .arg("--fifo")
+        .arg(&fifo)
+        .arg("--channel")
+        .arg("20")
+        .spawn()
+        .unwrap();
+    // Read a few frames and close the FIFO like Wireshark does when the capture is stopped
+    let mut reader = std::fs::File::open(&fifo).unwrap();
+    let mut data = [0u8; 1024];
+    let mut length = 0;
+    while length < data.len() {
+        length += reader.read(&mut data[length..]).unwrap();
+    }
+    drop(reader);
+    let status = extcap.wait().unwrap();
+    let _ = std::fs::remove_file(&fifo);
+    assert!(status.success());
+
+    stop.store(true, Ordering::Relaxed);
+    let simulator = simulator.join().unwrap();
+    assert!(!simulator.capturing());
+}
+
+#[test]
+fn error_reported_to_wireshark() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-extcap"))
+        .arg("--capture")
+        .arg("--extcap-interface")
+        .arg("/dev/ieee802154-sniffer-missing")
+        .arg("--fifo")
+        .arg(output_path("missing"))
+        .arg("--channel")
+        .arg("27")
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(output_path("missing"));
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Invalid channel \"27\", expected a channel between 11 and 26\n"
+    );
+}
+
+#[test]
+fn interfaces_from_configured_ports() {
+    let pty = Pty::open().unwrap();
+    let path = pty.path().clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator_stop = Arc::clone(&stop);
+    let simulator = thread::spawn(move || {
+        let mut simulator = Simulator::new(pty, Config::default());
+        simulator.run(&simulator_stop).unwrap();
+    });
+
+    let ports = format!("{},/dev/ieee802154-sniffer-missing", path.display());
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-extcap"))
+        .arg("--extcap-interfaces")
+        .env("IEEE802154_SNIFFER_PORTS", ports)
+        .env_remove("IEEE802154_SNIFFER_REMOTES")
+        .output()
+        .unwrap();
+    stop.store(true, Ordering::Relaxed);
+    simulator.join().unwrap();
+    assert!(output.status.success());
+    let interfaces = String::from_utf8_lossy(&output.stdout);
+    assert!(interfaces.contains(&format!("interface {{value={}}}", path.display())));
+    assert!(!interfaces.contains("ieee802154-sniffer-missing"));
+    let errors = String::from_utf8_lossy(&output.stderr);
+    assert!(errors.contains("Probe of /dev/ieee802154-sniffer-missing failed"));
+}
+
+/// CPU time used by a process in clock ticks, from `/proc/<pid>/stat`
+#[cfg(target_os = "linux")]
+fn cpu_ticks(pid: u32) -> u64 {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap();
+    // The fields following the parenthesised command name, user and system time are the
+    // 12th and 13th of these
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .unwrap()
+        .1
+        .split_whitespace()
+        .collect();
+    fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn idle_cpu_usage() {
+    let pty = Pty::open().unwrap();
+    let path = pty.path().clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator_stop = Arc::clone(&stop);
+    let simulator = thread::spawn(move || {
+        let config = Config {
+            rate: 0.1,
+            ..Config::default()
+        };
+        let mut simulator = Simulator::new(pty, config);
+        simulator.run(&simulator_stop).unwrap();
+    });
+
+    let output = output_path("idle");
+    let mut extcap = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-extcap"))
+        .arg("--capture")
+        .arg("--extcap-interface")
+        .arg(&path)
+        .arg("--fifo")
+        .arg(&output)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(500));
+    let start = cpu_ticks(extcap.id());
+    thread::sleep(Duration::from_secs(3));
+    let used = cpu_ticks(extcap.id()) - start;
+    signal::kill(Pid::from_raw(extcap.id() as i32), signal::Signal::SIGINT).unwrap();
+    assert!(extcap.wait().unwrap().success());
+    let _ = std::fs::remove_file(&output);
+    stop.store(true, Ordering::Relaxed);
+    simulator.join().unwrap();
+    // At 100 ticks per second, about 1 % of one core. Polling the device every millisecond
+    // used about twice this.
+    assert!(used < 4, "{} ticks used in 3 s", used);
+}
@@ -0,0 +1,311 @@
+//! Acknowledgement matching and retransmission analysis
+//!
+//! An [`Analyzer`] follows the frames that request an acknowledgement. An acknowledgement
+//! frame is paired with the frame just before it when both have the same sequence number and
+//! the acknowledgement starts within the wait window after the end of that frame. A frame with
+//! the same sequence number and content as the previous frame on its link is counted as a
+//! retransmission.
+//!
+//! Pairing relies on the time between frames, which is a few hundred microseconds. Use the
+//! start of frame times from the capturing device where available, capture times taken by
+//! the host are only meaningful with a much wider window. The sniffers of this project do not
+//! timestamp frames, device times are only found in captures from other sniffers, see
+//! [`crate::capture_file::Record::device_timestamp`].
+//!
+//! Only frames with both a source and a destination address are followed, acknowledgements
+//! carry no addresses so the link is known from the frame they acknowledge.
+use crate::{
+    airtime::{self, OQPSK_BITRATE},
+    mac::{self, Address, FrameType},
+    summary::Device,
+    wire_format,
+};
+use std::{collections::BTreeMap, time::Duration};
+
+/// Default acknowledgement wait window, macAckWaitDuration of the 2.4 GHz O-QPSK PHY
+pub const ACK_WAIT: Duration = Duration::from_micros(864);
+
+/// Frames sent from one device to another
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LinkStatistics {
+    /// Frames sent, retransmissions included
+    pub transmissions: u64,
+    /// Distinct frames sent
+    pub frames: u64,
+    /// Frames acknowledged at least once
+    pub delivered: u64,
+    pub retransmissions: u64,
+    /// Number of frames by the number of retries they took, complete after
+    /// [`Analyzer::finish`]
+    pub retries: BTreeMap<u32, u64>,
+}
+
+impl LinkStatistics {
+    /// Share of the frames that were acknowledged, `None` before any frame
+    pub fn delivery_ratio(&self) -> Option<f64> {
+        (self.frames > 0).then(|| self.delivered as f64 / self.frames as f64)
+    }
+}
+
+/// Last frame sent on a link
+#[derive(Clone, Debug)]
+struct Outstanding {
+    sequence: u8,
+    payload: wire_format::Payload,
+    acknowledged: bool,
+    attempts: u32,
+}
+
+/// Frame waiting for its acknowledgement
+#[derive(Clone, Copy, Debug)]
+struct Pending {
+    link: (Device, Device),
+    sequence: u8,
+    /// End of the frame
+    end: Duration,
+}
+
+/// Pairs acknowledgements with the frames they acknowledge
+#[derive(Clone, Debug)]
+pub struct Analyzer {
+    window: Duration,
+    pending: Option<Pending>,
+    outstanding: BTreeMap<(Device, Device), Outstanding>,
+    /// Statistics by source and destination
+    pub links: BTreeMap<(Device, Device), LinkStatistics>,
+    /// Acknowledgements paired with a frame
+    pub matched: u64,
+    /// Acknowledgements without a frame to pair with
+    pub unmatched: u64,
+}
+
+impl Analyzer {
+    /// Analyzer pairing acknowledgements that start within `window` after the end of a frame
+    pub fn new(window: Duration) -> Self {
+        Analyzer {
+            window,
+            pending: None,
+            outstanding: BTreeMap::new(),
+            links: BTreeMap::new(),
+            matched: 0,
+            unmatched: 0,
+        }
+    }
+
+    /// Add a frame without FCS that started at `timestamp`
+    ///
+    /// Frames must be added in the order they were captured.
+    pub fn add(&mut self, frame: &wire_format::Frame, timestamp: Duration) {
+        let Some((header, _)) = mac::Header::parse(&frame.payload) else {
+            self.pending = None;
+            return;
+        };
+        if header.frame_type == FrameType::Acknowledgement {
+            self.acknowledgement(header.sequence, timestamp);
+            return;
+        }
+        self.pending = None;
+        let (Some(sequence), Some(source), Some(destination)) =
+            (header.sequence, header.source, header.destination)
+        else {
+            return;
+        };
+        if !header.ack_request || destination == Address::BROADCAST {
+            return;
+        }
+        let link = (
+            Device {
+                pan_id: header.source_pan_or_destination(),
+                address: source,
+            },
+            Device {
+                pan_id: header.destination_pan.or(header.source_pan),
+                address: destination,
+            },
+        );
+        let statistics = self.links.entry(link).or_default();
+        statistics.transmissions += 1;
+        match self.outstanding.get_mut(&link) {
+            Some(outstanding)
+                if outstanding.sequence == sequence && outstanding.payload == frame.payload =>
+            {
+                statistics.retransmissions += 1;
+                outstanding.attempts += 1;
+            }
+            _ => {
+                statistics.frames += 1;
+                let previous = self.outstanding.insert(
+                    link,
+                    Outstanding {
+                        sequence,
+                        payload: frame.payload.clone(),
+                        acknowledged: false,
+                        attempts: 1,
+                    },
+                );
+                if let Some(previous) = previous {
+                    *statistics.retries.entry(previous.attempts - 1).or_default() += 1;
+                }
+            }
+        }
+        self.pending = Some(Pending {
+            link,
+            sequence,
+            end: timestamp + airtime::frame_airtime(frame.payload.len(), OQPSK_BITRATE),
+        });
+    }
+
+    fn acknowledgement(&mut self, sequence: Option<u8>, timestamp: Duration) {
+        let pending = self.pending.take().filter(|pending| {
+            Some(pending.sequence) == sequence
+                && timestamp.saturating_sub(pending.end) <= self.window
+        });
+        let Some(pending) = pending else {
+            self.unmatched += 1;
+            return;
+        };
+        self.matched += 1;
+        if let Some(outstanding) = self.outstanding.get_mut(&pending.link) {
+            if !outstanding.acknowledged {
+                outstanding.acknowledged = true;
+                if let Some(statistics) = self.links.get_mut(&pending.link) {
+                    statistics.delivered += 1;
+                }
+            }
+        }
+    }
+
+    /// Count the retries of the last frame of each link, call after the last frame
+    pub fn finish(&mut self) {
+        self.pending = None;
+        for (link, outstanding) in std::mem::take(&mut self.outstanding) {
+            if let Some(statistics) = self.links.get_mut(&link) {
+                *statistics
+                    .retries
+                    .entry(outstanding.attempts - 1)
+                    .or_default() += 1;
+            }
+        }
+    }
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Analyzer::new(ACK_WAIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK: (Device, Device) = (
+        Device {
+            pan_id: Some(0x1a62),
+            address: Address::Short(0x3c1f),
+        },
+        Device {
+            pan_id: Some(0x1a62),
+            address: Address::Short(0x0000),
+        },
+    );
+
+    fn frame(payload: &[u8]) -> wire_format::Frame {
+        wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(payload).unwrap(),
+        }
+    }
+
+    /// Data from 0x3c1f to 0x0000 requesting an acknowledgement
+    fn data(sequence: u8, content: u8) -> wire_format::Frame {
+        frame(&[
+            0x61, 0x88, sequence, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, content,
+        ])
+    }
+
+    fn ack(sequence: u8) -> wire_format::Frame {
+        frame(&[0x02, 0x00, sequence])
+    }
+
+    fn micros(micros: u64) -> Duration {
+        Duration::from_micros(micros)
+    }
+
+    #[test]
+    fn acknowledged() {
+        let mut analyzer = Analyzer::default();
+        // The data frame takes 576 µs, the acknowledgement follows 192 µs after it
+        analyzer.add(&data(5, 0xaa), micros(0));
+        analyzer.add(&ack(5), micros(768));
+        analyzer.add(&data(6, 0xbb), micros(10_000));
+        analyzer.add(&ack(6), micros(10_768));
+        analyzer.finish();
+
+        assert_eq!((analyzer.matched, analyzer.unmatched), (2, 0));
+        let link = &analyzer.links[&LINK];
+        assert_eq!((link.transmissions, link.frames, link.delivered), (2, 2, 2));
+        assert_eq!(link.retransmissions, 0);
+        assert_eq!(link.retries, BTreeMap::from([(0, 2)]));
+        assert_eq!(link.delivery_ratio(), Some(1.0));
+    }
+
+    #[test]
+    fn retransmission() {
+        let mut analyzer = Analyzer::default();
+        analyzer.add(&data(5, 0xaa), micros(0));
+        analyzer.add(&data(5, 0xaa), micros(2_000));
+        analyzer.add(&data(5, 0xaa), micros(4_000));
+        analyzer.add(&ack(5), micros(4_768));
+        // Same sequence number, other content
+        analyzer.add(&data(5, 0xbb), micros(10_000));
+        analyzer.finish();
+
+        assert_eq!((analyzer.matched, analyzer.unmatched), (1, 0));
+        let link = &analyzer.links[&LINK];
+        assert_eq!((link.transmissions, link.frames, link.delivered), (4, 2, 1));
+        assert_eq!(link.retransmissions, 2);
+        assert_eq!(link.retries, BTreeMap::from([(0, 1), (2, 1)]));
+        assert_eq!(link.delivery_ratio(), Some(0.5));
+    }
+
+    #[test]
+    fn unmatched() {
+        let mut analyzer = Analyzer::default();
+        // Too late
+        analyzer.add(&data(5, 0xaa), micros(0));
+        analyzer.add(&ack(5), micros(2_000));
+        // Other sequence number
+        analyzer.add(&data(6, 0xaa), micros(10_000));
+        analyzer.add(&ack(7), micros(10_768));
+        // Another frame in between
+        analyzer.add(&data(8, 0xaa), micros(20_000));
+        analyzer.add(
+            &frame(&[0x41, 0xc8, 0x01, 0xff, 0xff, 0xff, 0xff]),
+            micros(20_600),
+        );
+        analyzer.add(&ack(8), micros(20_768));
+        analyzer.finish();
+
+        assert_eq!((analyzer.matched, analyzer.unmatched), (0, 3));
+        let link = &analyzer.links[&LINK];
+        assert_eq!((link.frames, link.delivered), (3, 0));
+        assert_eq!(link.delivery_ratio(), Some(0.0));
+    }
+
+    #[test]
+    fn without_ack_request() {
+        let mut analyzer = Analyzer::default();
+        let mut broadcast = data(5, 0xaa);
+        broadcast.payload[5] = 0xff;
+        broadcast.payload[6] = 0xff;
+        analyzer.add(&broadcast, micros(0));
+        let mut no_request = data(6, 0xaa);
+        no_request.payload[0] = 0x41;
+        analyzer.add(&no_request, micros(10_000));
+        analyzer.finish();
+        assert!(analyzer.links.is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Airtime and channel utilisation
+//!
+//! The airtime of a frame is computed from its length and the PHY bit rate, with the
+//! synchronisation header, the PHY header and the FCS included. [`Utilisation`] keeps the
+//! airtime, frame count and frame size histogram of each channel, and the duty cycle and frame
+//! rate over a sliding window.
+use crate::{fcs, wire_format};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+/// Bit rate of the 2.4 GHz O-QPSK PHY
+pub const OQPSK_BITRATE: u32 = 250_000;
+/// Octets of the frame size histogram buckets
+pub const SIZE_BUCKET: usize = 16;
+/// Buckets of the frame size histogram, the last one also counts frames longer than 127 octets
+pub const SIZE_BUCKETS: usize = 8;
+
+/// Preamble, start of frame delimiter and PHY header octets
+const PHY_HEADER_LENGTH: usize = 6;
+
+/// Time to send a frame of `length` octets without FCS at `bitrate` bits per second
+pub fn frame_airtime(length: usize, bitrate: u32) -> Duration {
+    let bits = (PHY_HEADER_LENGTH + length + fcs::LENGTH) as u64 * 8;
+    Duration::from_nanos(bits * 1_000_000_000 / u64::from(bitrate))
+}
+
+/// Frames on a channel
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Channel {
+    pub frames: u64,
+    pub airtime: Duration,
+    /// Frames by length with FCS, in buckets of [`SIZE_BUCKET`] octets
+    pub sizes: [u64; SIZE_BUCKETS],
+    /// Start and airtime of the frames within the window
+    recent: VecDeque<(Duration, Duration)>,
+}
+
+/// Channel utilisation of captured frames
+#[derive(Clone, Debug)]
+pub struct Utilisation {
+    window: Duration,
+    bitrate: u32,
+    /// Channels by number, 0 for captures that do not record the channel
+    pub channels: BTreeMap<u8, Channel>,
+}
+
+impl Utilisation {
+    /// Utilisation with the duty cycle and frame rate over `window`, at `bitrate` bits per
+    /// second
+    pub fn new(window: Duration, bitrate: u32) -> Self {
+        Utilisation {
+            window,
+            bitrate,
+            channels: BTreeMap::new(),
+        }
+    }
+
+    /// Add a frame without FCS captured at `timestamp`
+    pub fn add(&mut self, frame: &wire_format::Frame, timestamp: Duration) {
+        let airtime = frame_airtime(frame.payload.len(), self.bitrate);
+        let channel = self.channels.entry(frame.channel).or_default();
+        channel.frames += 1;
+        channel.airtime += airtime;
+        let bucket = (frame.payload.len() + fcs::LENGTH) / SIZE_BUCKET;
+        channel.sizes[bucket.min(SIZE_BUCKETS - 1)] += 1;
+        channel.recent.push_back((timestamp, airtime));
+        while channel
+            .recent
+            .front()
+            .is_some_and(|&(first, _)| timestamp.saturating_sub(first) > self.window)
+        {
+            channel.recent.pop_front();
+        }
+    }
+
+    /// Frames within the window before `now`
+    fn recent(&self, channel: u8, now: Duration) -> impl Iterator<Item = Duration> + '_ {
+        let window = self.window;
+        self.channels
+            .get(&channel)
+            .into_iter()
+            .flat_map(|channel| channel.recent.iter())
+            .filter(move |&&(timestamp, _)| now.saturating_sub(timestamp) <= window)
+            .map(|&(_, airtime)| airtime)
+    }
+
+    /// Frames per second on `channel` over the window before `now`
+    pub fn rate(&self, channel: u8, now: Duration) -> f64 {
+        self.recent(channel, now).count() as f64 / self.window.as_secs_f64()
+    }
+
+    /// Share of the window before `now` that `channel` was in use
+    pub fn duty_cycle(&self, channel: u8, now: Duration) -> f64 {
+        let airtime: Duration = self.recent(channel, now).sum();
+        airtime.as_secs_f64() / self.window.as_secs_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(channel: u8, length: usize) -> wire_format::Frame {
+        wire_format::Frame {
+            channel,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&vec![0u8; length]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn airtime() {
+        // Acknowledgement, 11 octets
+        assert_eq!(frame_airtime(3, OQPSK_BITRATE), Duration::from_micros(352));
+        // Longest frame, 133 octets
+        assert_eq!(
+            frame_airtime(125, OQPSK_BITRATE),
+            Duration::from_micros(4256)
+        );
+    }
+
+    #[test]
+    fn duty_cycle() {
+        let mut utilisation = Utilisation::new(Duration::from_secs(1), OQPSK_BITRATE);
+        for n in 0..10 {
+            utilisation.add(&frame(20, 125), Duration::from_millis(100 * n));
+        }
+        utilisation.add(&frame(15, 3), Duration::from_millis(500));
+
+        let now = Duration::from_millis(900);
+        assert_eq!(utilisation.rate(20, now), 10.0);
+        assert!((utilisation.duty_cycle(20, now) - 0.04256).abs() < 1e-9);
+        // Frames older than the window are not counted
+        let later = Duration::from_millis(1450);
+        assert_eq!(utilisation.rate(20, later), 5.0);
+        assert_eq!(utilisation.rate(15, later), 1.0);
+        assert_eq!(utilisation.rate(11, later), 0.0);
+
+        let channel = &utilisation.channels[&20];
+        assert_eq!(channel.frames, 10);
+        assert_eq!(channel.airtime, Duration::from_micros(42_560));
+        assert_eq!(channel.sizes, [0, 0, 0, 0, 0, 0, 0, 10]);
+        assert_eq!(utilisation.channels[&15].sizes, [1, 0, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,543 @@
+//! Rule-based detection of suspicious traffic
+//!
+//! A [`Detector`] checks each captured frame against the rules below and returns an [`Alert`]
+//! for every rule the frame breaks.
+//!
+//! * Frame counters, a secured frame from a source with a frame counter that is not above the
+//!   last counter seen from that source with the same key is a replay or a counter reset.
+//!   Retransmissions, the same frame again within [`Rules::retransmission_window`], are not
+//!   reported.
+//! * Unknown devices, a frame from an extended address that is not among the known devices of
+//!   its PAN. Only PANs with known devices are checked, the known devices are given in
+//!   [`Rules::known_devices`] or learned from the start of the capture.
+//! * PAN ID conflicts, beacons from a second PAN coordinator with the same PAN identifier and
+//!   PAN ID conflict notifications sent by devices.
+//! * Beacon requests, more than [`Rules::beacon_requests`] within
+//!   [`Rules::beacon_request_window`], as sent by devices scanning over and over.
+//!
+//! Each unknown device and PAN coordinator is reported once, a burst of beacon requests is
+//! reported once until the rate falls below the limit again.
+use crate::{
+    mac::{self, Address, FrameType},
+    security::AuxiliaryHeader,
+    summary::Device,
+    wire_format,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    time::Duration,
+};
+
+/// MAC command identifiers
+const PAN_ID_CONFLICT_NOTIFICATION: u8 = 0x05;
+const BEACON_REQUEST: u8 = 0x07;
+/// Superframe specification bit set in beacons of the PAN coordinator
+const PAN_COORDINATOR: u16 = 1 << 14;
+/// PAN identifier used by devices that have not joined a PAN
+const BROADCAST_PAN: u16 = 0xffff;
+
+/// Thresholds and known devices of the rules
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules {
+    /// A frame repeated within this time is a retransmission rather than a replay
+    pub retransmission_window: Duration,
+    /// Extended addresses of the devices of each PAN
+    pub known_devices: BTreeMap<u16, BTreeSet<u64>>,
+    /// Learn the devices sending within this time from the first frame as known devices
+    pub learning: Option<Duration>,
+    /// Beacon requests allowed within the window
+    pub beacon_requests: usize,
+    pub beacon_request_window: Duration,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            retransmission_window: Duration::from_secs(1),
+            known_devices: BTreeMap::new(),
+            learning: None,
+            beacon_requests: 10,
+            beacon_request_window: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Suspicious traffic
+#[derive(Clone, Debug, PartialEq)]
+pub enum Anomaly {
+    /// Secured frame with a frame counter not above the last counter of the source
+    FrameCounter {
+        source: Device,
+        key_index: Option<u8>,
+        counter: u32,
+        last: u32,
+    },
+    /// Frame from an extended address that is not a known device of the PAN
+    UnknownDevice { pan_id: u16, address: u64 },
+    /// Beacon from a PAN coordinator other than the first one seen for the PAN
+    PanConflict {
+        pan_id: u16,
+        coordinator: Address,
+        other: Address,
+    },
+    /// PAN ID conflict notification command
+    PanConflictNotification {
+        pan_id: Option<u16>,
+        source: Option<Address>,
+    },
+    /// More beacon requests than allowed within the window
+    BeaconRequests { count: usize, window: Duration },
+}
+
+impl Anomaly {
+    /// Name of the rule that found the anomaly
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Anomaly::FrameCounter { .. } => "frame-counter",
+            Anomaly::UnknownDevice { .. } => "unknown-device",
+            Anomaly::PanConflict { .. } | Anomaly::PanConflictNotification { .. } => "pan-conflict",
+            Anomaly::BeaconRequests { .. } => "beacon-requests",
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::FrameCounter {
+                source,
+                key_index,
+                counter,
+                last,
+            } => {
+                match counter == last {
+                    true => write!(f, "Frame counter {} reused by ", counter)?,
+                    false => write!(f, "Frame counter {} after {} from ", counter, last)?,
+                }
+                if let Some(pan_id) = source.pan_id {
+                    write!(f, "0x{:04x} ", pan_id)?;
+                }
+                write!(f, "{}", source.address)?;
+                match key_index {
+                    Some(key_index) => write!(f, ", key index {}", key_index),
+                    None => Ok(()),
+                }
+            }
+            Anomaly::UnknownDevice { pan_id, address } => write!(
+                f,
+                "Unknown device {} in PAN 0x{:04x}",
+                Address::Extended(*address),
+                pan_id
+            ),
+            Anomaly::PanConflict {
+                pan_id,
+                coordinator,
+                other,
+            } => write!(
+                f,
+                "Second PAN coordinator {} in PAN 0x{:04x}, coordinator {}",
+                other, pan_id, coordinator
+            ),
+            Anomaly::PanConflictNotification { pan_id, source } => {
+                write!(f, "PAN ID conflict notification")?;
+                if let Some(source) = source {
+                    write!(f, " from {}", source)?;
+                }
+                match pan_id {
+                    Some(pan_id) => write!(f, " in PAN 0x{:04x}", pan_id),
+                    None => Ok(()),
+                }
+            }
+            Anomaly::BeaconRequests { count, window } => write!(
+                f,
+                "{} beacon requests within {} s",
+                count,
+                window.as_secs_f64()
+            ),
+        }
+    }
+}
+
+/// Anomaly found in a frame captured at `timestamp`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub timestamp: Duration,
+    pub anomaly: Anomaly,
+}
+
+/// Source and key of a frame counter
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct CounterKey {
+    source: Device,
+    key_id_mode: u8,
+    key_source: Vec<u8>,
+    key_index: Option<u8>,
+}
+
+/// Last secured frame of a source with a key
+#[derive(Clone, Debug)]
+struct LastCounter {
+    counter: u32,
+    timestamp: Duration,
+    payload: wire_format::Payload,
+}
+
+/// Checks captured frames against [`Rules`]
+#[derive(Clone, Debug)]
+pub struct Detector {
+    rules: Rules,
+    start: Option<Duration>,
+    counters: BTreeMap<CounterKey, LastCounter>,
+    /// Known devices, with the unknown devices already reported
+    devices: BTreeMap<u16, BTreeSet<u64>>,
+    coordinators: BTreeMap<u16, Address>,
+    /// Second coordinators already reported
+    conflicts: BTreeSet<(u16, Address)>,
+    beacon_requests: VecDeque<Duration>,
+    flooding: bool,
+}
+
+impl Detector {
+    pub fn new(rules: Rules) -> Self {
+        Detector {
+            devices: rules.known_devices.clone(),
+            rules,
+            start: None,
+            counters: BTreeMap::new(),
+            coordinators: BTreeMap::new(),
+            conflicts: BTreeSet::new(),
+            beacon_requests: VecDeque::new(),
+            flooding: false,
+        }
+    }
+
+    /// Check a frame without FCS captured at `timestamp`, returns the anomalies found
+    ///
+    /// Frames must be added in the order they were captured.
+    pub fn add(&mut self, frame: &wire_format::Frame, timestamp: Duration) -> Vec<Alert> {
+        let start = *self.start.get_or_insert(timestamp);
+        let Some((header, length)) = mac::Header::parse(&frame.payload) else {
+            return Vec::new();
+        };
+        let mut body = &frame.payload[length..];
+        let mut auxiliary = None;
+        if header.security_enabled {
+            match AuxiliaryHeader::parse(body) {
+                Some((header, length)) => {
+                    auxiliary = Some(header);
+                    body = &body[length..];
+                }
+                None => return Vec::new(),
+            }
+        }
+
+        let mut anomalies = Vec::new();
+        let source = header.source.map(|address| Device {
+            pan_id: header.source_pan_or_destination(),
+            address,
+        });
+        if let (Some(auxiliary), Some(source)) = (auxiliary, source) {
+            anomalies.extend(self.frame_counter(frame, timestamp, source, auxiliary));
+        }
+        if let Some(Device {
+            pan_id: Some(pan_id),
+            address: Address::Extended(address),
+        }) = source
+        {
+            let learning = self
+                .rules
+                .learning
+                .is_some_and(|learning| timestamp < start + learning);
+            anomalies.extend(self.device(pan_id, address, learning));
+        }
+        match header.frame_type {
+            FrameType::Beacon if !header.security_enabled => {
+                anomalies.extend(self.beacon(&header, body));
+            }
+            // Header IEs come before the command identifier
+            FrameType::Command if !header.information_elements => match body.first() {
+                Some(&PAN_ID_CONFLICT_NOTIFICATION) => {
+                    anomalies.push(Anomaly::PanConflictNotification {
+                        pan_id: header.source_pan_or_destination(),
+                        source: header.source,
+                    });
+                }
+                Some(&BEACON_REQUEST) => anomalies.extend(self.beacon_request(timestamp)),
+                _ => (),
+            },
+            _ => (),
+        }
+        anomalies
+            .into_iter()
+            .map(|anomaly| Alert { timestamp, anomaly })
+            .collect()
+    }
+
+    fn frame_counter(
+        &mut self,
+        frame: &wire_format::Frame,
+        timestamp: Duration,
+        source: Device,
+        auxiliary: AuxiliaryHeader,
+    ) -> Option<Anomaly> {
+        let counter = auxiliary.frame_counter?;
+        let key = CounterKey {
+            source,
+            key_id_mode: auxiliary.key_id_mode,
+            key_source: auxiliary.key_source,
+            key_index: auxiliary.key_index,
+        };
+        let current = LastCounter {
+            counter,
+            timestamp,
+            payload: frame.payload.clone(),
+        };
+        let key_index = key.key_index;
+        let Some(last) = self.counters.get_mut(&key) else {
+            self.counters.insert(key, current);
+            return None;
+        };
+        if counter > last.counter {
+            *last = current;
+            return None;
+        }
+        let retransmission = counter == last.counter
+            && frame.payload == last.payload
+            && timestamp.saturating_sub(last.timestamp) <= self.rules.retransmission_window;
+        if retransmission {
+            last.timestamp = timestamp;
+            return None;
+        }
+        Some(Anomaly::FrameCounter {
+            source,
+            key_index,
+            counter,
+            last: last.counter,
+        })
+    }
+
+    fn device(&mut self, pan_id: u16, address: u64, learning: bool) -> Option<Anomaly> {
+        if pan_id == BROADCAST_PAN {
+            return None;
+        }
+        if learning {
+            self.devices.entry(pan_id).or_default().insert(address);
+            return None;
+        }
+        let known = self.devices.get_mut(&pan_id)?;
+        known
+            .insert(address)
+            .then_some(Anomaly::UnknownDevice { pan_id, address })
+    }
+
+    fn beacon(&mut self, header: &mac::Header, body: &[u8]) -> Option<Anomaly> {
+        let superframe = body.get(..2).map(|b| u16::from_le_bytes([b[0], b[1]]))?;
+        let (Some(pan_id), Some(source)) = (header.source_pan, header.source) else {
+            return None;
+        };
+        if superframe & PAN_COORDINATOR == 0 {
+            return None;
+        }
+        let coordinator = *self.coordinators.entry(pan_id).or_insert(source);
+        (coordinator != source && self.conflicts.insert((pan_id, source))).then_some(
+            Anomaly::PanConflict {
+                pan_id,
+                coordinator,
+                other: source,
+            },
+        )
+    }
+
+    fn beacon_request(&mut self, timestamp: Duration) -> Option<Anomaly> {
+        let window = self.rules.beacon_request_window;
+        while self
+            .beacon_requests
+            .front()
+            .is_some_and(|&first| timestamp.saturating_sub(first) >= window)
+        {
+            self.beacon_requests.pop_front();
+        }
+        self.beacon_requests.push_back(timestamp);
+        let count = self.beacon_requests.len();
+        if count <= self.rules.beacon_requests {
+            self.flooding = false;
+            return None;
+        }
+        let first = !self.flooding;
+        self.flooding = true;
+        first.then_some(Anomaly::BeaconRequests { count, window })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(payload: &[u8]) -> wire_format::Frame {
+        wire_format::Frame {
+            channel: 15,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(payload).unwrap(),
+        }
+    }
+
+    /// Secured data frame from 0x3c1f in PAN 0x1a62, MIC-32 with key index 1
+    fn secured(sequence: u8, counter: u32) -> wire_format::Frame {
+        let mut payload = vec![
+            0x69, 0x88, sequence, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x09,
+        ];
+        payload.extend_from_slice(&counter.to_le_bytes());
+        payload.extend_from_slice(&[0x01, 0xaa, 0x01, 0x02, 0x03, 0x04]);
+        frame(&payload)
+    }
+
+    fn anomalies(detector: &mut Detector, frame: &wire_format::Frame, ms: u64) -> Vec<Anomaly> {
+        detector
+            .add(frame, Duration::from_millis(ms))
+            .into_iter()
+            .map(|alert| alert.anomaly)
+            .collect()
+    }
+
+    #[test]
+    fn frame_counters() {
+        let mut detector = Detector::new(Rules::default());
+        assert_eq!(anomalies(&mut detector, &secured(1, 100), 0), vec![]);
+        // Retransmission
+        assert_eq!(anomalies(&mut detector, &secured(1, 100), 5), vec![]);
+        assert_eq!(anomalies(&mut detector, &secured(2, 101), 10), vec![]);
+        let source = Device {
+            pan_id: Some(0x1a62),
+            address: Address::Short(0x3c1f),
+        };
+        // Same counter with another sequence number, then an older counter
+        let reused = anomalies(&mut detector, &secured(3, 101), 20);
+        assert_eq!(
+            reused,
+            vec![Anomaly::FrameCounter {
+                source,
+                key_index: Some(1),
+                counter: 101,
+                last: 101
+            }]
+        );
+        assert_eq!(
+            reused[0].to_string(),
+            "Frame counter 101 reused by 0x1a62 0x3c1f, key index 1"
+        );
+        let replayed = anomalies(&mut detector, &secured(1, 100), 30);
+        assert_eq!(
+            replayed[0].to_string(),
+            "Frame counter 100 after 101 from 0x1a62 0x3c1f, key index 1"
+        );
+        // The same frame long after is a replay
+        assert_eq!(anomalies(&mut detector, &secured(2, 101), 5000).len(), 1);
+    }
+
+    #[test]
+    fn unknown_devices() {
+        // Data frames from extended addresses to the coordinator of PAN 0x1a62
+        let data = |address: u8| {
+            frame(&[
+                0x41, 0xc8, 0x01, 0x62, 0x1a, 0x00, 0x00, address, 0x07, 0x06, 0x05, 0x04, 0x03,
+                0x02, 0x01, 0xaa,
+            ])
+        };
+        let rules = Rules {
+            learning: Some(Duration::from_secs(1)),
+            ..Rules::default()
+        };
+        let mut detector = Detector::new(rules);
+        assert_eq!(anomalies(&mut detector, &data(0x08), 0), vec![]);
+        assert_eq!(anomalies(&mut detector, &data(0x08), 2000), vec![]);
+        let unknown = anomalies(&mut detector, &data(0x09), 2500);
+        assert_eq!(
+            unknown,
+            vec![Anomaly::UnknownDevice {
+                pan_id: 0x1a62,
+                address: 0x0102030405060709
+            }]
+        );
+        assert_eq!(
+            unknown[0].to_string(),
+            "Unknown device 01:02:03:04:05:06:07:09 in PAN 0x1a62"
+        );
+        // Reported once
+        assert_eq!(anomalies(&mut detector, &data(0x09), 3000), vec![]);
+
+        // PANs without known devices are not checked
+        let mut detector = Detector::new(Rules::default());
+        assert_eq!(anomalies(&mut detector, &data(0x09), 0), vec![]);
+    }
+
+    #[test]
+    fn pan_conflicts() {
+        let mut detector = Detector::new(Rules::default());
+        // Beacons of PAN coordinators 0x0000 and 0x0001 in PAN 0x1a62
+        let beacon = |address: u8| {
+            frame(&[
+                0x00, 0x80, 0x01, 0x62, 0x1a, address, 0x00, 0xff, 0xcf, 0x00, 0x00,
+            ])
+        };
+        assert_eq!(anomalies(&mut detector, &beacon(0x00), 0), vec![]);
+        assert_eq!(anomalies(&mut detector, &beacon(0x00), 100), vec![]);
+        let conflict = anomalies(&mut detector, &beacon(0x01), 200);
+        assert_eq!(
+            conflict[0].to_string(),
+            "Second PAN coordinator 0x0001 in PAN 0x1a62, coordinator 0x0000"
+        );
+        assert_eq!(anomalies(&mut detector, &beacon(0x01), 300), vec![]);
+
+        // PAN ID conflict notification from an extended address to the coordinator
+        let notification = [
+            0x63, 0xc8, 0x02, 0x62, 0x1a, 0x00, 0x00, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02,
+            0x01, 0x05,
+        ];
+        let notified = anomalies(&mut detector, &frame(&notification), 400);
+        assert_eq!(notified[0].rule(), "pan-conflict");
+        assert_eq!(
+            notified[0].to_string(),
+            "PAN ID conflict notification from 01:02:03:04:05:06:07:08 in PAN 0x1a62"
+        );
+    }
+
+    #[test]
+    fn beacon_request_bursts() {
+        let rules = Rules {
+            beacon_requests: 2,
+            beacon_request_window: Duration::from_secs(1),
+            ..Rules::default()
+        };
+        let mut detector = Detector::new(rules);
+        let request = frame(&[0x03, 0x08, 0x01, 0xff, 0xff, 0xff, 0xff, 0x07]);
+        let mut alerts = Vec::new();
+        for ms in [0, 100, 200, 300, 2000, 2100, 2200] {
+            alerts.extend(detector.add(&request, Duration::from_millis(ms)));
+        }
+        assert_eq!(
+            alerts,
+            vec![
+                Alert {
+                    timestamp: Duration::from_millis(200),
+                    anomaly: Anomaly::BeaconRequests {
+                        count: 3,
+                        window: Duration::from_secs(1)
+                    }
+                },
+                Alert {
+                    timestamp: Duration::from_millis(2200),
+                    anomaly: Anomaly::BeaconRequests {
+                        count: 3,
+                        window: Duration::from_secs(1)
+                    }
+                }
+            ]
+        );
+        assert_eq!(
+            alerts[0].anomaly.to_string(),
+            "3 beacon requests within 1 s"
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Async sniffer device for tokio, enabled with the `tokio` feature
+//!
+//! [`AsyncDevice`] mirrors [`crate::Device`] and decodes the wire format the same way, but
+//! the commands are async and every command fails with [`std::io::ErrorKind::TimedOut`]
+//! when it has not completed within the timeout given when the device was created.
+//! Captured frames are received through a [`Stream`].
+use crate::{decoder::Decoder, transport::TCP_PREFIX, Error, Statistics};
+use futures_core::Stream;
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// Maximum time to wait for a TCP connection to be established
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Bidirectional async byte stream to a sniffer device
+pub trait AsyncTransport: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncTransport for T {}
+
+/// Open a serial port transport
+pub fn open_serial(name: &str) -> Result<tokio_serial::SerialStream, serialport::Error> {
+    use tokio_serial::SerialPortBuilderExt;
+    tokio_serial::new(name, wire_format::BAUD_RATE).open_native_async()
+}
+
+/// Open a TCP transport, `address` is on the form `host:port`
+pub async fn open_tcp(address: &str) -> std::io::Result<tokio::net::TcpStream> {
+    let stream = with_timeout(CONNECT_TIMEOUT, tokio::net::TcpStream::connect(address)).await??;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Open a transport by URI, either `tcp://host:port` or a serial port name
+pub async fn open(uri: &str) -> Result<Box<dyn AsyncTransport>, Error> {
+    match uri.strip_prefix(TCP_PREFIX) {
+        Some(address) => Ok(Box::new(open_tcp(address).await?)),
+        None => Ok(Box::new(open_serial(uri)?)),
+    }
+}
+
+async fn with_timeout<F: Future>(duration: Duration, future: F) -> std::io::Result<F::Output> {
+    tokio::time::timeout(duration, future)
+        .await
+        .map_err(|_| std::io::ErrorKind::TimedOut.into())
+}
+
+/// Async sniffer device speaking the wire format over a transport
+pub struct AsyncDevice<T: AsyncTransport = Box<dyn AsyncTransport>> {
+    transport: T,
+    decoder: Decoder,
+    timeout: Duration,
+    transmit_id: u32,
+}
+
+impl AsyncDevice {
+    /// Open a device by URI, see [`open`]
+    pub async fn open(uri: &str, timeout: Duration) -> Result<Self, Error> {
+        Ok(Self::new(open(uri).await?, timeout))
+    }
+}
+
+impl<T: AsyncTransport> AsyncDevice<T> {
+    /// Create a device on an open transport, commands time out after `timeout`
+    pub fn new(transport: T, timeout: Duration) -> Self {
+        AsyncDevice {
+            transport,
+            decoder: Decoder::new(),
+            timeout,
+            transmit_id: 0,
+        }
+    }
+
+    /// Counters of the data received so far
+    pub fn statistics(&self) -> Statistics {
+        self.decoder.statistics()
+    }
+
+    async fn write_packet(&mut self, packet: &wire_format::Packet) -> Result<(), Error> {
+        let mut buffer = [0u8; 512];
+        let payload = packet.encode(&mut buffer)?;
+        with_timeout(self.timeout, async {
+            self.transport.write_all(payload).await?;
+            self.transport.flush().await
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Wait for the next packet, without a timeout
+    async fn read_packet(&mut self) -> Result<wire_format::Packet, Error> {
+        loop {
+            if let Some(result) = self.decoder.decode() {
+                return result;
+            }
+            let bytes = self.transport.read(self.decoder.spare()).await?;
+            if bytes == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            self.decoder.fill(bytes);
+        }
+    }
+
+    /// Check that the device is a sniffer, waits for the probe reply until the timeout
+    pub async fn probe(&mut self) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::Probe(wire_format::PROBE_HOST))
+            .await?;
+        with_timeout(self.timeout, async {
+            loop {
+                if let wire_format::Packet::Probe(_value) = self.read_packet().await? {
+                    return Ok(());
+                }
+            }
+        })
+        .await?
+    }
+
+    /// Set the IEEE 802.15.4 channel, 11 to 26
+    pub async fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::Channel(channel))
+            .await
+    }
+
+    /// Set the transmit power in dBm
+    pub async fn set_power(&mut self, power: i32) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::Power(power)).await
+    }
+
+    /// Start sending captured frames
+    pub async fn start_capture(&mut self) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::CaptureStart).await
+    }
+
+    /// Stop sending captured frames
+    pub async fn stop_capture(&mut self) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::CaptureStop).await
+    }
+
+    /// Transmit a frame without FCS, waits for the result until the timeout
+    ///
+    /// Captured frames received while waiting are dropped.
+    pub async fn transmit(
+        &mut self,
+        payload: wire_format::Payload,
+    ) -> Result<wire_format::TransmitStatus, Error> {
+        self.transmit_id = self.transmit_id.wrapping_add(1);
+        let id = self.transmit_id;
+        self.write_packet(&wire_format::Packet::Transmit(wire_format::Transmit {
+            id,
+            payload,
+        }))
+        .await?;
+        with_timeout(self.timeout, async {
+            loop {
+                if let wire_format::Packet::TransmitResult(result) = self.read_packet().await? {
+                    if result.id == id {
+                        return Ok(result.status);
+                    }
+                }
+            }
+        })
+        .await?
+    }
+
+    /// Receive a packet, waits until the timeout
+    ///
+    /// Fails with [`Error::Wire`] for a packet that could not be decoded. Cancelling the
+    /// future does not lose any received data.
+    pub async fn receive(&mut self) -> Result<wire_format::Packet, Error> {
+        with_timeout(self.timeout, self.read_packet()).await?
+    }
+
+    /// Stream of captured frames
+    ///
+    /// Corrupted packets and other packets are skipped and the stream waits for frames
+    /// without a timeout. It ends after yielding any other error.
+    pub fn frames(&mut self) -> Frames<'_, T> {
+        Frames {
+            device: self,
+            failed: false,
+        }
+    }
+}
+
+/// Stream of captured frames, see [`AsyncDevice::frames`]
+pub struct Frames<'a, T: AsyncTransport> {
+    device: &'a mut AsyncDevice<T>,
+    failed: bool,
+}
+
+impl<T: AsyncTransport> Stream for Frames<'_, T> {
+    type Item = Result<wire_format::Frame, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let device = &mut *this.device;
+        while !this.failed {
+            match device.decoder.decode() {
+                Some(Ok(wire_format::Packet::CaptureFrame(frame))) => {
+                    return Poll::Ready(Some(Ok(frame)))
+                }
+                Some(Ok(_)) | Some(Err(Error::Wire(_))) => continue,
+                Some(Err(error)) => {
+                    this.failed = true;
+                    return Poll::Ready(Some(Err(error)));
+                }
+                None => (),
+            }
+            let mut buffer = ReadBuf::new(device.decoder.spare());
+            let result = match Pin::new(&mut device.transport).poll_read(cx, &mut buffer) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(result) => result.map(|()| buffer.filled().len()),
+            };
+            match result {
+                Ok(0) => {
+                    this.failed = true;
+                    let error = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+                    return Poll::Ready(Some(Err(error.into())));
+                }
+                Ok(bytes) => device.decoder.fill(bytes),
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(error) => {
+                    this.failed = true;
+                    return Poll::Ready(Some(Err(error.into())));
+                }
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    fn encode(packet: &wire_format::Packet) -> Vec<u8> {
+        let mut buffer = [0u8; 512];
+        packet.encode(&mut buffer).unwrap().to_vec()
+    }
+
+    fn device() -> (AsyncDevice<DuplexStream>, DuplexStream) {
+        let (host, sniffer) = tokio::io::duplex(4096);
+        (AsyncDevice::new(host, TIMEOUT), sniffer)
+    }
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn probe() {
+        let (mut device, mut sniffer) = device();
+        sniffer
+            .write_all(&encode(&wire_format::Packet::Probe(
+                wire_format::PROBE_DEVICE,
+            )))
+            .await
+            .unwrap();
+        device.probe().await.unwrap();
+        let expected = encode(&wire_format::Packet::Probe(wire_format::PROBE_HOST));
+        let mut sent = vec![0u8; expected.len()];
+        sniffer.read_exact(&mut sent).await.unwrap();
+        assert_eq!(sent, expected);
+    }
+
+    #[tokio::test]
+    async fn probe_without_reply() {
+        let (mut device, _sniffer) = device();
+        match device.probe().await {
+            Err(Error::Io(error)) => assert_eq!(error.kind(), std::io::ErrorKind::TimedOut),
+            result => panic!("Unexpected probe result {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn configure() {
+        let (mut device, mut sniffer) = device();
+        device.set_channel(15).await.unwrap();
+        device.start_capture().await.unwrap();
+        device.stop_capture().await.unwrap();
+        let mut expected = encode(&wire_format::Packet::Channel(15));
+        expected.extend(encode(&wire_format::Packet::CaptureStart));
+        expected.extend(encode(&wire_format::Packet::CaptureStop));
+        let mut sent = vec![0u8; expected.len()];
+        sniffer.read_exact(&mut sent).await.unwrap();
+        assert_eq!(sent, expected);
+    }
+
+    #[tokio::test]
+    async fn transmit() {
+        let (mut device, mut sniffer) = device();
+        let result = wire_format::Packet::TransmitResult(wire_format::TransmitResult {
+            id: 1,
+            status: wire_format::TransmitStatus::Sent,
+        });
+        sniffer.write_all(&encode(&result)).await.unwrap();
+        let payload = wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap();
+        assert_eq!(
+            device.transmit(payload.clone()).await.unwrap(),
+            wire_format::TransmitStatus::Sent
+        );
+        let expected = encode(&wire_format::Packet::Transmit(wire_format::Transmit {
+            id: 1,
+            payload,
+        }));
+        let mut sent = vec![0u8; expected.len()];
+        sniffer.read_exact(&mut sent).await.unwrap();
+        assert_eq!(sent, expected);
+    }
+
+    #[tokio::test]
+    async fn command_timeout() {
+        let (host, _sniffer) = tokio::io::duplex(1);
+        let mut device = AsyncDevice::new(host, TIMEOUT);
+        assert!(matches!(device.set_channel(15).await, Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn stream_frames() {
+        let (mut device, mut sniffer) = device();
+        let frame = |sequence| wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: Some(-50_000),
+            link_quality_index: Some(200),
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, sequence]).unwrap(),
+        };
+        let mut data = encode(&wire_format::Packet::CaptureFrame(frame(1)));
+        data.extend([0x05, 0xff, 0xff, 0x00]);
+        data.extend(encode(&wire_format::Packet::Probe(
+            wire_format::PROBE_DEVICE,
+        )));
+        data.extend(encode(&wire_format::Packet::CaptureFrame(frame(2))));
+        sniffer.write_all(&data).await.unwrap();
+        drop(sniffer);
+        let mut frames = device.frames();
+        assert_eq!(next(&mut frames).await.unwrap().unwrap(), frame(1));
+        assert_eq!(next(&mut frames).await.unwrap().unwrap(), frame(2));
+        assert!(next(&mut frames).await.unwrap().is_err());
+        assert!(next(&mut frames).await.is_none());
+        assert_eq!(
+            device.statistics(),
+            Statistics {
+                frames: 2,
+                corrupted: 1,
+                reconnects: 0,
+            }
+        );
+    }
+}
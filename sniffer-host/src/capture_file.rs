@@ -0,0 +1,317 @@
+//! Reading IEEE 802.15.4 frames from pcap and pcapng files
+//!
+//! Files with the IEEE 802.15.4 link types with and without FCS and IEEE 802.15.4 TAP are
+//! supported. The link-layer header and the FCS are stripped so that each frame is read as
+//! it is sent over the wire format, frames with an FCS that does not match are marked.
+use crate::{link_type::LinkType, pcapng, tap, Error};
+use ieee802154_sniffer_wire_format as wire_format;
+use pcap_file::{
+    pcap::PcapReader,
+    pcapng::{
+        blocks::{
+            enhanced_packet::EnhancedPacketOption,
+            interface_description::InterfaceDescriptionOption,
+        },
+        Block, PcapNgReader,
+    },
+    DataLink,
+};
+use std::{path::Path, time::Duration};
+
+/// pcapng section header block type, also the first four bytes of a pcapng file
+const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+
+/// Frame read from a capture file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Capture time since the Unix epoch
+    pub timestamp: Duration,
+    /// Frame without FCS, the channel is 0 and the signal fields are unset unless the link
+    /// type carries them
+    pub frame: wire_format::Frame,
+    /// Start of frame time from the clock of the capturing device, when the TAP header
+    /// records it, which the sniffers of this project do not
+    pub device_timestamp: Option<Duration>,
+    /// The FCS of the frame did not match, or the capture flags the frame with a CRC error
+    pub fcs_error: bool,
+}
+
+/// Read the frames of a pcap or pcapng capture
+///
+/// Frames too large for the wire format are skipped.
+pub fn read(data: &[u8]) -> Result<Vec<Record>, Error> {
+    if data.starts_with(&PCAPNG_MAGIC) {
+        read_pcapng(data)
+    } else {
+        read_pcap(data)
+    }
+}
+
+/// Read the frames of a pcap or pcapng file, see [`read`]
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<Record>, Error> {
+    read(&std::fs::read(path)?)
+}
+
+fn read_pcap(data: &[u8]) -> Result<Vec<Record>, Error> {
+    let mut reader = PcapReader::new(data)?;
+    let link_type = check_datalink(reader.header().datalink)?;
+    let mut records = Vec::new();
+    while let Some(packet) = reader.next_packet() {
+        let packet = packet?;
+        if let Some(frame) = link_type.decode(&packet.data) {
+            records.push(Record {
+                timestamp: packet.timestamp,
+                frame,
+                device_timestamp: device_timestamp(link_type, &packet.data),
+                fcs_error: link_type.fcs_error(&packet.data),
+            });
+        }
+    }
+    Ok(records)
+}
+
+/// Link type and timestamp units of a pcapng interface
+struct Interface {
+    link_type: LinkType,
+    resolution: Resolution,
+}
+
+/// pcapng timestamp resolution, `if_tsresol`
+#[derive(Clone, Copy)]
+enum Resolution {
+    /// Units of 10^-n seconds
+    Decimal(u32),
+    /// Units of 2^-n seconds
+    Binary(u32),
+}
+
+impl Resolution {
+    fn from_option(value: u8) -> Self {
+        match value & 0x80 {
+            0 => Resolution::Decimal(u32::from(value)),
+            _ => Resolution::Binary(u32::from(value & 0x7f)),
+        }
+    }
+
+    /// Timestamp from the raw value, which the pcapng reader gives as nanoseconds
+    fn timestamp(&self, raw: Duration) -> Duration {
+        let raw = raw.as_nanos();
+        let nanoseconds = match *self {
+            Resolution::Decimal(9) => raw,
+            Resolution::Decimal(n) if n < 9 => raw * 10u128.pow(9 - n),
+            Resolution::Decimal(n) => raw / 10u128.pow((n - 9).min(38)),
+            Resolution::Binary(n) => (raw * 1_000_000_000) >> n.min(127),
+        };
+        Duration::from_nanos(u64::try_from(nanoseconds).unwrap_or(u64::MAX))
+    }
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<Record>, Error> {
+    let mut reader = PcapNgReader::new(data)?;
+    let mut interfaces = Vec::new();
+    let mut records = Vec::new();
+    while let Some(block) = reader.next_block() {
+        let (interface_id, timestamp, data, flags) = match block? {
+            Block::InterfaceDescription(interface) => {
+                let link_type = check_datalink(interface.linktype)?;
+                let resolution = interface
+                    .options
+                    .iter()
+                    .find_map(|option| match option {
+                        InterfaceDescriptionOption::IfTsResol(value) => {
+                            Some(Resolution::from_option(*value))
+                        }
+                        _ => None,
+                    })
+                    .unwrap_or(Resolution::Decimal(6));
+                interfaces.push(Interface {
+                    link_type,
+                    resolution,
+                });
+                continue;
+            }
+            Block::EnhancedPacket(packet) => {
+                let flags = packet.options.iter().find_map(|option| match option {
+                    EnhancedPacketOption::Flags(flags) => Some(*flags),
+                    _ => None,
+                });
+                (
+                    packet.interface_id,
+                    Some(packet.timestamp),
+                    packet.data,
+                    flags.unwrap_or_default(),
+                )
+            }
+            Block::SimplePacket(packet) => (0, None, packet.data, 0),
+            _ => continue,
+        };
+        let interface = match interfaces.get(interface_id as usize) {
+            Some(interface) => interface,
+            None => continue,
+        };
+        if let Some(frame) = interface.link_type.decode(&data) {
+            let timestamp = timestamp
+                .map(|timestamp| interface.resolution.timestamp(timestamp))
+                .or_else(|| records.last().map(|record: &Record| record.timestamp))
+                .unwrap_or_default();
+            records.push(Record {
+                timestamp,
+                frame,
+                device_timestamp: device_timestamp(interface.link_type, &data),
+                fcs_error: flags & pcapng::FLAG_CRC_ERROR != 0
+                    || interface.link_type.fcs_error(&data),
+            });
+        }
+    }
+    Ok(records)
+}
+
+fn check_datalink(datalink: DataLink) -> Result<LinkType, Error> {
+    LinkType::from_datalink(datalink).ok_or(Error::UnsupportedLinkType(datalink))
+}
+
+fn device_timestamp(link_type: LinkType, data: &[u8]) -> Option<Duration> {
+    match link_type {
+        LinkType::Tap => tap::start_of_frame(data),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcapng::{CaptureWriter, InterfaceDescription, PacketAnnotation};
+    use pcap_file::pcap::{PcapHeader, PcapPacket, PcapWriter};
+
+    const FRAME: [u8; 3] = [0x02, 0x00, 0x04];
+
+    #[test]
+    fn pcap_with_fcs() {
+        let header = PcapHeader {
+            datalink: DataLink::IEEE802_15_4,
+            ..PcapHeader::default()
+        };
+        let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+        let data = [0x02, 0x00, 0x04, 0x9c, 0xf3];
+        let timestamp = Duration::new(1_700_000_000, 250_000_000);
+        writer
+            .write_packet(&PcapPacket::new(timestamp, 5, &data))
+            .unwrap();
+        // Too short to have an FCS
+        writer
+            .write_packet(&PcapPacket::new(timestamp, 1, &[0x02]))
+            .unwrap();
+        let records = read(&writer.into_writer()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].timestamp, timestamp);
+        assert_eq!(&records[0].frame.payload[..], &FRAME);
+        assert_eq!(records[0].frame.channel, 0);
+        assert_eq!(records[0].device_timestamp, None);
+        assert!(!records[0].fcs_error);
+    }
+
+    #[test]
+    fn pcap_with_bad_fcs() {
+        let header = PcapHeader {
+            datalink: DataLink::IEEE802_15_4,
+            ..PcapHeader::default()
+        };
+        let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+        let data = [0x02, 0x00, 0x04, 0x9c, 0xf4];
+        writer
+            .write_packet(&PcapPacket::new(Duration::ZERO, 5, &data))
+            .unwrap();
+        let records = read(&writer.into_writer()).unwrap();
+        assert_eq!(&records[0].frame.payload[..], &FRAME);
+        assert!(records[0].fcs_error);
+    }
+
+    #[test]
+    fn pcapng_flagged_crc_error() {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        let interface = InterfaceDescription {
+            name: String::from("/dev/ttyACM0"),
+            channel: 20,
+            serial_number: None,
+        };
+        writer
+            .add_interface(DataLink::IEEE802_15_4_NOFCS, &interface)
+            .unwrap();
+        let annotation = PacketAnnotation {
+            fcs_error: true,
+            ..PacketAnnotation::default()
+        };
+        writer
+            .write_packet(0, Duration::ZERO, &FRAME, &annotation)
+            .unwrap();
+        let records = read(&writer.finish().unwrap()).unwrap();
+        assert!(records[0].fcs_error);
+    }
+
+    #[test]
+    fn pcapng_tap() {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        let interface = InterfaceDescription {
+            name: String::from("/dev/ttyACM0"),
+            channel: 20,
+            serial_number: None,
+        };
+        writer
+            .add_interface(DataLink::IEEE802_15_4_TAP, &interface)
+            .unwrap();
+        let frame = wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: Some(-70_000),
+            link_quality_index: Some(100),
+            payload: wire_format::Payload::from_slice(&FRAME).unwrap(),
+        };
+        let mut buffer = [0u8; tap::BUFFER_SIZE];
+        let length = tap::encode(&frame, &mut buffer);
+        let timestamp = Duration::new(1_700_000_000, 123_456_789);
+        writer
+            .write_packet(
+                0,
+                timestamp,
+                &buffer[..length],
+                &PacketAnnotation::default(),
+            )
+            .unwrap();
+        let records = read(&writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            records,
+            vec![Record {
+                timestamp,
+                frame,
+                device_timestamp: None,
+                fcs_error: false
+            }]
+        );
+    }
+
+    #[test]
+    fn timestamp_resolution() {
+        let raw = Duration::from_nanos(1_500_000);
+        assert_eq!(
+            Resolution::from_option(6).timestamp(raw),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(Resolution::from_option(9).timestamp(raw), raw);
+        assert_eq!(
+            Resolution::from_option(0x80 | 10).timestamp(Duration::from_nanos(3072)),
+            Duration::from_secs(3)
+        );
+    }
+
+    #[test]
+    fn unsupported_link_type() {
+        let header = PcapHeader {
+            datalink: DataLink::ETHERNET,
+            ..PcapHeader::default()
+        };
+        let writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+        assert!(matches!(
+            read(&writer.into_writer()),
+            Err(Error::UnsupportedLinkType(DataLink::ETHERNET))
+        ));
+    }
+}
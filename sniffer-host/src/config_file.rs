@@ -0,0 +1,45 @@
+//! Location of the configuration file shared by the extcap and the tool
+//!
+//! The file is read from the path given on the command line, the path in the
+//! `IEEE802154_SNIFFER_CONFIG` environment variable or the per-user default
+//! `ieee802154-sniffer/config.toml` in the user configuration directory. A missing per-user
+//! file is the same as an empty file. Each program parses the sections it uses.
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Path of the configuration file, instead of the per-user default
+pub const CONFIG_VARIABLE: &str = "IEEE802154_SNIFFER_CONFIG";
+
+/// Read the configuration file, `path` overrides the environment and per-user default
+///
+/// Returns the path and contents of the file, or `None` when there is no file to read. The
+/// error has the path of the file that could not be read.
+pub fn read(path: Option<&Path>) -> Result<Option<(PathBuf, String)>, (PathBuf, io::Error)> {
+    let path = path
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os(CONFIG_VARIABLE).map(PathBuf::from));
+    let (path, required) = match path {
+        Some(path) => (path, true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(None),
+        },
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(Some((path, text))),
+        Err(error) if !required && error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err((path, error)),
+    }
+}
+
+/// Per-user configuration file, in `$XDG_CONFIG_HOME`, `%APPDATA%` or `~/.config`
+fn default_path() -> Option<PathBuf> {
+    let variable = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let directory = variable("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| variable("APPDATA").map(PathBuf::from))
+        .or_else(|| variable("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(directory.join("ieee802154-sniffer").join("config.toml"))
+}
@@ -0,0 +1,70 @@
+//! Splitting received bytes into wire format packets, shared by the blocking and async devices
+use crate::{device::Statistics, Error};
+use ieee802154_sniffer_wire_format as wire_format;
+
+const BUFFER_SIZE: usize = 4096;
+
+/// Buffer of received bytes decoded into packets at each zero end marker
+pub(crate) struct Decoder {
+    buffer: [u8; BUFFER_SIZE],
+    offset: usize,
+    statistics: Statistics,
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Self {
+        Decoder {
+            buffer: [0u8; BUFFER_SIZE],
+            offset: 0,
+            statistics: Statistics::default(),
+        }
+    }
+
+    pub(crate) fn statistics(&self) -> Statistics {
+        self.statistics
+    }
+
+    /// Free part of the buffer for the next read
+    ///
+    /// A full buffer without an end marker can never hold a valid packet, it is discarded and
+    /// counted as corrupted.
+    pub(crate) fn spare(&mut self) -> &mut [u8] {
+        if self.offset == BUFFER_SIZE && !self.has_packet() {
+            self.statistics.corrupted += 1;
+            self.offset = 0;
+        }
+        &mut self.buffer[self.offset..]
+    }
+
+    /// Mark `length` bytes of [`Decoder::spare`] as received
+    pub(crate) fn fill(&mut self, length: usize) {
+        self.offset += length;
+    }
+
+    /// A complete packet has been received
+    pub(crate) fn has_packet(&self) -> bool {
+        self.buffer[..self.offset].contains(&0x00)
+    }
+
+    /// Decode the next complete packet, `None` if no complete packet has been received
+    pub(crate) fn decode(&mut self) -> Option<Result<wire_format::Packet, Error>> {
+        let frame_len = self.buffer[..self.offset].iter().position(|&b| b == 0x00)? + 1;
+        let mut work_buffer = [0u8; BUFFER_SIZE];
+        work_buffer[..frame_len].copy_from_slice(&self.buffer[..frame_len]);
+        let result = match wire_format::Packet::decode(&mut work_buffer[..frame_len]) {
+            Ok((packet, _)) => {
+                if let wire_format::Packet::CaptureFrame(_) = packet {
+                    self.statistics.frames += 1;
+                }
+                Ok(packet)
+            }
+            Err(e) => {
+                self.statistics.corrupted += 1;
+                Err(e.into())
+            }
+        };
+        self.buffer.copy_within(frame_len..self.offset, 0);
+        self.offset -= frame_len;
+        Some(result)
+    }
+}
@@ -0,0 +1,365 @@
+//! Sniffer device speaking the wire format
+use crate::{
+    decoder::Decoder,
+    transport::{self, Transport},
+    Error,
+};
+use ieee802154_sniffer_wire_format as wire_format;
+use std::time::{Duration, Instant};
+
+/// Longest wait for a reply of a device created on an open transport, reads time out sooner
+/// but a device that keeps sending other packets would be waited for forever
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Counters of the data received from a device
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Statistics {
+    /// Captured frames received
+    pub frames: u64,
+    /// Packets that could not be decoded, each is a lost frame
+    pub corrupted: u64,
+    /// Times a lost device came back, only counted by a [`crate::Session`]
+    pub reconnects: u64,
+}
+
+/// Sniffer device speaking the wire format over a transport
+pub struct Device<T: Transport = Box<dyn Transport>> {
+    transport: T,
+    decoder: Decoder,
+    transmit_id: u32,
+    reply_timeout: Duration,
+}
+
+impl Device {
+    /// Open a device by URI, see [`transport::open`]
+    ///
+    /// `timeout` is the read timeout and the longest wait for a reply.
+    pub fn open(uri: &str, timeout: Duration) -> Result<Self, Error> {
+        Ok(Self::with_reply_timeout(
+            transport::open(uri, timeout)?,
+            timeout,
+        ))
+    }
+}
+
+impl<T: Transport> Device<T> {
+    /// Create a device on an open transport, discarding any pending input
+    pub fn new(transport: T) -> Self {
+        Self::with_reply_timeout(transport, REPLY_TIMEOUT)
+    }
+
+    fn with_reply_timeout(mut transport: T, reply_timeout: Duration) -> Self {
+        // A device left capturing never stops sending, stop discarding at some point
+        let deadline = Instant::now() + reply_timeout;
+        while let Ok(len) = transport.read(&mut [0u8; 128]) {
+            if len == 0 || Instant::now() >= deadline {
+                break;
+            }
+        }
+        Device {
+            transport,
+            decoder: Decoder::new(),
+            transmit_id: 0,
+            reply_timeout,
+        }
+    }
+
+    /// Counters of the data received so far
+    pub fn statistics(&self) -> Statistics {
+        self.decoder.statistics()
+    }
+
+    fn write_packet(&mut self, packet: &wire_format::Packet) -> Result<(), Error> {
+        let mut buffer = [0u8; 512];
+        let payload = packet.encode(&mut buffer)?;
+        self.transport.write_all(payload)?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<(), Error> {
+        let bytes = self.transport.read(self.decoder.spare())?;
+        if bytes == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        self.decoder.fill(bytes);
+        Ok(())
+    }
+
+    fn read_packet(&mut self) -> Result<Option<wire_format::Packet>, Error> {
+        if !self.decoder.has_packet() {
+            self.read()?;
+        }
+        self.decoder.decode().transpose()
+    }
+
+    /// Send a probe without waiting for the reply
+    pub fn send_probe(&mut self) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::Probe(wire_format::PROBE_HOST))
+    }
+
+    /// Read packets until `reply` accepts one, fails with a timeout error when no packet was
+    /// accepted within the reply timeout
+    fn wait_for<R>(
+        &mut self,
+        mut reply: impl FnMut(wire_format::Packet) -> Option<R>,
+    ) -> Result<R, Error> {
+        let deadline = Instant::now() + self.reply_timeout;
+        while Instant::now() < deadline {
+            if let Some(result) = self.read_packet()?.and_then(&mut reply) {
+                return Ok(result);
+            }
+        }
+        Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into())
+    }
+
+    /// Check that the device is a sniffer, waits for the probe reply until the read or reply
+    /// timeout
+    pub fn probe(&mut self) -> Result<(), Error> {
+        self.send_probe()?;
+        self.wait_for(|packet| match packet {
+            wire_format::Packet::Probe(_value) => Some(()),
+            _ => None,
+        })
+    }
+
+    /// Set the IEEE 802.15.4 channel, 11 to 26
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::Channel(channel))
+    }
+
+    /// Set the transmit power in dBm
+    pub fn set_power(&mut self, power: i32) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::Power(power))
+    }
+
+    /// Start sending captured frames
+    pub fn start_capture(&mut self) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::CaptureStart)
+    }
+
+    /// Stop sending captured frames
+    pub fn stop_capture(&mut self) -> Result<(), Error> {
+        self.write_packet(&wire_format::Packet::CaptureStop)
+    }
+
+    /// Transmit a frame without FCS, waits for the result until the read or reply timeout
+    ///
+    /// Captured frames received while waiting are dropped.
+    pub fn transmit(
+        &mut self,
+        payload: wire_format::Payload,
+    ) -> Result<wire_format::TransmitStatus, Error> {
+        self.transmit_id = self.transmit_id.wrapping_add(1);
+        let id = self.transmit_id;
+        self.write_packet(&wire_format::Packet::Transmit(wire_format::Transmit {
+            id,
+            payload,
+        }))?;
+        self.wait_for(|packet| match packet {
+            wire_format::Packet::TransmitResult(result) if result.id == id => Some(result.status),
+            _ => None,
+        })
+    }
+
+    /// Receive a packet, `Ok(None)` if no complete packet has arrived yet
+    ///
+    /// Fails with a timeout error when nothing was received within the read timeout and with
+    /// [`Error::Wire`] for a packet that could not be decoded.
+    pub fn receive(&mut self) -> Result<Option<wire_format::Packet>, Error> {
+        self.read_packet()
+    }
+
+    /// Iterate over captured frames
+    ///
+    /// Read timeouts, corrupted packets and other packets are skipped, so the iterator blocks
+    /// until a frame arrives. It ends after yielding any other error.
+    pub fn frames(&mut self) -> Frames<'_, T> {
+        Frames {
+            device: self,
+            failed: false,
+        }
+    }
+}
+
+/// Iterator over captured frames, see [`Device::frames`]
+pub struct Frames<'a, T: Transport> {
+    device: &'a mut Device<T>,
+    failed: bool,
+}
+
+impl<T: Transport> Iterator for Frames<'_, T> {
+    type Item = Result<wire_format::Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            match self.device.receive() {
+                Ok(Some(wire_format::Packet::CaptureFrame(frame))) => return Some(Ok(frame)),
+                Ok(_) | Err(Error::Wire(_)) => (),
+                Err(Error::Io(ref error)) if transport::is_transient(error) => (),
+                Err(error) => {
+                    self.failed = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::MockTransport;
+
+    fn encode(packet: &wire_format::Packet) -> Vec<u8> {
+        let mut buffer = [0u8; 512];
+        packet.encode(&mut buffer).unwrap().to_vec()
+    }
+
+    #[test]
+    fn probe() {
+        let transport = MockTransport::default();
+        let mut device = Device::new(transport.clone());
+        transport.push_rx(&encode(&wire_format::Packet::Probe(
+            wire_format::PROBE_DEVICE,
+        )));
+        device.probe().unwrap();
+        assert_eq!(
+            transport.take_tx(),
+            encode(&wire_format::Packet::Probe(wire_format::PROBE_HOST))
+        );
+    }
+
+    #[test]
+    fn probe_without_reply() {
+        let mut device = Device::new(MockTransport::default());
+        assert!(device.probe().is_err());
+    }
+
+    #[test]
+    fn probe_while_capturing() {
+        let transport = MockTransport::default();
+        let frame = wire_format::Packet::CaptureFrame(wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        });
+        transport.stream_rx(&encode(&frame));
+        let started = Instant::now();
+        let mut device = Device::with_reply_timeout(transport, Duration::from_millis(50));
+        assert!(matches!(
+            device.probe(),
+            Err(Error::Io(error)) if error.kind() == std::io::ErrorKind::TimedOut
+        ));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn configure() {
+        let transport = MockTransport::default();
+        let mut device = Device::new(transport.clone());
+        device.set_channel(15).unwrap();
+        device.start_capture().unwrap();
+        device.stop_capture().unwrap();
+        let mut expected = encode(&wire_format::Packet::Channel(15));
+        expected.extend(encode(&wire_format::Packet::CaptureStart));
+        expected.extend(encode(&wire_format::Packet::CaptureStop));
+        assert_eq!(transport.take_tx(), expected);
+    }
+
+    #[test]
+    fn transmit() {
+        let transport = MockTransport::default();
+        let mut device = Device::new(transport.clone());
+        let result = |id, status| {
+            encode(&wire_format::Packet::TransmitResult(
+                wire_format::TransmitResult { id, status },
+            ))
+        };
+        // A late result of an earlier request is skipped
+        let mut data = result(0, wire_format::TransmitStatus::Failed);
+        data.extend(result(1, wire_format::TransmitStatus::ChannelBusy));
+        transport.push_rx(&data);
+        let payload = wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap();
+        assert_eq!(
+            device.transmit(payload.clone()).unwrap(),
+            wire_format::TransmitStatus::ChannelBusy
+        );
+        assert_eq!(
+            transport.take_tx(),
+            encode(&wire_format::Packet::Transmit(wire_format::Transmit {
+                id: 1,
+                payload: payload.clone(),
+            }))
+        );
+        assert!(matches!(device.transmit(payload), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn receive_frames() {
+        let transport = MockTransport::default();
+        let mut device = Device::new(transport.clone());
+        let frame = wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: Some(-50_000),
+            link_quality_index: Some(200),
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        };
+        let packet = wire_format::Packet::CaptureFrame(frame);
+        let mut data = encode(&packet);
+        data.extend(encode(&packet));
+        transport.push_rx(&data);
+        assert_eq!(device.receive().unwrap(), Some(packet.clone()));
+        assert_eq!(device.receive().unwrap(), Some(packet));
+        assert!(matches!(device.receive(), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn receive_corrupted() {
+        let transport = MockTransport::default();
+        let mut device = Device::new(transport.clone());
+        let mut data = vec![0x05, 0xff, 0xff, 0x00];
+        data.extend(encode(&wire_format::Packet::CaptureStop));
+        transport.push_rx(&data);
+        assert!(matches!(device.receive(), Err(Error::Wire(_))));
+        assert_eq!(
+            device.receive().unwrap(),
+            Some(wire_format::Packet::CaptureStop)
+        );
+        assert_eq!(
+            device.statistics(),
+            Statistics {
+                frames: 0,
+                corrupted: 1,
+                reconnects: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn iterate_frames() {
+        let transport = MockTransport::default();
+        let mut device = Device::new(transport.clone());
+        let frame = |sequence| wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, sequence]).unwrap(),
+        };
+        let mut data = encode(&wire_format::Packet::CaptureFrame(frame(1)));
+        data.extend([0x05, 0xff, 0xff, 0x00]);
+        data.extend(encode(&wire_format::Packet::Probe(
+            wire_format::PROBE_DEVICE,
+        )));
+        data.extend(encode(&wire_format::Packet::CaptureFrame(frame(2))));
+        transport.push_rx(&data);
+        let mut frames = device.frames();
+        assert_eq!(frames.next().unwrap().unwrap(), frame(1));
+        assert_eq!(frames.next().unwrap().unwrap(), frame(2));
+        transport.disconnect();
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+        assert_eq!(device.statistics().frames, 2);
+    }
+}
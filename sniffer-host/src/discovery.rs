@@ -0,0 +1,358 @@
+//! Finding sniffers
+//!
+//! Serial ports with a known USB identifier are probed by default, a [`Config`] adds more
+//! identifiers and ports or selects every port.
+use crate::{device::Device, transport, Error};
+use std::{str::FromStr, time::Duration};
+
+/// NXP LPC-Link2 / micro:bit CMSIS-DAP interface
+pub const NXP_VID: u16 = 0x0d28;
+pub const NXP_CMSIS_DAP_PID: u16 = 0x0204;
+
+/// Silicon Labs CP210x USB to UART bridge
+pub const SILICON_LABS_VID: u16 = 0x10c4;
+pub const SILICON_LABS_UART_PID: u16 = 0xea60;
+
+/// Espressif USB-Serial-JTAG, the native USB port of the ESP32-C6
+pub const ESPRESSIF_VID: u16 = 0x303a;
+pub const ESPRESSIF_USB_SERIAL_JTAG_PID: u16 = 0x1001;
+
+/// USB vendor and product identifiers of the serial ports sniffers are attached through
+pub const KNOWN_USB_IDS: [(u16, u16); 3] = [
+    (NXP_VID, NXP_CMSIS_DAP_PID),
+    (SILICON_LABS_VID, SILICON_LABS_UART_PID),
+    (ESPRESSIF_VID, ESPRESSIF_USB_SERIAL_JTAG_PID),
+];
+
+/// USB vendor and product identifier, parsed from hexadecimal `vid:pid`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+impl FromStr for UsbId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid USB identifier {}, expected vid:pid", s);
+        let (vid, pid) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let parse = |value: &str| {
+            let value = value.trim();
+            let value = value.strip_prefix("0x").unwrap_or(value);
+            u16::from_str_radix(value, 16).map_err(|_| invalid())
+        };
+        Ok(UsbId {
+            vid: parse(vid)?,
+            pid: parse(pid)?,
+        })
+    }
+}
+
+/// Which serial ports are candidates for sniffers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    /// USB identifiers in addition to [`KNOWN_USB_IDS`]
+    pub usb_ids: Vec<UsbId>,
+    /// Port names, or patterns where `*` matches any characters and `?` a single character
+    ///
+    /// A name without wildcards is a candidate even if the port is not enumerated, for
+    /// example a pseudo terminal.
+    pub ports: Vec<String>,
+    /// Every serial port is a candidate
+    pub probe_all: bool,
+}
+
+impl Config {
+    fn is_candidate(&self, port: &serialport::SerialPortInfo) -> bool {
+        let usb_id = match &port.port_type {
+            serialport::SerialPortType::UsbPort(usb_port) => Some((usb_port.vid, usb_port.pid)),
+            _ => None,
+        };
+        self.probe_all
+            || usb_id.is_some_and(|(vid, pid)| {
+                KNOWN_USB_IDS.contains(&(vid, pid)) || self.usb_ids.contains(&UsbId { vid, pid })
+            })
+            || self
+                .ports
+                .iter()
+                .any(|pattern| matches_pattern(pattern, &port.port_name))
+    }
+
+    /// Serial ports that may have a sniffer attached, without probing them
+    pub fn candidates(&self) -> Result<Vec<DeviceInfo>, Error> {
+        self.select(serialport::available_ports()?)
+    }
+
+    fn select(&self, ports: Vec<serialport::SerialPortInfo>) -> Result<Vec<DeviceInfo>, Error> {
+        let mut devices: Vec<DeviceInfo> = ports
+            .into_iter()
+            .filter(|port| self.is_candidate(port))
+            .map(DeviceInfo::from)
+            .collect();
+        for name in self.ports.iter().filter(|name| !name.contains(['*', '?'])) {
+            if !devices.iter().any(|device| &device.uri == name) {
+                devices.push(DeviceInfo {
+                    uri: name.clone(),
+                    serial_number: None,
+                });
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Sniffers attached to this machine, candidates replying to a probe within `timeout`
+    pub fn discover(&self, timeout: Duration) -> Result<Vec<DeviceInfo>, Error> {
+        Ok(probe_all(self.candidates()?, timeout)
+            .into_iter()
+            .filter_map(|(device, result)| result.ok().map(|()| device))
+            .collect())
+    }
+}
+
+/// Match a name against a pattern where `*` matches any characters and `?` a single character
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position in the pattern of the last `*` and the position in the name it was tried at
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, position)) => {
+                    backtrack = Some((star, position + 1));
+                    p = star + 1;
+                    n = position + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// A sniffer, or a possible sniffer before it has been probed
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceInfo {
+    /// URI to open the device with, see [`transport::open`]
+    pub uri: String,
+    /// USB serial number, if the device is attached through USB
+    pub serial_number: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Sniffer shared over TCP, `address` is on the form `host:port`
+    pub fn remote(address: &str) -> Self {
+        Self {
+            uri: format!("{}{}", transport::TCP_PREFIX, address),
+            serial_number: None,
+        }
+    }
+
+    /// Check that the device replies as a sniffer within `timeout`
+    pub fn probe(&self, timeout: Duration) -> Result<(), Error> {
+        Device::open(&self.uri, timeout)?.probe()
+    }
+
+    /// Check that the device replies as a sniffer within `timeout`, a serial port is opened
+    /// with `baud_rate`
+    pub fn probe_with_baud_rate(&self, baud_rate: u32, timeout: Duration) -> Result<(), Error> {
+        Device::new(transport::open_with_baud_rate(
+            &self.uri, baud_rate, timeout,
+        )?)
+        .probe()
+    }
+}
+
+impl From<serialport::SerialPortInfo> for DeviceInfo {
+    fn from(port: serialport::SerialPortInfo) -> Self {
+        let serial_number = match port.port_type {
+            serialport::SerialPortType::UsbPort(usb_port) => usb_port.serial_number,
+            _ => None,
+        };
+        DeviceInfo {
+            uri: port.port_name,
+            serial_number,
+        }
+    }
+}
+
+/// Serial ports with a known USB identifier, without probing them
+pub fn candidates() -> Result<Vec<DeviceInfo>, Error> {
+    Config::default().candidates()
+}
+
+/// Sniffers attached through serial ports with a known USB identifier
+pub fn discover(timeout: Duration) -> Result<Vec<DeviceInfo>, Error> {
+    Config::default().discover(timeout)
+}
+
+/// Probe the devices in parallel, each result is in the order of the devices
+///
+/// Every probe is given `timeout`, so probing many devices takes about as long as one.
+pub fn probe_all(
+    devices: Vec<DeviceInfo>,
+    timeout: Duration,
+) -> Vec<(DeviceInfo, Result<(), Error>)> {
+    probe_all_with(devices, |device| device.probe(timeout))
+}
+
+/// Probe the devices in parallel with `probe`, each result is in the order of the devices
+pub fn probe_all_with<F>(devices: Vec<DeviceInfo>, probe: F) -> Vec<(DeviceInfo, Result<(), Error>)>
+where
+    F: Fn(&DeviceInfo) -> Result<(), Error> + Sync,
+{
+    let probe = &probe;
+    std::thread::scope(|scope| {
+        let probes: Vec<_> = devices
+            .into_iter()
+            .map(|device| {
+                scope.spawn(move || {
+                    let result = probe(&device);
+                    (device, result)
+                })
+            })
+            .collect();
+        probes
+            .into_iter()
+            .map(|probe| probe.join().expect("Probe thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+    fn usb_port(name: &str, vid: u16, pid: u16) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: String::from(name),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: Some(String::from("0123")),
+                manufacturer: None,
+                product: None,
+            }),
+        }
+    }
+
+    fn ports() -> Vec<SerialPortInfo> {
+        vec![
+            usb_port("/dev/ttyACM0", NXP_VID, NXP_CMSIS_DAP_PID),
+            usb_port("/dev/ttyUSB0", SILICON_LABS_VID, SILICON_LABS_UART_PID),
+            usb_port("/dev/ttyUSB1", 0x0403, 0x6001),
+            usb_port("/dev/ttyACM1", ESPRESSIF_VID, ESPRESSIF_USB_SERIAL_JTAG_PID),
+            SerialPortInfo {
+                port_name: String::from("/dev/ttyS0"),
+                port_type: SerialPortType::Unknown,
+            },
+        ]
+    }
+
+    fn names(devices: Vec<DeviceInfo>) -> Vec<String> {
+        devices.into_iter().map(|device| device.uri).collect()
+    }
+
+    #[test]
+    fn known_usb_ports() {
+        let devices = Config::default().select(ports()).unwrap();
+        assert_eq!(
+            devices[0],
+            DeviceInfo {
+                uri: String::from("/dev/ttyACM0"),
+                serial_number: Some(String::from("0123")),
+            }
+        );
+        assert_eq!(
+            names(devices),
+            ["/dev/ttyACM0", "/dev/ttyUSB0", "/dev/ttyACM1"]
+        );
+    }
+
+    #[test]
+    fn configured_ports() {
+        let config = Config {
+            usb_ids: vec!["0403:6001".parse().unwrap()],
+            ports: vec![String::from("/dev/ttyS*"), String::from("/dev/pts/3")],
+            probe_all: false,
+        };
+        assert_eq!(
+            names(config.select(ports()).unwrap()),
+            [
+                "/dev/ttyACM0",
+                "/dev/ttyUSB0",
+                "/dev/ttyUSB1",
+                "/dev/ttyACM1",
+                "/dev/ttyS0",
+                "/dev/pts/3"
+            ]
+        );
+        let config = Config {
+            probe_all: true,
+            ..Config::default()
+        };
+        assert_eq!(config.select(ports()).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn usb_id() {
+        assert_eq!(
+            "0x1a86:7523".parse(),
+            Ok(UsbId {
+                vid: 0x1a86,
+                pid: 0x7523
+            })
+        );
+        assert_eq!(
+            "303A:1001".parse(),
+            Ok(UsbId {
+                vid: ESPRESSIF_VID,
+                pid: ESPRESSIF_USB_SERIAL_JTAG_PID
+            })
+        );
+        assert!("1a86".parse::<UsbId>().is_err());
+        assert!("1a86:75230".parse::<UsbId>().is_err());
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches_pattern("/dev/ttyUSB*", "/dev/ttyUSB10"));
+        assert!(matches_pattern("/dev/tty*", "/dev/tty"));
+        assert!(matches_pattern("COM?", "COM3"));
+        assert!(matches_pattern("/dev/*ACM*", "/dev/ttyACM0"));
+        assert!(!matches_pattern("COM?", "COM12"));
+        assert!(!matches_pattern("/dev/ttyUSB*", "/dev/ttyACM0"));
+    }
+
+    #[test]
+    fn parallel_probes() {
+        let devices: Vec<_> = (0..8)
+            .map(|n| DeviceInfo {
+                uri: format!("/nonexistent/tty{}", n),
+                serial_number: None,
+            })
+            .collect();
+        let results = probe_all(devices.clone(), Duration::from_millis(100));
+        assert_eq!(
+            results.iter().map(|(device, _)| device).collect::<Vec<_>>(),
+            devices.iter().collect::<Vec<_>>()
+        );
+        assert!(results.iter().all(|(_, result)| result.is_err()));
+    }
+
+    #[test]
+    fn remote() {
+        assert_eq!(DeviceInfo::remote("lab:4000").uri, "tcp://lab:4000");
+    }
+}
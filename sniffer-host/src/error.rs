@@ -0,0 +1,88 @@
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{convert::From, fmt};
+
+/// Errors talking to a sniffer or writing its output
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Wire(wire_format::Error),
+    SerialPort(serialport::Error),
+    Pcap(pcap_file::PcapError),
+    /// A capture file with a link type that is not IEEE 802.15.4
+    UnsupportedLinkType(pcap_file::DataLink),
+}
+
+impl Error {
+    /// The reader of the output went away, for example Wireshark closing its FIFO
+    pub fn is_broken_pipe(&self) -> bool {
+        let error = match self {
+            Error::Io(error) => error,
+            Error::Pcap(pcap_file::PcapError::IoError(error)) => error,
+            _ => return false,
+        };
+        error.kind() == std::io::ErrorKind::BrokenPipe
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error, {}", error),
+            Error::Wire(error) => write!(f, "{}", error),
+            Error::SerialPort(error) => write!(f, "Serial port error, {}", error),
+            Error::Pcap(error) => write!(f, "Capture file error, {}", error),
+            Error::UnsupportedLinkType(datalink) => {
+                write!(f, "Unsupported link type {:?}", datalink)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Wire(error) => Some(error),
+            Error::SerialPort(error) => Some(error),
+            Error::Pcap(error) => Some(error),
+            Error::UnsupportedLinkType(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<wire_format::Error> for Error {
+    fn from(error: wire_format::Error) -> Self {
+        Error::Wire(error)
+    }
+}
+
+impl From<serialport::Error> for Error {
+    fn from(error: serialport::Error) -> Self {
+        Error::SerialPort(error)
+    }
+}
+
+impl From<pcap_file::PcapError> for Error {
+    fn from(error: pcap_file::PcapError) -> Self {
+        Error::Pcap(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_pipe() {
+        let error = || std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+        assert!(Error::Io(error()).is_broken_pipe());
+        assert!(Error::Pcap(pcap_file::PcapError::IoError(error())).is_broken_pipe());
+        assert!(!Error::Io(std::io::ErrorKind::NotFound.into()).is_broken_pipe());
+    }
+}
@@ -0,0 +1,52 @@
+//! IEEE 802.15.4 frame check sequence
+//!
+//! The sniffer firmware strips the FCS of received frames, link types and protocols that carry
+//! it have it computed by the host.
+
+/// Length of the 16-bit frame check sequence
+pub const LENGTH: usize = 2;
+
+/// Compute the 16-bit FCS (ITU-T CRC-16, also known as CRC-16/KERMIT) of a frame
+pub fn compute(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, octet| {
+        (0..8).fold(crc ^ u16::from(*octet), |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Check the FCS at the end of a frame, `false` for frames too short to have one
+pub fn check(data: &[u8]) -> bool {
+    match data.len().checked_sub(LENGTH) {
+        Some(length) => compute(&data[..length]).to_le_bytes() == data[length..],
+        None => false,
+    }
+}
+
+/// Append the FCS of `data[..length]` to the data, returns the new length
+pub fn append(data: &mut [u8], length: usize) -> usize {
+    let fcs = compute(&data[..length]);
+    data[length..length + LENGTH].copy_from_slice(&fcs.to_le_bytes());
+    length + LENGTH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(compute(b"123456789"), 0x2189);
+        assert_eq!(compute(&[]), 0x0000);
+        let mut frame = [0x02, 0x00, 0x04, 0x00, 0x00];
+        assert_eq!(append(&mut frame, 3), 5);
+        assert_eq!(frame, [0x02, 0x00, 0x04, 0x9c, 0xf3]);
+        assert!(check(&frame));
+        assert!(!check(&[0x02, 0x00, 0x04, 0x9c, 0xf4]));
+        assert!(!check(&[0x02]));
+    }
+}
@@ -0,0 +1,76 @@
+//! Host library for IEEE 802.15.4 sniffers
+//!
+//! Finds sniffers, configures them and receives the captured frames, together with the
+//! encodings used to pass the frames on: IEEE 802.15.4 TAP, pcapng and ZEP.
+//!
+//! ```no_run
+//! use ieee802154_sniffer_host::{discovery, Device};
+//! use std::time::Duration;
+//!
+//! # fn main() -> Result<(), ieee802154_sniffer_host::Error> {
+//! let sniffer = discovery::discover(Duration::from_millis(500))?
+//!     .into_iter()
+//!     .next()
+//!     .expect("No sniffer found");
+//! let mut device = Device::open(&sniffer.uri, Duration::from_millis(100))?;
+//! device.set_channel(15)?;
+//! device.start_capture()?;
+//! for frame in device.frames().take(10) {
+//!     let frame = frame?;
+//!     println!("{:02x?}", &frame.payload[..]);
+//! }
+//! device.stop_capture()?;
+//! println!("{:?}", device.statistics());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A [`Session`] wraps a device for long running captures, it reopens and reconfigures the
+//! device when it is unplugged or reset.
+//!
+//! Secured frames are decrypted and authenticated with a [`security::KeyTable`], and
+//! 6LoWPAN datagrams are decompressed to IPv6 packets with a [`sixlowpan::Decompressor`].
+//!
+//! Acknowledgements are paired with the frames they acknowledge and retransmissions are
+//! counted per link with an [`acknowledgement::Analyzer`]. The airtime, duty cycle and frame
+//! sizes of each channel are measured with an [`airtime::Utilisation`]. An
+//! [`anomaly::Detector`] reports replayed frame counters, unknown devices, PAN ID conflicts
+//! and bursts of beacon requests.
+//!
+//! Recorded captures are read with [`capture_file`] and can be transmitted again through a
+//! sniffer with [`replay`], frames are framed for each IEEE 802.15.4 link type with
+//! [`link_type::LinkType`].
+//!
+//! With the `tokio` feature, [`asynchronous::AsyncDevice`] provides the same device API for
+//! async code, with a `Stream` of captured frames and commands that time out. With the `mock`
+//! feature, `transport::mock::MockTransport` stands in for a sniffer in tests.
+pub mod acknowledgement;
+pub mod airtime;
+pub mod anomaly;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod capture_file;
+pub mod config_file;
+mod decoder;
+pub mod device;
+pub mod discovery;
+mod error;
+pub mod fcs;
+pub mod link_type;
+pub mod mac;
+pub mod pcapng;
+pub mod replay;
+pub mod rotation;
+pub mod security;
+pub mod session;
+pub mod sixlowpan;
+pub mod summary;
+pub mod tap;
+pub mod topology;
+pub mod transport;
+pub mod zep;
+
+pub use device::{Device, Statistics};
+pub use error::Error;
+pub use ieee802154_sniffer_wire_format as wire_format;
+pub use session::{Event, Session};
@@ -0,0 +1,156 @@
+//! IEEE 802.15.4 link types of pcap and pcapng captures
+//!
+//! Frames are framed for a link type with [`LinkType::encode`] and read back with
+//! [`LinkType::decode`], so captures can be converted between link types. The sniffer firmware
+//! strips the FCS, it is computed when the link type carries it. The TAP header carries the
+//! channel, RSSI and LQI, the other link types lose them.
+use crate::{fcs, tap, wire_format};
+use pcap_file::DataLink;
+
+/// Link type of captured IEEE 802.15.4 frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LinkType {
+    /// IEEE 802.15.4 TAP header with channel, RSSI and LQI, no FCS
+    Tap,
+    /// Frames with FCS
+    Fcs,
+    /// Frames without FCS
+    NoFcs,
+}
+
+impl LinkType {
+    /// All link types
+    pub const ALL: [LinkType; 3] = [LinkType::Tap, LinkType::Fcs, LinkType::NoFcs];
+
+    /// Lower case name, as used in configuration and on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            LinkType::Tap => "tap",
+            LinkType::Fcs => "fcs",
+            LinkType::NoFcs => "nofcs",
+        }
+    }
+
+    /// Link type by name, see [`LinkType::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|link_type| link_type.name() == name)
+    }
+
+    /// pcap link type
+    pub fn datalink(&self) -> DataLink {
+        match self {
+            LinkType::Tap => DataLink::IEEE802_15_4_TAP,
+            LinkType::Fcs => DataLink::IEEE802_15_4,
+            LinkType::NoFcs => DataLink::IEEE802_15_4_NOFCS,
+        }
+    }
+
+    /// Link type of a pcap link type, `None` for link types other than IEEE 802.15.4
+    pub fn from_datalink(datalink: DataLink) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|link_type| link_type.datalink() == datalink)
+    }
+
+    /// Frame a captured frame without FCS, returns the length of the encoded data
+    pub fn encode(&self, frame: &wire_format::Frame, buffer: &mut [u8; tap::BUFFER_SIZE]) -> usize {
+        let length = frame.payload.len();
+        match self {
+            LinkType::Tap => tap::encode(frame, buffer),
+            LinkType::Fcs => {
+                buffer[..length].copy_from_slice(&frame.payload);
+                fcs::append(buffer, length)
+            }
+            LinkType::NoFcs => {
+                buffer[..length].copy_from_slice(&frame.payload);
+                length
+            }
+        }
+    }
+
+    /// The link type carries an FCS and it does not match the frame
+    pub fn fcs_error(&self, data: &[u8]) -> bool {
+        match self {
+            LinkType::Fcs => !fcs::check(data),
+            LinkType::Tap | LinkType::NoFcs => false,
+        }
+    }
+
+    /// Strip the link-layer header and FCS, `None` for frames that do not fit a payload
+    ///
+    /// The channel is 0 and the signal fields are unset unless the link type carries them. The
+    /// FCS is stripped whether it matches or not, check it first with [`LinkType::fcs_error`].
+    pub fn decode(&self, data: &[u8]) -> Option<wire_format::Frame> {
+        let payload = match self {
+            LinkType::Tap => return tap::decode(data),
+            LinkType::Fcs => &data[..data.len().checked_sub(fcs::LENGTH)?],
+            LinkType::NoFcs => data,
+        };
+        Some(wire_format::Frame {
+            channel: 0,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(payload).ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let frame = wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: Some(-70_000),
+            link_quality_index: Some(100),
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        };
+        let mut buffer = [0u8; tap::BUFFER_SIZE];
+
+        let length = LinkType::Fcs.encode(&frame, &mut buffer);
+        assert_eq!(&buffer[..length], &[0x02, 0x00, 0x04, 0x9c, 0xf3]);
+        let length = LinkType::NoFcs.encode(&frame, &mut buffer);
+        assert_eq!(&buffer[..length], &[0x02, 0x00, 0x04]);
+        let length = LinkType::Tap.encode(&frame, &mut buffer);
+        assert_eq!(LinkType::Tap.decode(&buffer[..length]), Some(frame.clone()));
+
+        let without_metadata = wire_format::Frame {
+            channel: 0,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            ..frame
+        };
+        for link_type in [LinkType::Fcs, LinkType::NoFcs] {
+            let length = link_type.encode(&without_metadata, &mut buffer);
+            assert_eq!(
+                link_type.decode(&buffer[..length]),
+                Some(without_metadata.clone())
+            );
+        }
+        assert_eq!(LinkType::Fcs.decode(&[0x02]), None);
+    }
+
+    #[test]
+    fn fcs_errors() {
+        assert!(!LinkType::Fcs.fcs_error(&[0x02, 0x00, 0x04, 0x9c, 0xf3]));
+        assert!(LinkType::Fcs.fcs_error(&[0x02, 0x00, 0x05, 0x9c, 0xf3]));
+        assert!(!LinkType::NoFcs.fcs_error(&[0x02, 0x00, 0x05, 0x9c, 0xf3]));
+    }
+
+    #[test]
+    fn names() {
+        for link_type in LinkType::ALL {
+            assert_eq!(LinkType::from_name(link_type.name()), Some(link_type));
+            assert_eq!(
+                LinkType::from_datalink(link_type.datalink()),
+                Some(link_type)
+            );
+        }
+        assert_eq!(LinkType::from_name("ethernet"), None);
+        assert_eq!(LinkType::from_datalink(DataLink::ETHERNET), None);
+    }
+}
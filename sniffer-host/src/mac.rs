@@ -0,0 +1,366 @@
+//! IEEE 802.15.4 MAC header parsing
+//!
+//! Only the fields needed to classify and route frames are parsed, the frame control, the
+//! sequence number and the addressing fields. Frame versions 2003, 2006 and 2015 are
+//! supported.
+use core::fmt;
+
+/// MAC frame type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FrameType {
+    Beacon,
+    Data,
+    Acknowledgement,
+    Command,
+    Multipurpose,
+    Fragment,
+    Extended,
+    Reserved,
+}
+
+impl FrameType {
+    /// Lower case name, as used in configuration and exports
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameType::Beacon => "beacon",
+            FrameType::Data => "data",
+            FrameType::Acknowledgement => "ack",
+            FrameType::Command => "command",
+            FrameType::Multipurpose => "multipurpose",
+            FrameType::Fragment => "fragment",
+            FrameType::Extended => "extended",
+            FrameType::Reserved => "reserved",
+        }
+    }
+
+    /// All frame types, in frame type value order
+    pub const ALL: [FrameType; 8] = [
+        FrameType::Beacon,
+        FrameType::Data,
+        FrameType::Acknowledgement,
+        FrameType::Command,
+        FrameType::Multipurpose,
+        FrameType::Fragment,
+        FrameType::Extended,
+        FrameType::Reserved,
+    ];
+
+    /// Frame type by name, see [`FrameType::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|frame_type| frame_type.name() == name)
+    }
+}
+
+impl From<u16> for FrameType {
+    fn from(value: u16) -> Self {
+        match value & 0b111 {
+            0 => FrameType::Beacon,
+            1 => FrameType::Data,
+            2 => FrameType::Acknowledgement,
+            3 => FrameType::Command,
+            5 => FrameType::Multipurpose,
+            6 => FrameType::Fragment,
+            7 => FrameType::Extended,
+            _ => FrameType::Reserved,
+        }
+    }
+}
+
+/// Short or extended device address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Address {
+    Short(u16),
+    Extended(u64),
+}
+
+impl Address {
+    /// The broadcast short address
+    pub const BROADCAST: Address = Address::Short(0xffff);
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Short(address) => write!(f, "0x{:04x}", address),
+            Address::Extended(address) => {
+                let bytes = address.to_be_bytes();
+                for (n, byte) in bytes.iter().enumerate() {
+                    if n > 0 {
+                        write!(f, ":")?;
+                    }
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl core::str::FromStr for Address {
+    type Err = String;
+
+    /// Parse a short address as `0x1234` or an extended address as eight colon separated
+    /// hexadecimal octets, most significant first, as written by [`Address`]'s `Display`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid address \"{}\"", text);
+        if let Some(short) = text.strip_prefix("0x") {
+            return u16::from_str_radix(short, 16)
+                .map(Address::Short)
+                .map_err(|_| invalid());
+        }
+        let octets = text
+            .split(':')
+            .map(|octet| match octet.len() {
+                2 => u8::from_str_radix(octet, 16).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        let octets: [u8; 8] = octets.try_into().map_err(|_| invalid())?;
+        Ok(Address::Extended(u64::from_be_bytes(octets)))
+    }
+}
+
+/// Parsed MAC header
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub frame_type: FrameType,
+    pub security_enabled: bool,
+    pub frame_pending: bool,
+    pub ack_request: bool,
+    pub pan_id_compression: bool,
+    pub information_elements: bool,
+    /// Frame version, 0 for 2003, 1 for 2006 and 2 for 2015
+    pub version: u8,
+    /// Sequence number, absent when suppressed in a 2015 frame
+    pub sequence: Option<u8>,
+    pub destination_pan: Option<u16>,
+    pub destination: Option<Address>,
+    pub source_pan: Option<u16>,
+    pub source: Option<Address>,
+}
+
+const SECURITY_ENABLED: u16 = 1 << 3;
+const FRAME_PENDING: u16 = 1 << 4;
+const ACK_REQUEST: u16 = 1 << 5;
+const PAN_ID_COMPRESSION: u16 = 1 << 6;
+const SEQUENCE_SUPPRESSION: u16 = 1 << 8;
+const INFORMATION_ELEMENTS: u16 = 1 << 9;
+
+/// Header IE terminations, the payload follows HT1, HT2 ends the frame
+const HEADER_TERMINATION_1: u16 = 0x7e;
+const HEADER_TERMINATION_2: u16 = 0x7f;
+
+/// Addressing mode, the length of the address in octets or `None` for reserved modes
+fn address_length(mode: u16) -> Option<usize> {
+    match mode {
+        0 => Some(0),
+        2 => Some(2),
+        3 => Some(8),
+        _ => None,
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Option<&[u8]> {
+        let bytes = self.data.get(self.offset..self.offset + length)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn address(&mut self, length: usize) -> Option<Option<Address>> {
+        Some(match length {
+            2 => Some(Address::Short(self.u16()?)),
+            8 => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(self.take(8)?);
+                Some(Address::Extended(u64::from_le_bytes(bytes)))
+            }
+            _ => None,
+        })
+    }
+}
+
+impl Header {
+    /// Parse the header at the start of a frame without FCS, returns the header and its
+    /// length, or `None` if the frame is too short or uses reserved addressing modes
+    pub fn parse(data: &[u8]) -> Option<(Header, usize)> {
+        let mut reader = Reader { data, offset: 0 };
+        let control = reader.u16()?;
+        let frame_type = FrameType::from(control);
+        let version = ((control >> 12) & 0b11) as u8;
+        let pan_id_compression = control & PAN_ID_COMPRESSION != 0;
+        let destination_length = address_length((control >> 10) & 0b11)?;
+        let source_length = address_length((control >> 14) & 0b11)?;
+        let sequence = if version >= 2 && control & SEQUENCE_SUPPRESSION != 0 {
+            None
+        } else {
+            Some(reader.take(1)?[0])
+        };
+        let (destination_pan_present, source_pan_present) = if version < 2 {
+            (
+                destination_length != 0,
+                source_length != 0 && !pan_id_compression,
+            )
+        } else {
+            // IEEE 802.15.4-2015 table 7-2
+            match (destination_length, source_length) {
+                (0, 0) => (pan_id_compression, false),
+                (_, 0) => (!pan_id_compression, false),
+                (0, _) => (false, !pan_id_compression),
+                (8, 8) => (!pan_id_compression, false),
+                _ => (true, !pan_id_compression),
+            }
+        };
+        let destination_pan = match destination_pan_present {
+            true => Some(reader.u16()?),
+            false => None,
+        };
+        let destination = reader.address(destination_length)?;
+        let source_pan = match source_pan_present {
+            true => Some(reader.u16()?),
+            false => None,
+        };
+        let source = reader.address(source_length)?;
+        let header = Header {
+            frame_type,
+            security_enabled: control & SECURITY_ENABLED != 0,
+            frame_pending: control & FRAME_PENDING != 0,
+            ack_request: control & ACK_REQUEST != 0,
+            pan_id_compression,
+            information_elements: control & INFORMATION_ELEMENTS != 0,
+            version,
+            sequence,
+            destination_pan,
+            destination,
+            source_pan,
+            source,
+        };
+        Some((header, reader.offset))
+    }
+
+    /// PAN identifier of the source, the destination PAN when compressed
+    pub fn source_pan_or_destination(&self) -> Option<u16> {
+        self.source_pan.or(self.destination_pan)
+    }
+}
+
+/// Length of the header IEs at the start of `data`
+pub(crate) fn header_ies_length(data: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    while offset + 2 <= data.len() {
+        let descriptor = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        offset += 2 + usize::from(descriptor & 0x7f);
+        let element_id = (descriptor >> 7) & 0xff;
+        if element_id == HEADER_TERMINATION_1 || element_id == HEADER_TERMINATION_2 {
+            break;
+        }
+    }
+    (offset <= data.len()).then_some(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acknowledgement() {
+        let (header, length) = Header::parse(&[0x02, 0x00, 0x2a]).unwrap();
+        assert_eq!(header.frame_type, FrameType::Acknowledgement);
+        assert_eq!(header.sequence, Some(0x2a));
+        assert_eq!(header.destination, None);
+        assert_eq!(header.source, None);
+        assert_eq!(length, 3);
+    }
+
+    #[test]
+    fn data_short_addresses() {
+        // Data, ack request, PAN ID compression, short addresses, 2006
+        let data = [
+            0x61, 0x98, 0x07, 0x62, 0x1a, 0x00, 0x00, 0x34, 0x12, 0xde, 0xad,
+        ];
+        let (header, length) = Header::parse(&data).unwrap();
+        assert_eq!(header.frame_type, FrameType::Data);
+        assert!(header.ack_request);
+        assert!(header.pan_id_compression);
+        assert_eq!(header.version, 1);
+        assert_eq!(header.sequence, Some(7));
+        assert_eq!(header.destination_pan, Some(0x1a62));
+        assert_eq!(header.destination, Some(Address::Short(0x0000)));
+        assert_eq!(header.source_pan, None);
+        assert_eq!(header.source_pan_or_destination(), Some(0x1a62));
+        assert_eq!(header.source, Some(Address::Short(0x1234)));
+        assert_eq!(length, 9);
+    }
+
+    #[test]
+    fn beacon_extended_source() {
+        let data = [
+            0x00, 0xc0, 0x01, 0xcd, 0xab, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+        ];
+        let (header, length) = Header::parse(&data).unwrap();
+        assert_eq!(header.frame_type, FrameType::Beacon);
+        assert_eq!(header.destination_pan, None);
+        assert_eq!(header.source_pan, Some(0xabcd));
+        let source = header.source.unwrap();
+        assert_eq!(source, Address::Extended(0x0102030405060708));
+        assert_eq!(source.to_string(), "01:02:03:04:05:06:07:08");
+        assert_eq!(length, 13);
+    }
+
+    #[test]
+    fn version_2015() {
+        // Data, sequence number suppressed, PAN ID compression, both extended addresses
+        let mut data = vec![0x41, 0xed];
+        data.extend(1u64.to_le_bytes());
+        data.extend(2u64.to_le_bytes());
+        let (header, length) = Header::parse(&data).unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!(header.sequence, None);
+        assert_eq!(header.destination_pan, None);
+        assert_eq!(header.source_pan, None);
+        assert_eq!(header.destination, Some(Address::Extended(1)));
+        assert_eq!(header.source, Some(Address::Extended(2)));
+        assert_eq!(length, 18);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Header::parse(&[0x41]).is_none());
+        // Reserved destination addressing mode
+        assert!(Header::parse(&[0x01, 0x04, 0x00]).is_none());
+        // Truncated addresses
+        assert!(Header::parse(&[0x61, 0x88, 0x07, 0x62, 0x1a]).is_none());
+    }
+
+    #[test]
+    fn parse_addresses() {
+        assert_eq!("0x1a62".parse(), Ok(Address::Short(0x1a62)));
+        let extended: Address = "ac:de:48:00:00:00:00:01".parse().unwrap();
+        assert_eq!(extended, Address::Extended(0xacde480000000001));
+        assert_eq!(extended.to_string(), "ac:de:48:00:00:00:00:01");
+        assert!("ac:de:48".parse::<Address>().is_err());
+        assert!("1234".parse::<Address>().is_err());
+    }
+
+    #[test]
+    fn frame_type_names() {
+        assert_eq!(
+            FrameType::from_name("ack"),
+            Some(FrameType::Acknowledgement)
+        );
+        assert_eq!(FrameType::from_name("data").unwrap().name(), "data");
+        assert_eq!(FrameType::from_name("unknown"), None);
+    }
+}
@@ -0,0 +1,301 @@
+//! pcapng capture output
+//!
+//! Writes a section header, one interface description block per sniffer, enhanced packet
+//! blocks with nanosecond timestamps and, when finished, an interface statistics block per
+//! sniffer. The channel can change during a capture, so each packet comment records the
+//! channel the frame was received on.
+use crate::Error;
+use pcap_file::{
+    pcapng::{
+        blocks::{
+            enhanced_packet::{EnhancedPacketBlock, EnhancedPacketOption},
+            interface_description::{InterfaceDescriptionBlock, InterfaceDescriptionOption},
+            interface_statistics::{InterfaceStatisticsBlock, InterfaceStatisticsOption},
+        },
+        PcapNgWriter,
+    },
+    DataLink,
+};
+use std::{
+    borrow::Cow,
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Timestamp resolution, 10^-9 seconds
+const TIMESTAMP_RESOLUTION: u8 = 9;
+
+/// EPB flags, inbound direction
+const FLAG_INBOUND: u32 = 0b01;
+/// EPB flags, link-layer CRC error
+pub(crate) const FLAG_CRC_ERROR: u32 = 1 << 24;
+
+/// Description of a sniffer interface
+#[derive(Clone)]
+pub struct InterfaceDescription {
+    /// Interface name, the serial port
+    pub name: String,
+    /// IEEE 802.15.4 channel at the start of the capture, 0 when not known
+    pub channel: u8,
+    /// Device serial number, if known
+    pub serial_number: Option<String>,
+}
+
+/// Per-packet information written as EPB flags and comments
+#[derive(Default)]
+pub struct PacketAnnotation {
+    /// Channel the frame was received on, 0 when not known
+    pub channel: u8,
+    /// The frame check sequence did not match
+    pub fcs_error: bool,
+    /// Number of packets lost since the previous packet
+    pub dropped: u64,
+    /// The device was lost for this long before the packet
+    pub reconnected: Option<Duration>,
+    /// The frame was decrypted by the host, see [`crate::security`]
+    pub decrypted: bool,
+}
+
+#[derive(Default)]
+struct InterfaceStatistics {
+    received: u64,
+    dropped: u64,
+}
+
+/// pcapng writer for captured frames
+pub struct CaptureWriter<W: Write> {
+    writer: PcapNgWriter<W>,
+    start: Duration,
+    statistics: Vec<InterfaceStatistics>,
+}
+
+/// Current time since the Unix epoch
+pub fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a capture, writes the section header
+    pub fn new(writer: W) -> Result<Self, Error> {
+        Ok(Self {
+            writer: PcapNgWriter::new(writer)?,
+            start: now(),
+            statistics: Vec::new(),
+        })
+    }
+
+    /// Add a sniffer interface, returns the interface identifier
+    pub fn add_interface(
+        &mut self,
+        datalink: DataLink,
+        interface: &InterfaceDescription,
+    ) -> Result<u32, Error> {
+        let description = match interface.channel {
+            0 => String::from("IEEE 802.15.4 Sniffer"),
+            channel => format!("IEEE 802.15.4 Sniffer, channel {}", channel),
+        };
+        let mut options = vec![
+            InterfaceDescriptionOption::IfName(Cow::Owned(interface.name.clone())),
+            InterfaceDescriptionOption::IfDescription(Cow::Owned(description)),
+            InterfaceDescriptionOption::IfTsResol(TIMESTAMP_RESOLUTION),
+        ];
+        if let Some(serial_number) = &interface.serial_number {
+            options.push(InterfaceDescriptionOption::IfHardware(Cow::Owned(format!(
+                "Serial number {}",
+                serial_number
+            ))));
+        }
+        let block = InterfaceDescriptionBlock {
+            linktype: datalink,
+            snaplen: 0,
+            options,
+        };
+        self.writer.write_pcapng_block(block)?;
+        self.statistics.push(InterfaceStatistics::default());
+        Ok((self.statistics.len() - 1) as u32)
+    }
+
+    /// Write a packet received on the given interface
+    pub fn write_packet(
+        &mut self,
+        interface_id: u32,
+        timestamp: Duration,
+        data: &[u8],
+        annotation: &PacketAnnotation,
+    ) -> Result<(), Error> {
+        let mut flags = FLAG_INBOUND;
+        let mut comments = Vec::new();
+        let mut options = Vec::new();
+        if annotation.channel != 0 {
+            comments.push(format!("Channel {}", annotation.channel));
+        }
+        if annotation.fcs_error {
+            flags |= FLAG_CRC_ERROR;
+            comments.push(String::from("FCS bad"));
+        }
+        if annotation.dropped > 0 {
+            comments.push(format!(
+                "Gap detected, {} packet(s) lost",
+                annotation.dropped
+            ));
+            options.push(EnhancedPacketOption::DropCount(annotation.dropped));
+        }
+        if let Some(gap) = annotation.reconnected {
+            comments.push(format!(
+                "Device reconnected after {:.1} s",
+                gap.as_secs_f64()
+            ));
+        }
+        if annotation.decrypted {
+            comments.push(String::from("Decrypted"));
+        }
+        options.push(EnhancedPacketOption::Flags(flags));
+        if !comments.is_empty() {
+            options.push(EnhancedPacketOption::Comment(Cow::Owned(
+                comments.join(", "),
+            )));
+        }
+        let block = EnhancedPacketBlock {
+            interface_id,
+            timestamp,
+            original_len: data.len() as u32,
+            data: Cow::Borrowed(data),
+            options,
+        };
+        self.writer.write_pcapng_block(block)?;
+        self.writer.get_mut().flush()?;
+        if let Some(statistics) = self.statistics.get_mut(interface_id as usize) {
+            statistics.received += 1;
+            statistics.dropped += annotation.dropped;
+        }
+        Ok(())
+    }
+
+    /// The inner writer
+    pub fn get_ref(&self) -> &W {
+        self.writer.get_ref()
+    }
+
+    /// Write interface statistics blocks and return the inner writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        let start = self.start.as_nanos() as u64;
+        let end = now().as_nanos() as u64;
+        for (interface_id, statistics) in self.statistics.iter().enumerate() {
+            let block = InterfaceStatisticsBlock {
+                interface_id: interface_id as u32,
+                timestamp: end,
+                options: vec![
+                    InterfaceStatisticsOption::IsbStartTime(start),
+                    InterfaceStatisticsOption::IsbEndTime(end),
+                    InterfaceStatisticsOption::IsbIfRecv(statistics.received),
+                    InterfaceStatisticsOption::IsbIfDrop(statistics.dropped),
+                ],
+            };
+            self.writer.write_pcapng_block(block)?;
+        }
+        let mut writer = self.writer.into_inner();
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pcap_file::pcapng::{Block, PcapNgReader};
+
+    #[test]
+    fn write_and_read_back() {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        let interface = InterfaceDescription {
+            name: String::from("/dev/ttyACM0"),
+            channel: 15,
+            serial_number: Some(String::from("0123")),
+        };
+        let interface_id = writer
+            .add_interface(DataLink::IEEE802_15_4_TAP, &interface)
+            .unwrap();
+        let timestamp = Duration::new(1_700_000_000, 123_456_789);
+        writer
+            .write_packet(
+                interface_id,
+                timestamp,
+                &[1, 2, 3],
+                &PacketAnnotation::default(),
+            )
+            .unwrap();
+        let annotation = PacketAnnotation {
+            channel: 20,
+            fcs_error: true,
+            dropped: 2,
+            reconnected: Some(Duration::from_millis(2500)),
+            decrypted: true,
+        };
+        writer
+            .write_packet(interface_id, timestamp, &[4, 5], &annotation)
+            .unwrap();
+        let data = writer.finish().unwrap();
+
+        let mut reader = PcapNgReader::new(&data[..]).unwrap();
+        let mut blocks = Vec::new();
+        while let Some(block) = reader.next_block() {
+            blocks.push(block.unwrap().into_owned());
+        }
+        assert_eq!(blocks.len(), 4);
+        match &blocks[0] {
+            Block::InterfaceDescription(idb) => {
+                assert_eq!(idb.linktype, DataLink::IEEE802_15_4_TAP);
+                assert!(idb
+                    .options
+                    .contains(&InterfaceDescriptionOption::IfTsResol(9)));
+                assert!(idb
+                    .options
+                    .contains(&InterfaceDescriptionOption::IfName(Cow::Borrowed(
+                        "/dev/ttyACM0"
+                    ))));
+                assert!(idb
+                    .options
+                    .contains(&InterfaceDescriptionOption::IfDescription(Cow::Borrowed(
+                        "IEEE 802.15.4 Sniffer, channel 15"
+                    ))));
+            }
+            _ => panic!("Expected interface description block"),
+        }
+        match &blocks[1] {
+            Block::EnhancedPacket(epb) => {
+                assert_eq!(epb.timestamp, timestamp);
+                assert_eq!(&epb.data[..], &[1, 2, 3]);
+                assert_eq!(epb.options, vec![EnhancedPacketOption::Flags(FLAG_INBOUND)]);
+            }
+            _ => panic!("Expected enhanced packet block"),
+        }
+        match &blocks[2] {
+            Block::EnhancedPacket(epb) => {
+                assert!(epb.options.contains(&EnhancedPacketOption::DropCount(2)));
+                assert!(epb
+                    .options
+                    .contains(&EnhancedPacketOption::Flags(FLAG_INBOUND | FLAG_CRC_ERROR)));
+                assert!(epb
+                    .options
+                    .contains(&EnhancedPacketOption::Comment(Cow::Borrowed(
+                        "Channel 20, FCS bad, Gap detected, 2 packet(s) lost, Device reconnected \
+                         after 2.5 s, Decrypted"
+                    ))));
+            }
+            _ => panic!("Expected enhanced packet block"),
+        }
+        match &blocks[3] {
+            Block::InterfaceStatistics(isb) => {
+                assert!(isb
+                    .options
+                    .contains(&InterfaceStatisticsOption::IsbIfRecv(2)));
+                assert!(isb
+                    .options
+                    .contains(&InterfaceStatisticsOption::IsbIfDrop(2)));
+            }
+            _ => panic!("Expected interface statistics block"),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Transmitting recorded frames through a sniffer
+//!
+//! Frames read with [`crate::capture_file`] are sent with the timing of the capture, scaled by
+//! a speed factor. Each frame is acknowledged by the device with a
+//! [`wire_format::TransmitStatus`], frames longer than [`wire_format::MAX_TRANSMIT_LENGTH`]
+//! and frames recorded with a bad FCS are skipped.
+use crate::{capture_file::Record, transport::Transport, Device, Error};
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Longest sleep between checks of the stop flag
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Times at which recorded frames are due
+pub struct Schedule {
+    speed: f64,
+    start: Option<(Instant, Duration)>,
+}
+
+impl Schedule {
+    /// Schedule with the capture timing divided by `speed`, frames are sent back to back
+    /// when the speed is zero or negative
+    pub fn new(speed: f64) -> Self {
+        Schedule { speed, start: None }
+    }
+
+    /// Time at which a frame with `timestamp` is due, the first frame is due at `now`
+    ///
+    /// Frames recorded before the first frame are due at once.
+    pub fn due(&mut self, timestamp: Duration, now: Instant) -> Instant {
+        let (start, first) = *self.start.get_or_insert((now, timestamp));
+        if self.speed <= 0.0 {
+            return now;
+        }
+        let offset = timestamp.saturating_sub(first).as_secs_f64() / self.speed;
+        start + Duration::try_from_secs_f64(offset).unwrap_or(Duration::MAX / 2)
+    }
+}
+
+/// Counters of the transmit results
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub sent: u64,
+    pub channel_busy: u64,
+    pub failed: u64,
+    pub unsupported: u64,
+    /// Frames skipped for being too long to transmit
+    pub too_long: u64,
+    /// Frames skipped for having been recorded with a bad FCS
+    pub bad_fcs: u64,
+}
+
+impl Summary {
+    fn add(&mut self, status: wire_format::TransmitStatus) {
+        match status {
+            wire_format::TransmitStatus::Sent => self.sent += 1,
+            wire_format::TransmitStatus::ChannelBusy => self.channel_busy += 1,
+            wire_format::TransmitStatus::Failed => self.failed += 1,
+            wire_format::TransmitStatus::Unsupported => self.unsupported += 1,
+        }
+    }
+
+    /// Frames transmitted or attempted
+    pub fn total(&self) -> u64 {
+        self.sent + self.channel_busy + self.failed + self.unsupported
+    }
+}
+
+/// Transmit the frames at their scheduled times, until done or `stop` is set
+///
+/// `report` is called with the index of each frame and its transmit result, or `None` for a
+/// frame skipped for being too long or having a bad FCS. A device that does not answer within its read timeout
+/// ends the replay with a timeout error.
+pub fn replay<T: Transport>(
+    device: &mut Device<T>,
+    records: &[Record],
+    speed: f64,
+    stop: &AtomicBool,
+    mut report: impl FnMut(usize, &Record, Option<wire_format::TransmitStatus>),
+) -> Result<Summary, Error> {
+    let mut schedule = Schedule::new(speed);
+    let mut summary = Summary::default();
+    for (index, record) in records.iter().enumerate() {
+        let due = schedule.due(record.timestamp, Instant::now());
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return Ok(summary);
+            }
+            let remaining = due.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            std::thread::sleep(remaining.min(POLL_INTERVAL));
+        }
+        if record.fcs_error {
+            summary.bad_fcs += 1;
+            report(index, record, None);
+            continue;
+        }
+        if record.frame.payload.len() > wire_format::MAX_TRANSMIT_LENGTH {
+            summary.too_long += 1;
+            report(index, record, None);
+            continue;
+        }
+        let status = device.transmit(record.frame.payload.clone())?;
+        summary.add(status);
+        report(index, record, Some(status));
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::MockTransport;
+
+    fn record(milliseconds: u64, sequence: u8) -> Record {
+        Record {
+            timestamp: Duration::from_secs(1_700_000_000) + Duration::from_millis(milliseconds),
+            frame: wire_format::Frame {
+                channel: 0,
+                received_signal_strength_indicator: None,
+                link_quality_index: None,
+                payload: wire_format::Payload::from_slice(&[0x02, 0x00, sequence]).unwrap(),
+            },
+            device_timestamp: None,
+            fcs_error: false,
+        }
+    }
+
+    #[test]
+    fn schedule() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(2.0);
+        assert_eq!(schedule.due(record(1000, 0).timestamp, now), now);
+        assert_eq!(
+            schedule.due(record(2000, 1).timestamp, now + Duration::from_secs(5)),
+            now + Duration::from_millis(500)
+        );
+        // Out of order frames are due at once
+        assert_eq!(schedule.due(record(0, 2).timestamp, now), now);
+    }
+
+    #[test]
+    fn schedule_without_delay() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(0.0);
+        schedule.due(record(0, 0).timestamp, now);
+        let later = now + Duration::from_millis(1);
+        assert_eq!(schedule.due(record(60_000, 1).timestamp, later), later);
+    }
+
+    #[test]
+    fn replay_frames() {
+        let transport = MockTransport::default();
+        let mut device = Device::new(transport.clone());
+        let encode = |id, status| {
+            let mut buffer = [0u8; 64];
+            wire_format::Packet::TransmitResult(wire_format::TransmitResult { id, status })
+                .encode(&mut buffer)
+                .unwrap()
+                .to_vec()
+        };
+        let mut data = encode(1, wire_format::TransmitStatus::Sent);
+        data.extend(encode(2, wire_format::TransmitStatus::ChannelBusy));
+        transport.push_rx(&data);
+        let mut too_long = record(10, 3);
+        too_long.frame.payload = wire_format::Payload::from_slice(&[0; 126]).unwrap();
+        let mut corrupted = record(15, 4);
+        corrupted.fcs_error = true;
+        let records = [record(0, 1), too_long, corrupted, record(20, 2)];
+        let mut reported = Vec::new();
+        let start = Instant::now();
+        let summary = replay(
+            &mut device,
+            &records,
+            1.0,
+            &AtomicBool::new(false),
+            |index, _, status| reported.push((index, status)),
+        )
+        .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(
+            reported,
+            [
+                (0, Some(wire_format::TransmitStatus::Sent)),
+                (1, None),
+                (2, None),
+                (3, Some(wire_format::TransmitStatus::ChannelBusy))
+            ]
+        );
+        assert_eq!(summary.sent, 1);
+        assert_eq!(summary.channel_busy, 1);
+        assert_eq!(summary.too_long, 1);
+        assert_eq!(summary.bad_fcs, 1);
+        assert_eq!(summary.total(), 2);
+    }
+}
@@ -0,0 +1,459 @@
+//! Ring buffer of pcapng capture files and capture limits
+//!
+//! A [`RotatingWriter`] writes captured frames to a sequence of files in a directory,
+//! starting a new file when the current one reaches a size or an age and removing the
+//! oldest files so that only the last few are kept. Each file is a complete pcapng capture.
+//! [`Limits`] end a capture after a number of frames or a duration.
+use crate::{
+    pcapng::{CaptureWriter, InterfaceDescription, PacketAnnotation},
+    Error,
+};
+use pcap_file::DataLink;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// When to end a capture, unset limits never end it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// Stop after this many frames
+    pub frames: Option<u64>,
+    /// Stop after this time
+    pub duration: Option<Duration>,
+}
+
+impl Limits {
+    /// A limit is reached after `frames` frames and `elapsed` time
+    pub fn reached(&self, frames: u64, elapsed: Duration) -> bool {
+        self.frames.is_some_and(|limit| frames >= limit)
+            || self.duration.is_some_and(|limit| elapsed >= limit)
+    }
+}
+
+/// When to start a new file and how many files to keep
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rotation {
+    /// Start a new file when the current file has reached this size in bytes
+    pub file_size: Option<u64>,
+    /// Start a new file when the first frame of the current file is this old
+    pub file_duration: Option<Duration>,
+    /// Remove the oldest files when there are more files than this
+    pub max_files: Option<usize>,
+}
+
+/// Writer counting the bytes written
+struct Counter<W: Write> {
+    writer: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let length = self.writer.write(buf)?;
+        self.count += length as u64;
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+type FileWriter = CaptureWriter<Counter<BufWriter<File>>>;
+
+/// Capture written to rotating files
+pub struct RotatingWriter {
+    directory: PathBuf,
+    device: String,
+    datalink: DataLink,
+    interface: InterfaceDescription,
+    rotation: Rotation,
+    writer: Option<FileWriter>,
+    /// Timestamp of the first frame in the current file
+    file_start: Option<Duration>,
+    files: VecDeque<PathBuf>,
+    index: u32,
+}
+
+/// Device identifier usable in a file name, the serial number or the last part of the
+/// interface name
+fn file_name_device(interface: &InterfaceDescription) -> String {
+    let name = interface.serial_number.as_deref().unwrap_or_else(|| {
+        interface
+            .name
+            .rsplit(['/', '\\', ':'])
+            .find(|part| !part.is_empty())
+            .unwrap_or("sniffer")
+    });
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+            true => c,
+            false => '-',
+        })
+        .collect()
+}
+
+/// UTC date and time for a file name, `YYYYmmddTHHMMSSZ`
+fn file_name_time(timestamp: Duration) -> String {
+    let seconds = timestamp.as_secs();
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+    // Civil date from days since the epoch, from Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Files of earlier captures named with `prefix` in `directory`, with their index, oldest
+/// first
+fn existing_files(directory: &Path, prefix: &str) -> Result<Vec<(u32, PathBuf)>, Error> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        let index = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(prefix))
+            .and_then(|name| name.strip_suffix(".pcapng"))
+            .and_then(|name| name.split_once('_'))
+            .filter(|(_, time)| time.len() == 16 && time.ends_with('Z'))
+            .and_then(|(index, _)| index.parse().ok());
+        if let Some(index) = index {
+            files.push((index, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+impl RotatingWriter {
+    /// Start writing files to `directory`, which is created if missing
+    ///
+    /// Files are named `<device>_ch<channel>_<index>_<time>.pcapng` where device is the
+    /// serial number or the interface name and time is the UTC start time of the file. Files
+    /// of earlier captures of the same device and channel in the directory count towards the
+    /// files kept, the index continues after theirs.
+    pub fn new(
+        directory: &Path,
+        datalink: DataLink,
+        interface: InterfaceDescription,
+        rotation: Rotation,
+    ) -> Result<Self, Error> {
+        std::fs::create_dir_all(directory)?;
+        let device = file_name_device(&interface);
+        let prefix = format!("{}_ch{}_", device, interface.channel);
+        let existing = existing_files(directory, &prefix)?;
+        let index = existing.last().map_or(0, |(index, _)| *index);
+        Ok(RotatingWriter {
+            directory: directory.to_path_buf(),
+            device,
+            datalink,
+            interface,
+            rotation,
+            writer: None,
+            file_start: None,
+            files: existing.into_iter().map(|(_, path)| path).collect(),
+            index,
+        })
+    }
+
+    /// Files kept, those of earlier captures included, oldest first
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(PathBuf::as_path)
+    }
+
+    fn open(&mut self, timestamp: Duration) -> Result<(), Error> {
+        self.index += 1;
+        let path = self.directory.join(format!(
+            "{}_ch{}_{:05}_{}.pcapng",
+            self.device,
+            self.interface.channel,
+            self.index,
+            file_name_time(timestamp)
+        ));
+        let file = Counter {
+            writer: BufWriter::new(File::create(&path)?),
+            count: 0,
+        };
+        let mut writer = CaptureWriter::new(file)?;
+        writer.add_interface(self.datalink, &self.interface)?;
+        self.writer = Some(writer);
+        self.file_start = None;
+        self.files.push_back(path);
+        if let Some(max_files) = self.rotation.max_files {
+            while self.files.len() > max_files.max(1) {
+                if let Some(oldest) = self.files.pop_front() {
+                    std::fs::remove_file(oldest)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn full(&self, timestamp: Duration) -> bool {
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return false,
+        };
+        let size = self
+            .rotation
+            .file_size
+            .is_some_and(|limit| writer.get_ref().count >= limit);
+        let age = match (self.rotation.file_duration, self.file_start) {
+            (Some(limit), Some(start)) => timestamp.saturating_sub(start) >= limit,
+            _ => false,
+        };
+        size || age
+    }
+
+    /// Write a packet, starting a new file first if the current one is full
+    pub fn write_packet(
+        &mut self,
+        timestamp: Duration,
+        data: &[u8],
+        annotation: &PacketAnnotation,
+    ) -> Result<(), Error> {
+        if self.full(timestamp) {
+            self.close()?;
+        }
+        if self.writer.is_none() {
+            self.open(timestamp)?;
+        }
+        let writer = self.writer.as_mut().expect("File opened");
+        writer.write_packet(0, timestamp, data, annotation)?;
+        self.file_start.get_or_insert(timestamp);
+        Ok(())
+    }
+
+    /// Complete the current file
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "ieee802154-sniffer-rotation-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn interface() -> InterfaceDescription {
+        InterfaceDescription {
+            name: String::from("/dev/ttyACM0"),
+            channel: 15,
+            serial_number: None,
+        }
+    }
+
+    fn names(writer: &RotatingWriter) -> Vec<String> {
+        writer
+            .files()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Limits {
+            frames: Some(10),
+            duration: Some(Duration::from_secs(60)),
+        };
+        assert!(!limits.reached(9, Duration::from_secs(59)));
+        assert!(limits.reached(10, Duration::ZERO));
+        assert!(limits.reached(0, Duration::from_secs(60)));
+        assert!(!Limits::default().reached(u64::MAX, Duration::MAX));
+    }
+
+    #[test]
+    fn time_in_file_names() {
+        assert_eq!(file_name_time(Duration::ZERO), "19700101T000000Z");
+        assert_eq!(
+            file_name_time(Duration::from_secs(1_709_210_096)),
+            "20240229T123456Z"
+        );
+    }
+
+    #[test]
+    fn device_in_file_names() {
+        assert_eq!(file_name_device(&interface()), "ttyACM0");
+        let remote = InterfaceDescription {
+            name: String::from("tcp://lab:4000"),
+            ..interface()
+        };
+        assert_eq!(file_name_device(&remote), "4000");
+        let usb = InterfaceDescription {
+            serial_number: Some(String::from("E1A2 B3")),
+            ..interface()
+        };
+        assert_eq!(file_name_device(&usb), "E1A2-B3");
+    }
+
+    #[test]
+    fn rotate_by_duration_and_keep_last() {
+        let directory = directory("duration");
+        let rotation = Rotation {
+            file_duration: Some(Duration::from_secs(10)),
+            max_files: Some(2),
+            ..Rotation::default()
+        };
+        let mut writer = RotatingWriter::new(
+            &directory,
+            DataLink::IEEE802_15_4_TAP,
+            interface(),
+            rotation,
+        )
+        .unwrap();
+        let start = Duration::from_secs(1_709_210_096);
+        for second in [0, 5, 10, 15, 20, 31] {
+            writer
+                .write_packet(
+                    start + Duration::from_secs(second),
+                    &[0x02, 0x00, second as u8],
+                    &PacketAnnotation::default(),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            names(&writer),
+            [
+                "ttyACM0_ch15_00003_20240229T123516Z.pcapng",
+                "ttyACM0_ch15_00004_20240229T123527Z.pcapng"
+            ]
+        );
+        let files: Vec<PathBuf> = writer.files().map(PathBuf::from).collect();
+        writer.finish().unwrap();
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 2);
+
+        let data = std::fs::read(&files[0]).unwrap();
+        let mut reader = pcap_file::pcapng::PcapNgReader::new(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(block) = reader.next_block() {
+            if let pcap_file::pcapng::Block::EnhancedPacket(packet) = block.unwrap() {
+                frames.push(packet.data[2]);
+            }
+        }
+        assert_eq!(frames, [20]);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn keep_last_across_captures() {
+        let directory = directory("reopen");
+        let rotation = Rotation {
+            file_duration: Some(Duration::from_secs(10)),
+            max_files: Some(3),
+            ..Rotation::default()
+        };
+        let capture = |start: u64| {
+            let mut writer = RotatingWriter::new(
+                &directory,
+                DataLink::IEEE802_15_4_TAP,
+                interface(),
+                rotation,
+            )
+            .unwrap();
+            for second in [0, 10] {
+                writer
+                    .write_packet(
+                        Duration::from_secs(1_709_210_096 + start + second),
+                        &[0x02, 0x00, 0x01],
+                        &PacketAnnotation::default(),
+                    )
+                    .unwrap();
+            }
+            writer.finish().unwrap();
+        };
+        capture(0);
+        // Files of other devices and channels are left alone
+        let other = directory.join("ttyACM1_ch15_00001_20240229T123456Z.pcapng");
+        std::fs::write(&other, []).unwrap();
+        capture(60);
+        let writer = RotatingWriter::new(
+            &directory,
+            DataLink::IEEE802_15_4_TAP,
+            interface(),
+            rotation,
+        )
+        .unwrap();
+        assert_eq!(
+            names(&writer),
+            [
+                "ttyACM0_ch15_00002_20240229T123506Z.pcapng",
+                "ttyACM0_ch15_00003_20240229T123556Z.pcapng",
+                "ttyACM0_ch15_00004_20240229T123606Z.pcapng"
+            ]
+        );
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 4);
+        assert!(other.exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn rotate_by_size() {
+        let directory = directory("size");
+        let rotation = Rotation {
+            file_size: Some(400),
+            ..Rotation::default()
+        };
+        let mut writer = RotatingWriter::new(
+            &directory,
+            DataLink::IEEE802_15_4_TAP,
+            interface(),
+            rotation,
+        )
+        .unwrap();
+        for n in 0..20 {
+            writer
+                .write_packet(
+                    Duration::from_secs(n),
+                    &[0u8; 40],
+                    &PacketAnnotation::default(),
+                )
+                .unwrap();
+        }
+        let files: Vec<PathBuf> = writer.files().map(PathBuf::from).collect();
+        writer.finish().unwrap();
+        assert!(files.len() > 2);
+        for file in &files[..files.len() - 1] {
+            let size = std::fs::metadata(file).unwrap().len();
+            // A file is full once it reaches the size, the statistics block follows
+            assert!((400..600).contains(&size), "{} bytes", size);
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,545 @@
+//! IEEE 802.15.4 frame security, AES-CCM* decryption and authentication
+//!
+//! The auxiliary security header of a secured frame selects the security level and the key.
+//! A [`KeyTable`] holds the keys, each limited to a PAN, a key index or a source address, and
+//! the extended addresses of devices that send with short addresses, which are needed for the
+//! nonce. [`KeyTable::decrypt`] returns the unsecured frame, with the security enabled bit
+//! cleared and the auxiliary security header and the MIC removed. Frames secured without a
+//! MIC cannot be verified, they are only decrypted by [`KeyTable::decrypt_unverified`].
+use crate::mac::{self, Address, FrameType};
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use core::fmt;
+use std::collections::HashMap;
+
+/// Length of AES-128 keys and blocks
+pub const KEY_LENGTH: usize = 16;
+
+const SECURITY_ENABLED: u8 = 1 << 3;
+const FRAME_COUNTER_SUPPRESSION: u8 = 1 << 5;
+const ASN_IN_NONCE: u8 = 1 << 6;
+
+/// Length of the CCM* nonce, source address, frame counter and security level
+const NONCE_LENGTH: usize = 13;
+
+/// Parsed auxiliary security header
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuxiliaryHeader {
+    /// Security level, 0 to 7, bit 2 selects encryption and bits 0 and 1 the MIC length
+    pub level: u8,
+    /// Key identifier mode, 0 for an implicit key, 1 for a key index, 2 and 3 for a key
+    /// index with a 4 or 8 octet key source
+    pub key_id_mode: u8,
+    /// Frame counter, absent when suppressed in a 2015 frame
+    pub frame_counter: Option<u32>,
+    /// Key source octets as they appear in the frame
+    pub key_source: Vec<u8>,
+    pub key_index: Option<u8>,
+    /// The nonce uses the absolute slot number, as in TSCH networks
+    pub asn_in_nonce: bool,
+}
+
+impl AuxiliaryHeader {
+    /// Parse the auxiliary security header at the start of `data`, returns the header and
+    /// its length
+    pub fn parse(data: &[u8]) -> Option<(AuxiliaryHeader, usize)> {
+        let control = *data.first()?;
+        let mut offset = 1;
+        let frame_counter = match control & FRAME_COUNTER_SUPPRESSION {
+            0 => {
+                let counter = data.get(offset..offset + 4)?;
+                offset += 4;
+                Some(u32::from_le_bytes([
+                    counter[0], counter[1], counter[2], counter[3],
+                ]))
+            }
+            _ => None,
+        };
+        let key_id_mode = (control >> 3) & 0b11;
+        let source_length = match key_id_mode {
+            2 => 4,
+            3 => 8,
+            _ => 0,
+        };
+        let key_source = data.get(offset..offset + source_length)?.to_vec();
+        offset += source_length;
+        let key_index = match key_id_mode {
+            0 => None,
+            _ => {
+                offset += 1;
+                Some(*data.get(offset - 1)?)
+            }
+        };
+        let header = AuxiliaryHeader {
+            level: control & 0b111,
+            key_id_mode,
+            frame_counter,
+            key_source,
+            key_index,
+            asn_in_nonce: control & ASN_IN_NONCE != 0,
+        };
+        Some((header, offset))
+    }
+
+    /// Length of the MIC, 0, 4, 8 or 16 octets
+    pub fn mic_length(&self) -> usize {
+        match self.level & 0b11 {
+            0 => 0,
+            n => 2 << n,
+        }
+    }
+
+    /// The payload is encrypted
+    pub fn encrypted(&self) -> bool {
+        self.level & 0b100 != 0
+    }
+}
+
+/// Key for secured frames, the optional fields limit the frames the key is tried for
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub key: [u8; KEY_LENGTH],
+    /// PAN of the frames, the source PAN or the destination PAN when compressed
+    pub pan_id: Option<u16>,
+    /// Key index from the auxiliary security header
+    pub key_index: Option<u8>,
+    /// Source address of the frames
+    pub source: Option<Address>,
+}
+
+impl Key {
+    /// Key for every frame
+    pub fn new(key: [u8; KEY_LENGTH]) -> Self {
+        Key {
+            key,
+            pan_id: None,
+            key_index: None,
+            source: None,
+        }
+    }
+
+    fn matches(&self, header: &mac::Header, auxiliary: &AuxiliaryHeader) -> bool {
+        let pan_id = header.source_pan_or_destination();
+        self.pan_id
+            .is_none_or(|key_pan_id| pan_id == Some(key_pan_id))
+            && self
+                .key_index
+                .is_none_or(|index| auxiliary.key_index == Some(index))
+            && self
+                .source
+                .is_none_or(|source| header.source == Some(source))
+    }
+}
+
+/// Reasons a frame could not be decrypted
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecryptError {
+    /// The frame does not have the security enabled bit set
+    NotSecured,
+    /// The MAC header or auxiliary security header could not be parsed
+    Malformed,
+    /// The nonce uses the absolute slot number, which is not captured
+    UnsupportedNonce,
+    /// No key matches the frame
+    NoKey,
+    /// The extended address of the source, needed for the nonce, is not known
+    UnknownSource,
+    /// No matching key verified the MIC
+    Authentication,
+    /// The frame is secured without a MIC, so a key cannot be verified
+    Unverified,
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            DecryptError::NotSecured => "Frame is not secured",
+            DecryptError::Malformed => "Malformed secured frame",
+            DecryptError::UnsupportedNonce => "Nonce with absolute slot number not supported",
+            DecryptError::NoKey => "No key for the frame",
+            DecryptError::UnknownSource => "Unknown extended source address",
+            DecryptError::Authentication => "MIC verification failed",
+            DecryptError::Unverified => "Frame without MIC cannot be verified",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl std::error::Error for DecryptError {}
+
+/// Keys and the address mapping used for decryption
+#[derive(Clone, Debug, Default)]
+pub struct KeyTable {
+    keys: Vec<Key>,
+    /// Extended address by PAN and short address
+    addresses: HashMap<(u16, u16), u64>,
+}
+
+/// Parts of a secured frame
+struct Secured<'a> {
+    header: mac::Header,
+    auxiliary: AuxiliaryHeader,
+    /// MAC header, up to the auxiliary security header
+    mac_header: &'a [u8],
+    /// Header IEs and the open payload, authenticated but not encrypted
+    open: &'a [u8],
+    /// Private payload followed by the MIC
+    private: &'a [u8],
+}
+
+/// Split a secured frame at the auxiliary security header and the start of the private
+/// payload
+fn split(frame: &[u8]) -> Result<Secured<'_>, DecryptError> {
+    let (header, header_length) = mac::Header::parse(frame).ok_or(DecryptError::Malformed)?;
+    if !header.security_enabled {
+        return Err(DecryptError::NotSecured);
+    }
+    let (auxiliary, auxiliary_length) =
+        AuxiliaryHeader::parse(&frame[header_length..]).ok_or(DecryptError::Malformed)?;
+    let body = &frame[header_length + auxiliary_length..];
+    let body = &body[..body
+        .len()
+        .checked_sub(auxiliary.mic_length())
+        .ok_or(DecryptError::Malformed)?];
+    let mut open_length = 0;
+    if header.information_elements {
+        open_length = mac::header_ies_length(body).ok_or(DecryptError::Malformed)?;
+    }
+    // The command identifier is sent in the clear
+    if header.frame_type == FrameType::Command && open_length < body.len() {
+        open_length += 1;
+    }
+    let private_start = header_length + auxiliary_length + open_length;
+    Ok(Secured {
+        header,
+        auxiliary,
+        mac_header: &frame[..header_length],
+        open: &frame[header_length + auxiliary_length..private_start],
+        private: &frame[private_start..],
+    })
+}
+
+impl KeyTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_key(&mut self, key: Key) {
+        self.keys.push(key);
+    }
+
+    /// Map a short address in a PAN to the extended address of the device
+    pub fn add_address(&mut self, pan_id: u16, short: u16, extended: u64) {
+        self.addresses.insert((pan_id, short), extended);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Extended address of the frame source
+    fn source(&self, header: &mac::Header) -> Option<u64> {
+        match header.source? {
+            Address::Extended(address) => Some(address),
+            Address::Short(short) => {
+                let pan_id = header.source_pan_or_destination()?;
+                self.addresses.get(&(pan_id, short)).copied()
+            }
+        }
+    }
+
+    /// Decrypt and authenticate a secured frame without FCS
+    ///
+    /// Every matching key is tried until one verifies the MIC. Frames secured without a MIC
+    /// fail with [`DecryptError::Unverified`].
+    pub fn decrypt(&self, frame: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        self.unsecure(frame, true)
+    }
+
+    /// Decrypt a secured frame without FCS, also when it is secured without a MIC
+    ///
+    /// Frames with a MIC are authenticated like [`KeyTable::decrypt`] does. Frames without a
+    /// MIC are decrypted with the first matching key, which may be the wrong one.
+    pub fn decrypt_unverified(&self, frame: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        self.unsecure(frame, false)
+    }
+
+    fn unsecure(&self, frame: &[u8], verified: bool) -> Result<Vec<u8>, DecryptError> {
+        let secured = split(frame)?;
+        if secured.auxiliary.asn_in_nonce {
+            return Err(DecryptError::UnsupportedNonce);
+        }
+        let frame_counter = secured
+            .auxiliary
+            .frame_counter
+            .ok_or(DecryptError::UnsupportedNonce)?;
+        let mut keys = self
+            .keys
+            .iter()
+            .filter(|key| key.matches(&secured.header, &secured.auxiliary))
+            .peekable();
+        keys.peek().ok_or(DecryptError::NoKey)?;
+        if verified && secured.auxiliary.mic_length() == 0 {
+            return Err(DecryptError::Unverified);
+        }
+        let source = self
+            .source(&secured.header)
+            .ok_or(DecryptError::UnknownSource)?;
+        let mut nonce = [0u8; NONCE_LENGTH];
+        nonce[..8].copy_from_slice(&source.to_be_bytes());
+        nonce[8..12].copy_from_slice(&frame_counter.to_be_bytes());
+        nonce[12] = secured.auxiliary.level;
+
+        // The MAC header is authenticated as sent, with the security enabled bit set
+        let auxiliary_end = frame.len() - secured.open.len() - secured.private.len();
+        let mut authenticated = frame[..auxiliary_end].to_vec();
+        authenticated.extend_from_slice(secured.open);
+        let (authenticated, private) = match secured.auxiliary.encrypted() {
+            true => (authenticated, secured.private),
+            // Without encryption the whole payload is authenticated
+            false => {
+                let mic_start = secured.private.len() - secured.auxiliary.mic_length();
+                authenticated.extend_from_slice(&secured.private[..mic_start]);
+                (authenticated, &secured.private[mic_start..])
+            }
+        };
+        for key in keys {
+            let cipher = Aes128::new(GenericArray::from_slice(&key.key));
+            if let Some(plaintext) = ccm_star_decrypt(
+                &cipher,
+                &nonce,
+                &authenticated,
+                private,
+                secured.auxiliary.mic_length(),
+                secured.auxiliary.encrypted(),
+            ) {
+                let mut unsecured = secured.mac_header.to_vec();
+                unsecured[0] &= !SECURITY_ENABLED;
+                unsecured.extend_from_slice(secured.open);
+                if !secured.auxiliary.encrypted() {
+                    unsecured
+                        .extend_from_slice(&authenticated[auxiliary_end + secured.open.len()..]);
+                }
+                unsecured.extend_from_slice(&plaintext);
+                return Ok(unsecured);
+            }
+        }
+        Err(DecryptError::Authentication)
+    }
+}
+
+fn encrypt_block(cipher: &Aes128, block: &[u8; KEY_LENGTH]) -> [u8; KEY_LENGTH] {
+    let mut block = GenericArray::clone_from_slice(block);
+    cipher.encrypt_block(&mut block);
+    block.into()
+}
+
+/// CCM* counter block `A_i`
+fn counter_block(nonce: &[u8; NONCE_LENGTH], counter: u16) -> [u8; KEY_LENGTH] {
+    let mut block = [0u8; KEY_LENGTH];
+    // L = 2, the length field is two octets
+    block[0] = 1;
+    block[1..14].copy_from_slice(nonce);
+    block[14..].copy_from_slice(&counter.to_be_bytes());
+    block
+}
+
+/// CBC-MAC of the authentication data and the message, CCM* authentication field `T`
+fn authentication_tag(
+    cipher: &Aes128,
+    nonce: &[u8; NONCE_LENGTH],
+    authenticated: &[u8],
+    message: &[u8],
+    mic_length: usize,
+) -> [u8; KEY_LENGTH] {
+    let mut first = [0u8; KEY_LENGTH];
+    let mic_flags = match mic_length {
+        0 => 0,
+        length => ((length as u8 - 2) / 2) << 3,
+    };
+    let adata_flag = if authenticated.is_empty() { 0 } else { 1 << 6 };
+    first[0] = adata_flag | mic_flags | 1;
+    first[1..14].copy_from_slice(nonce);
+    first[14..].copy_from_slice(&(message.len() as u16).to_be_bytes());
+
+    // The authentication data is prefixed with its length, frames are shorter than 0xff00
+    let mut data = Vec::with_capacity(2 + authenticated.len() + message.len() + 2 * KEY_LENGTH);
+    if !authenticated.is_empty() {
+        data.extend_from_slice(&(authenticated.len() as u16).to_be_bytes());
+        data.extend_from_slice(authenticated);
+        data.resize(data.len().next_multiple_of(KEY_LENGTH), 0);
+    }
+    data.extend_from_slice(message);
+    data.resize(data.len().next_multiple_of(KEY_LENGTH), 0);
+
+    let mut tag = encrypt_block(cipher, &first);
+    for block in data.chunks(KEY_LENGTH) {
+        for (t, b) in tag.iter_mut().zip(block) {
+            *t ^= b;
+        }
+        tag = encrypt_block(cipher, &tag);
+    }
+    tag
+}
+
+/// CCM* decryption, returns the plaintext if the MIC matches
+///
+/// `private` is the encrypted or, without encryption, empty message followed by the
+/// encrypted MIC.
+fn ccm_star_decrypt(
+    cipher: &Aes128,
+    nonce: &[u8; NONCE_LENGTH],
+    authenticated: &[u8],
+    private: &[u8],
+    mic_length: usize,
+    encrypted: bool,
+) -> Option<Vec<u8>> {
+    let (ciphertext, mic) = private.split_at(private.len().checked_sub(mic_length)?);
+    let mut plaintext = ciphertext.to_vec();
+    if encrypted {
+        for (n, block) in plaintext.chunks_mut(KEY_LENGTH).enumerate() {
+            let stream = encrypt_block(cipher, &counter_block(nonce, n as u16 + 1));
+            for (p, s) in block.iter_mut().zip(stream) {
+                *p ^= s;
+            }
+        }
+    }
+    if mic_length == 0 {
+        return Some(plaintext);
+    }
+    let tag = authentication_tag(cipher, nonce, authenticated, &plaintext, mic_length);
+    let stream = encrypt_block(cipher, &counter_block(nonce, 0));
+    let matches = mic
+        .iter()
+        .zip(tag.iter().zip(stream))
+        .fold(0u8, |difference, (m, (t, s))| difference | (m ^ t ^ s));
+    (matches == 0).then_some(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key and source address of the IEEE 802.15.4-2006 annex C test vectors
+    const KEY: [u8; KEY_LENGTH] = [
+        0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcc, 0xcd, 0xce,
+        0xcf,
+    ];
+
+    fn table() -> KeyTable {
+        let mut table = KeyTable::new();
+        table.add_key(Key::new(KEY));
+        table
+    }
+
+    #[test]
+    fn data_frame_encryption() {
+        // IEEE 802.15.4-2006 C.2.2, data frame with security level 4, ENC
+        let secured = [
+            0x69, 0xdc, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x04, 0x05, 0x00, 0x00, 0x00, 0xd4, 0x3e,
+            0x02, 0x2b,
+        ];
+        let mut expected = secured[..21].to_vec();
+        expected[0] = 0x61;
+        expected.extend_from_slice(b"abcd");
+        assert_eq!(table().decrypt(&secured), Err(DecryptError::Unverified));
+        assert_eq!(table().decrypt_unverified(&secured), Ok(expected));
+    }
+
+    #[test]
+    fn command_frame_encryption_and_authentication() {
+        // IEEE 802.15.4-2006 C.2.3, association request with security level 6, ENC-MIC-64
+        let secured = [
+            0x2b, 0xdc, 0x84, 0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0xff,
+            0xff, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x06, 0x05, 0x00, 0x00, 0x00,
+            0x01, 0xd8, 0x4f, 0xde, 0x52, 0x90, 0x61, 0xf9, 0xc6, 0xf1,
+        ];
+        let mut expected = secured[..23].to_vec();
+        expected[0] = 0x23;
+        expected.extend_from_slice(&[0x01, 0xce]);
+        assert_eq!(table().decrypt(&secured), Ok(expected));
+
+        let mut tampered = secured;
+        tampered[28] = 0x02;
+        assert_eq!(
+            table().decrypt(&tampered),
+            Err(DecryptError::Authentication)
+        );
+    }
+
+    #[test]
+    fn beacon_authentication() {
+        // IEEE 802.15.4-2006 C.2.1, beacon with security level 2, MIC-64
+        let secured = [
+            0x08, 0xd0, 0x84, 0x21, 0x43, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, 0x02,
+            0x05, 0x00, 0x00, 0x00, 0x55, 0xcf, 0x00, 0x00, 0x51, 0x52, 0x53, 0x54, 0x22, 0x3b,
+            0xc1, 0xec, 0x84, 0x1a, 0xb5, 0x53,
+        ];
+        let mut expected = secured[..13].to_vec();
+        expected[0] = 0x00;
+        expected.extend_from_slice(&secured[18..26]);
+        assert_eq!(table().decrypt(&secured), Ok(expected));
+
+        let mut tampered = secured;
+        tampered[25] = 0x00;
+        assert_eq!(
+            table().decrypt(&tampered),
+            Err(DecryptError::Authentication)
+        );
+    }
+
+    #[test]
+    fn key_selection() {
+        // Short addresses, security level 5 with key index 1
+        let secured = [
+            0x69, 0x98, 0x01, 0x21, 0x43, 0x02, 0x00, 0x01, 0x00, 0x0d, 0x05, 0x00, 0x00, 0x00,
+            0x01, 0xaa, 0x00, 0x00, 0x00, 0x00,
+        ];
+        // The nonce needs the extended address of the source
+        let mut table = KeyTable::new();
+        table.add_key(Key {
+            pan_id: Some(0x4321),
+            ..Key::new(KEY)
+        });
+        assert_eq!(table.decrypt(&secured), Err(DecryptError::UnknownSource));
+        table.add_address(0x4321, 0x0001, 0xacde480000000001);
+        assert_eq!(table.decrypt(&secured), Err(DecryptError::Authentication));
+
+        let mut table = KeyTable::new();
+        table.add_key(Key {
+            key_index: Some(2),
+            ..Key::new(KEY)
+        });
+        assert_eq!(table.decrypt(&secured), Err(DecryptError::NoKey));
+        assert_eq!(
+            table.decrypt(&[0x02, 0x00, 0x04]),
+            Err(DecryptError::NotSecured)
+        );
+    }
+
+    #[test]
+    fn auxiliary_header() {
+        let (header, length) =
+            AuxiliaryHeader::parse(&[0x35, 0x04, 0x03, 0x02, 0x01, 0x07]).unwrap();
+        assert_eq!(header.level, 5);
+        assert_eq!(header.key_id_mode, 2);
+        assert_eq!(header.frame_counter, None);
+        assert_eq!(header.key_source, [0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(header.key_index, Some(7));
+        assert_eq!(header.mic_length(), 4);
+        assert!(header.encrypted());
+        assert_eq!(length, 6);
+
+        let (header, length) =
+            AuxiliaryHeader::parse(&[0x0f, 0x01, 0x00, 0x00, 0x00, 0x09]).unwrap();
+        assert_eq!(header.frame_counter, Some(1));
+        assert_eq!(header.key_index, Some(9));
+        assert_eq!(header.mic_length(), 16);
+        assert_eq!(length, 6);
+
+        assert!(AuxiliaryHeader::parse(&[0x0d, 0x01, 0x00]).is_none());
+    }
+}
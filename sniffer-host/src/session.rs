@@ -0,0 +1,543 @@
+//! Capture session surviving device disconnects and resets
+//!
+//! A session keeps the desired device configuration and re-applies it whenever the device
+//! comes back. A lost port is detected through read and write errors. A device that reset
+//! without the port being lost goes silent, so after a period without any packets the
+//! device is probed. The device follows its reply with its state, and is configured again
+//! when the state shows that it lost the configuration. A device that does not reply is
+//! treated as lost.
+use crate::{
+    device::{Device, Statistics},
+    transport, Error,
+};
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Time without packets before the device is probed
+const KEEPALIVE: Duration = Duration::from_secs(5);
+
+/// Time to wait for a probe reply
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Time between attempts to reopen a lost device
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Read timeout of the device transport, bounds the time to notice commands and stops
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+type Opener = Box<dyn FnMut(&str) -> Result<Device, Error> + Send>;
+
+/// Something that happened in the session
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Event {
+    /// Packet received from the device
+    Packet(wire_format::Packet),
+    /// Data that could not be decoded was received, a frame was lost
+    Corrupted,
+    /// The device was lost, it will be reopened
+    Lost(Error),
+    /// The device is back after being lost for the given time
+    Reconnected(Duration),
+}
+
+/// Device configuration to apply after a reconnect
+struct Configuration {
+    channel: u8,
+    power: Option<i32>,
+    capturing: bool,
+}
+
+/// Sniffer device that is reopened and reconfigured when lost
+pub struct Session {
+    uri: String,
+    serial_number: Option<String>,
+    open: Opener,
+    device: Option<Device>,
+    configuration: Configuration,
+    last_received: Instant,
+    probe_sent: Option<Instant>,
+    /// A probe was answered, the state that follows is checked, with the time of the last
+    /// packet before the probe
+    state_expected: Option<Instant>,
+    lost_at: Option<Instant>,
+    last_attempt: Option<Instant>,
+    lost_error: Option<Error>,
+    statistics: Statistics,
+    keepalive: Duration,
+    probe_timeout: Duration,
+    retry_interval: Duration,
+}
+
+impl Session {
+    /// Open a session on a device by URI, see [`transport::open`]
+    pub fn open(uri: &str, channel: u8) -> Result<Self, Error> {
+        Self::open_with_baud_rate(uri, wire_format::BAUD_RATE, channel)
+    }
+
+    /// Open a session on a device by URI, a serial port is opened with `baud_rate`
+    pub fn open_with_baud_rate(uri: &str, baud_rate: u32, channel: u8) -> Result<Self, Error> {
+        let serial_number = transport::usb_serial_number(uri);
+        Self::with_opener(
+            uri,
+            serial_number,
+            channel,
+            Box::new(move |uri| {
+                let transport = transport::open_with_baud_rate(uri, baud_rate, READ_TIMEOUT)?;
+                Ok(Device::new(transport))
+            }),
+        )
+    }
+
+    fn with_opener(
+        uri: &str,
+        serial_number: Option<String>,
+        channel: u8,
+        mut open: Opener,
+    ) -> Result<Self, Error> {
+        let mut device = open(uri)?;
+        device.set_channel(channel)?;
+        Ok(Self {
+            uri: uri.to_string(),
+            serial_number,
+            open,
+            device: Some(device),
+            configuration: Configuration {
+                channel,
+                power: None,
+                capturing: false,
+            },
+            last_received: Instant::now(),
+            probe_sent: None,
+            state_expected: None,
+            lost_at: None,
+            last_attempt: None,
+            lost_error: None,
+            statistics: Statistics::default(),
+            keepalive: KEEPALIVE,
+            probe_timeout: PROBE_TIMEOUT,
+            retry_interval: RETRY_INTERVAL,
+        })
+    }
+
+    /// Device URI, changes when a lost device is found under another name
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Counters of the data received over all connections of the session
+    pub fn statistics(&self) -> Statistics {
+        self.statistics
+    }
+
+    /// Set the IEEE 802.15.4 channel, 11 to 26
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        self.configuration.channel = channel;
+        self.command(&wire_format::Packet::Channel(channel))
+    }
+
+    /// Set the transmit power in dBm
+    pub fn set_power(&mut self, power: i32) -> Result<(), Error> {
+        self.configuration.power = Some(power);
+        self.command(&wire_format::Packet::Power(power))
+    }
+
+    /// Start sending captured frames
+    pub fn start_capture(&mut self) -> Result<(), Error> {
+        self.configuration.capturing = true;
+        self.command(&wire_format::Packet::CaptureStart)
+    }
+
+    /// Stop sending captured frames
+    pub fn stop_capture(&mut self) -> Result<(), Error> {
+        self.configuration.capturing = false;
+        self.command(&wire_format::Packet::CaptureStop)
+    }
+
+    /// Receive the next event, `Ok(None)` when nothing happened within the read timeout
+    pub fn receive(&mut self) -> Result<Option<Event>, Error> {
+        if let Some(error) = self.lost_error.take() {
+            return Ok(Some(Event::Lost(error)));
+        }
+        let event = self.receive_device()?;
+        match &event {
+            Some(Event::Packet(wire_format::Packet::CaptureFrame(_))) => {
+                self.statistics.frames += 1
+            }
+            Some(Event::Corrupted) => self.statistics.corrupted += 1,
+            Some(Event::Reconnected(_)) => self.statistics.reconnects += 1,
+            None => return Ok(self.lost_error.take().map(Event::Lost)),
+            _ => (),
+        }
+        Ok(event)
+    }
+
+    fn receive_device(&mut self) -> Result<Option<Event>, Error> {
+        let device = match self.device.as_mut() {
+            Some(device) => device,
+            None => return self.reconnect(),
+        };
+        match device.receive() {
+            Ok(Some(wire_format::Packet::Probe(_))) if self.probe_sent.is_some() => {
+                self.probe_sent = None;
+                let silent_since = std::mem::replace(&mut self.last_received, Instant::now());
+                match self.lost_at.take() {
+                    Some(lost_at) => {
+                        self.configure();
+                        Ok(Some(Event::Reconnected(lost_at.elapsed())))
+                    }
+                    None => {
+                        self.state_expected = Some(silent_since);
+                        Ok(None)
+                    }
+                }
+            }
+            Ok(Some(wire_format::Packet::State(state))) => {
+                self.last_received = Instant::now();
+                let Some(silent_since) = self.state_expected.take() else {
+                    return Ok(None);
+                };
+                if state.channel == self.configuration.channel
+                    && state.capturing == self.configuration.capturing
+                {
+                    return Ok(None);
+                }
+                // The device was reset
+                self.configure();
+                Ok(Some(Event::Reconnected(silent_since.elapsed())))
+            }
+            Ok(Some(packet)) => {
+                self.last_received = Instant::now();
+                Ok(Some(Event::Packet(packet)))
+            }
+            Ok(None) => {
+                self.check_alive();
+                Ok(None)
+            }
+            Err(Error::Wire(_)) => {
+                self.last_received = Instant::now();
+                Ok(Some(Event::Corrupted))
+            }
+            Err(Error::Io(ref error)) if transport::is_transient(error) => {
+                self.check_alive();
+                Ok(None)
+            }
+            Err(error) => {
+                self.lost(error);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Send a configuration command, a device that fails is treated as lost
+    fn command(&mut self, packet: &wire_format::Packet) -> Result<(), Error> {
+        if let Some(device) = self.device.as_mut() {
+            let result = match packet {
+                wire_format::Packet::Channel(channel) => device.set_channel(*channel),
+                wire_format::Packet::Power(power) => device.set_power(*power),
+                wire_format::Packet::CaptureStart => device.start_capture(),
+                wire_format::Packet::CaptureStop => device.stop_capture(),
+                _ => Ok(()),
+            };
+            match result {
+                Err(Error::Wire(error)) => return Err(Error::Wire(error)),
+                Err(error) => self.lost(error),
+                Ok(()) => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the full configuration to the device
+    fn configure(&mut self) {
+        let _ = self.command(&wire_format::Packet::Channel(self.configuration.channel));
+        if let Some(power) = self.configuration.power {
+            let _ = self.command(&wire_format::Packet::Power(power));
+        }
+        if self.configuration.capturing {
+            let _ = self.command(&wire_format::Packet::CaptureStart);
+        }
+    }
+
+    fn probe(&mut self) {
+        if let Some(device) = self.device.as_mut() {
+            match device.send_probe() {
+                Ok(()) => self.probe_sent = Some(Instant::now()),
+                Err(error) => self.lost(error),
+            }
+        }
+    }
+
+    /// Probe a silent device, a device not replying in time is lost
+    fn check_alive(&mut self) {
+        match self.probe_sent {
+            Some(sent) if sent.elapsed() >= self.probe_timeout => {
+                self.lost(std::io::Error::from(std::io::ErrorKind::TimedOut).into())
+            }
+            Some(_) => (),
+            None if self.last_received.elapsed() >= self.keepalive => self.probe(),
+            None => (),
+        }
+    }
+
+    fn lost(&mut self, error: Error) {
+        if self.device.take().is_some() {
+            self.lost_error = Some(error);
+        }
+        self.probe_sent = None;
+        self.state_expected = None;
+        self.lost_at.get_or_insert_with(Instant::now);
+    }
+
+    /// Find the lost device, it may have been enumerated under another name
+    fn locate(&self) -> String {
+        self.serial_number
+            .as_ref()
+            .and_then(|serial_number| {
+                serialport::available_ports()
+                    .ok()?
+                    .into_iter()
+                    .find(|port| match &port.port_type {
+                        serialport::SerialPortType::UsbPort(usb_port) => {
+                            usb_port.serial_number.as_ref() == Some(serial_number)
+                        }
+                        _ => false,
+                    })
+                    .map(|port| port.port_name)
+            })
+            .unwrap_or_else(|| self.uri.clone())
+    }
+
+    /// Try to reopen a lost device, at most once per retry interval
+    fn reconnect(&mut self) -> Result<Option<Event>, Error> {
+        if let Some(attempt) = self.last_attempt {
+            if attempt.elapsed() < self.retry_interval {
+                // Avoid spinning while there is no device to read from
+                thread::sleep(Duration::from_millis(10));
+                return Ok(None);
+            }
+        }
+        self.last_attempt = Some(Instant::now());
+        let uri = self.locate();
+        if let Ok(device) = (self.open)(&uri) {
+            self.uri = uri;
+            self.device = Some(device);
+            // The configuration is applied once the device replies
+            self.probe();
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for Session {
+    /// Leave the device stopped whichever way the capture ends
+    fn drop(&mut self) {
+        if self.configuration.capturing {
+            let _ = self.stop_capture();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::MockTransport;
+    use std::sync::{Arc, Mutex};
+
+    fn encode(packet: &wire_format::Packet) -> Vec<u8> {
+        let mut buffer = [0u8; 512];
+        packet.encode(&mut buffer).unwrap().to_vec()
+    }
+
+    fn encode_all(packets: &[wire_format::Packet]) -> Vec<u8> {
+        packets.iter().flat_map(encode).collect()
+    }
+
+    /// Session on mock transports, each open hands out the next transport
+    fn session(transports: Vec<MockTransport>) -> Session {
+        let transports = Arc::new(Mutex::new(transports));
+        let opener: Opener = Box::new(move |_uri| {
+            let mut transports = transports.lock().unwrap();
+            if transports.is_empty() {
+                return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+            }
+            let transport = transports.remove(0);
+            Ok(Device::new(Box::new(transport)))
+        });
+        let mut session = Session::with_opener("mock", None, 15, opener).unwrap();
+        session.retry_interval = Duration::ZERO;
+        session
+    }
+
+    fn reply() -> Vec<u8> {
+        encode(&wire_format::Packet::Probe(wire_format::PROBE_DEVICE))
+    }
+
+    #[test]
+    fn reconnect_after_disconnect() {
+        let first = MockTransport::default();
+        let second = MockTransport::default();
+        let mut session = session(vec![first.clone(), second.clone()]);
+        session.set_power(4).unwrap();
+        session.start_capture().unwrap();
+        first.disconnect();
+        assert!(matches!(session.receive().unwrap(), Some(Event::Lost(_))));
+        assert!(session.device.is_none());
+
+        // Reopened and probed, configured once the probe is answered
+        assert!(session.receive().unwrap().is_none());
+        assert_eq!(
+            second.take_tx(),
+            encode(&wire_format::Packet::Probe(wire_format::PROBE_HOST))
+        );
+        second.push_rx(&reply());
+        assert!(matches!(
+            session.receive().unwrap(),
+            Some(Event::Reconnected(_))
+        ));
+        assert_eq!(
+            second.take_tx(),
+            encode_all(&[
+                wire_format::Packet::Channel(15),
+                wire_format::Packet::Power(4),
+                wire_format::Packet::CaptureStart,
+            ])
+        );
+        assert_eq!(session.statistics().reconnects, 1);
+    }
+
+    #[test]
+    fn commands_while_lost() {
+        let first = MockTransport::default();
+        let second = MockTransport::default();
+        let mut session = session(vec![first.clone(), second.clone()]);
+        session.start_capture().unwrap();
+        first.disconnect();
+        session.set_channel(20).unwrap();
+        session.stop_capture().unwrap();
+        assert!(session.device.is_none());
+        assert!(matches!(session.receive().unwrap(), Some(Event::Lost(_))));
+        session.receive().unwrap();
+        second.push_rx(&reply());
+        assert!(matches!(
+            session.receive().unwrap(),
+            Some(Event::Reconnected(_))
+        ));
+        let mut expected = encode(&wire_format::Packet::Probe(wire_format::PROBE_HOST));
+        expected.extend(encode(&wire_format::Packet::Channel(20)));
+        assert_eq!(second.take_tx(), expected);
+    }
+
+    fn state(channel: u8, capturing: bool) -> Vec<u8> {
+        encode(&wire_format::Packet::State(wire_format::State {
+            channel,
+            capturing,
+        }))
+    }
+
+    /// Session capturing on channel 15 that has sent a keepalive probe
+    fn probed() -> (Session, MockTransport) {
+        let transport = MockTransport::default();
+        let mut session = session(vec![transport.clone()]);
+        session.start_capture().unwrap();
+        transport.take_tx();
+        session.keepalive = Duration::ZERO;
+        assert!(session.receive().unwrap().is_none());
+        assert_eq!(
+            transport.take_tx(),
+            encode(&wire_format::Packet::Probe(wire_format::PROBE_HOST))
+        );
+        session.keepalive = KEEPALIVE;
+        (session, transport)
+    }
+
+    #[test]
+    fn keepalive_does_not_reconfigure() {
+        let (mut session, transport) = probed();
+        let mut data = reply();
+        data.extend(state(15, true));
+        transport.push_rx(&data);
+        assert!(session.receive().unwrap().is_none());
+        assert!(session.receive().unwrap().is_none());
+        assert_eq!(transport.take_tx(), Vec::<u8>::new());
+        assert_eq!(session.statistics().reconnects, 0);
+    }
+
+    #[test]
+    fn keepalive_detects_reset() {
+        let (mut session, transport) = probed();
+        let mut data = reply();
+        data.extend(state(11, false));
+        transport.push_rx(&data);
+        assert!(session.receive().unwrap().is_none());
+        assert!(matches!(
+            session.receive().unwrap(),
+            Some(Event::Reconnected(_))
+        ));
+        assert_eq!(
+            transport.take_tx(),
+            encode_all(&[
+                wire_format::Packet::Channel(15),
+                wire_format::Packet::CaptureStart,
+            ])
+        );
+        assert_eq!(session.statistics().reconnects, 1);
+    }
+
+    #[test]
+    fn silent_device_is_lost() {
+        let first = MockTransport::default();
+        let second = MockTransport::default();
+        let mut session = session(vec![first.clone(), second.clone()]);
+        session.keepalive = Duration::ZERO;
+        session.probe_timeout = Duration::ZERO;
+        session.receive().unwrap();
+        assert!(matches!(session.receive().unwrap(), Some(Event::Lost(_))));
+        assert!(session.device.is_none());
+        session.receive().unwrap();
+        second.push_rx(&reply());
+        assert!(matches!(
+            session.receive().unwrap(),
+            Some(Event::Reconnected(_))
+        ));
+    }
+
+    #[test]
+    fn stop_when_dropped() {
+        let transport = MockTransport::default();
+        let mut session = session(vec![transport.clone()]);
+        session.start_capture().unwrap();
+        transport.take_tx();
+        drop(session);
+        assert_eq!(
+            transport.take_tx(),
+            encode(&wire_format::Packet::CaptureStop)
+        );
+    }
+
+    #[test]
+    fn frames_and_corruption() {
+        let transport = MockTransport::default();
+        let mut session = session(vec![transport.clone()]);
+        let frame = wire_format::Packet::CaptureFrame(wire_format::Frame {
+            channel: 15,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        });
+        let mut data = encode(&frame);
+        data.extend([0x05, 0xff, 0xff, 0x00]);
+        transport.push_rx(&data);
+        assert!(matches!(
+            session.receive().unwrap(),
+            Some(Event::Packet(packet)) if packet == frame
+        ));
+        assert!(matches!(session.receive().unwrap(), Some(Event::Corrupted)));
+        assert!(session.receive().unwrap().is_none());
+        assert_eq!(session.statistics().frames, 1);
+        assert_eq!(session.statistics().corrupted, 1);
+    }
+}
@@ -0,0 +1,927 @@
+//! 6LoWPAN reassembly and header decompression
+//!
+//! A [`Decompressor`] turns the 6LoWPAN payloads of unsecured IEEE 802.15.4 data frames into
+//! IPv6 packets. Mesh and broadcast headers are skipped, fragmented datagrams are reassembled
+//! as described in RFC 4944 and IPHC compressed IPv6 headers, with NHC compressed extension
+//! and UDP headers, are decompressed as described in RFC 6282. Stateful address compression
+//! uses the contexts given with [`Decompressor::set_context`], in a Thread network context 0
+//! is the mesh-local prefix.
+//!
+//! Secured frames have to be decrypted first, with a [`crate::security::KeyTable`].
+use crate::mac::{self, Address, FrameType};
+use core::fmt;
+use std::{collections::HashMap, net::Ipv6Addr, str::FromStr, time::Duration};
+
+/// Fragments of a datagram that is not completed within this time are dropped
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+const IPV6_HEADER_LENGTH: usize = 40;
+
+const DISPATCH_IPV6: u8 = 0x41;
+const DISPATCH_BROADCAST: u8 = 0x50;
+
+const NEXT_HEADER_HOP_BY_HOP: u8 = 0;
+const NEXT_HEADER_UDP: u8 = 17;
+const NEXT_HEADER_IPV6: u8 = 41;
+const NEXT_HEADER_ROUTING: u8 = 43;
+const NEXT_HEADER_FRAGMENT: u8 = 44;
+const NEXT_HEADER_DESTINATION_OPTIONS: u8 = 60;
+const NEXT_HEADER_MOBILITY: u8 = 135;
+
+/// Padding options of the hop-by-hop and destination options headers
+const OPTION_PAD1: u8 = 0;
+const OPTION_PADN: u8 = 1;
+
+/// Prefix used for stateful address compression
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Context {
+    pub prefix: Ipv6Addr,
+    /// Prefix length in bits
+    pub length: u8,
+}
+
+impl FromStr for Context {
+    type Err = String;
+
+    /// Parse a prefix as `fd00:db8::/64`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid prefix \"{}\"", text);
+        let (prefix, length) = text.split_once('/').ok_or_else(invalid)?;
+        let prefix = prefix.parse().map_err(|_| invalid())?;
+        let length = length
+            .parse()
+            .ok()
+            .filter(|&length| length <= 128)
+            .ok_or_else(invalid)?;
+        Ok(Context { prefix, length })
+    }
+}
+
+impl Context {
+    /// Replace the leading bits of `address` with the prefix
+    fn apply(&self, address: &mut [u8; 16]) {
+        let prefix = self.prefix.octets();
+        for bit in 0..usize::from(self.length) {
+            let mask = 0x80 >> (bit % 8);
+            address[bit / 8] = (address[bit / 8] & !mask) | (prefix[bit / 8] & mask);
+        }
+    }
+}
+
+/// Reasons a frame does not give an IPv6 packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecompressError {
+    /// Not a data frame
+    NotData,
+    /// The frame is secured and has to be decrypted first
+    Secured,
+    /// The payload is not a 6LoWPAN datagram
+    NotLowpan,
+    /// The frame ends early or uses reserved encodings
+    Malformed,
+    /// Stateful compression uses a context that is not set
+    UnknownContext(u8),
+    /// An address derived from the link-layer address, which the frame does not carry
+    NoLinkAddress,
+    /// Compressed next header that is not known
+    UnsupportedNextHeader,
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressError::NotData => write!(f, "Not a data frame"),
+            DecompressError::Secured => write!(f, "Secured frame"),
+            DecompressError::NotLowpan => write!(f, "Not a 6LoWPAN datagram"),
+            DecompressError::Malformed => write!(f, "Malformed datagram"),
+            DecompressError::UnknownContext(id) => write!(f, "Unknown context {}", id),
+            DecompressError::NoLinkAddress => write!(f, "Link-layer address missing"),
+            DecompressError::UnsupportedNextHeader => write!(f, "Unsupported next header"),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Result<u8, DecompressError> {
+        self.data
+            .get(self.offset)
+            .copied()
+            .ok_or(DecompressError::Malformed)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], DecompressError> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + length)
+            .ok_or(DecompressError::Malformed)?;
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecompressError> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecompressError> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.offset..];
+        self.offset = self.data.len();
+        rest
+    }
+}
+
+/// Link-layer addresses used to derive IPv6 addresses, from the MAC or the mesh header
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Link {
+    source: Option<Address>,
+    destination: Option<Address>,
+}
+
+/// Length fields and checksums filled in when the datagram is complete
+#[derive(Clone, Copy, Debug)]
+enum Fixup {
+    Ipv6 {
+        start: usize,
+    },
+    Udp {
+        start: usize,
+        /// Start of the IPv6 header, for the pseudo header
+        ipv6: usize,
+        checksum: bool,
+    },
+}
+
+/// Fragments of a datagram, identified by the link-layer addresses, the size and the tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct FragmentKey {
+    link: Link,
+    size: u16,
+    tag: u16,
+}
+
+struct Reassembly {
+    data: Vec<u8>,
+    received: Vec<bool>,
+    /// Set by the first fragment, which holds the compressed headers
+    fixups: Option<Vec<Fixup>>,
+    started: Duration,
+}
+
+/// Interface identifier derived from a link-layer address
+fn interface_identifier(address: Option<Address>) -> Result<[u8; 8], DecompressError> {
+    match address.ok_or(DecompressError::NoLinkAddress)? {
+        Address::Short(short) => {
+            let [high, low] = short.to_be_bytes();
+            Ok([0, 0, 0, 0xff, 0xfe, 0, high, low])
+        }
+        Address::Extended(extended) => {
+            let mut identifier = extended.to_be_bytes();
+            // Invert the universal/local bit
+            identifier[0] ^= 0x02;
+            Ok(identifier)
+        }
+    }
+}
+
+/// Unicast address with address mode 1 to 3, link-local or with the context prefix
+fn unicast_address(
+    reader: &mut Reader,
+    mode: u8,
+    context: Option<&Context>,
+    link: Option<Address>,
+) -> Result<[u8; 16], DecompressError> {
+    let mut address = [0u8; 16];
+    match mode {
+        1 => address[8..].copy_from_slice(reader.take(8)?),
+        2 => {
+            address[11] = 0xff;
+            address[12] = 0xfe;
+            address[14..].copy_from_slice(reader.take(2)?);
+        }
+        _ => address[8..].copy_from_slice(&interface_identifier(link)?),
+    }
+    match context {
+        Some(context) => context.apply(&mut address),
+        None => address[..2].copy_from_slice(&[0xfe, 0x80]),
+    }
+    Ok(address)
+}
+
+/// Address in a mesh header, a short or an extended address
+fn mesh_address(reader: &mut Reader, short: bool) -> Result<Address, DecompressError> {
+    Ok(match short {
+        true => Address::Short(reader.u16()?),
+        false => {
+            let mut octets = [0u8; 8];
+            octets.copy_from_slice(reader.take(8)?);
+            Address::Extended(u64::from_be_bytes(octets))
+        }
+    })
+}
+
+/// Sum of 16-bit words for the internet checksum
+fn checksum_sum(data: &[u8]) -> u32 {
+    data.chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)])))
+        .sum()
+}
+
+/// UDP checksum of the UDP header at `start` in the IPv6 packet starting at `ipv6`
+fn udp_checksum(packet: &[u8], start: usize, ipv6: usize) -> u16 {
+    let udp = &packet[start..];
+    let mut sum = checksum_sum(&packet[ipv6 + 8..ipv6 + IPV6_HEADER_LENGTH])
+        + checksum_sum(&(udp.len() as u32).to_be_bytes())
+        + u32::from(NEXT_HEADER_UDP)
+        + checksum_sum(&udp[..6])
+        + checksum_sum(&udp[8..]);
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    match !(sum as u16) {
+        0 => 0xffff,
+        checksum => checksum,
+    }
+}
+
+/// Fill in the elided lengths and checksums of a complete datagram
+fn complete(mut packet: Vec<u8>, fixups: &[Fixup]) -> Result<Vec<u8>, DecompressError> {
+    for fixup in fixups {
+        match *fixup {
+            Fixup::Ipv6 { start } => {
+                let length = packet
+                    .len()
+                    .checked_sub(start + IPV6_HEADER_LENGTH)
+                    .and_then(|length| u16::try_from(length).ok())
+                    .ok_or(DecompressError::Malformed)?;
+                packet[start + 4..start + 6].copy_from_slice(&length.to_be_bytes());
+            }
+            Fixup::Udp {
+                start,
+                ipv6,
+                checksum,
+            } => {
+                let length =
+                    u16::try_from(packet.len() - start).map_err(|_| DecompressError::Malformed)?;
+                packet[start + 4..start + 6].copy_from_slice(&length.to_be_bytes());
+                if checksum {
+                    let checksum = udp_checksum(&packet, start, ipv6);
+                    packet[start + 6..start + 8].copy_from_slice(&checksum.to_be_bytes());
+                }
+            }
+        }
+    }
+    Ok(packet)
+}
+
+/// Reassembles and decompresses 6LoWPAN datagrams
+#[derive(Default)]
+pub struct Decompressor {
+    contexts: [Option<Context>; 16],
+    reassembly: HashMap<FragmentKey, Reassembly>,
+}
+
+impl Decompressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set context `id`, 0 to 15
+    pub fn set_context(&mut self, id: u8, context: Context) {
+        self.contexts[usize::from(id & 0x0f)] = Some(context);
+    }
+
+    fn context(&self, id: u8) -> Result<&Context, DecompressError> {
+        self.contexts[usize::from(id)]
+            .as_ref()
+            .ok_or(DecompressError::UnknownContext(id))
+    }
+
+    /// Decompress the IPHC header and the following compressed headers
+    fn iphc(
+        &self,
+        reader: &mut Reader,
+        link: Link,
+        packet: &mut Vec<u8>,
+        fixups: &mut Vec<Fixup>,
+    ) -> Result<(), DecompressError> {
+        let first = reader.u8()?;
+        let second = reader.u8()?;
+        let (source_context, destination_context) = match second & 0x80 != 0 {
+            true => {
+                let identifiers = reader.u8()?;
+                (identifiers >> 4, identifiers & 0x0f)
+            }
+            false => (0, 0),
+        };
+        // The traffic class is carried as ECN and DSCP, the reverse of the IPv6 order
+        let (traffic_class, flow_label) = match (first >> 3) & 0b11 {
+            0b00 => {
+                let b = reader.take(4)?;
+                (
+                    b[0].rotate_left(2),
+                    u32::from_be_bytes([0, b[1] & 0x0f, b[2], b[3]]),
+                )
+            }
+            0b01 => {
+                let b = reader.take(3)?;
+                (
+                    (b[0] & 0xc0).rotate_left(2),
+                    u32::from_be_bytes([0, b[0] & 0x0f, b[1], b[2]]),
+                )
+            }
+            0b10 => (reader.u8()?.rotate_left(2), 0),
+            _ => (0, 0),
+        };
+        let next_header = match first & 0x04 != 0 {
+            true => None,
+            false => Some(reader.u8()?),
+        };
+        let hop_limit = match first & 0b11 {
+            0b00 => reader.u8()?,
+            0b01 => 1,
+            0b10 => 64,
+            _ => 255,
+        };
+
+        let source_mode = (second >> 4) & 0b11;
+        let source = match (second & 0x40 != 0, source_mode) {
+            (false, 0) => reader.take(16)?.try_into().unwrap(),
+            (false, mode) => unicast_address(reader, mode, None, link.source)?,
+            // The unspecified address
+            (true, 0) => [0u8; 16],
+            (true, mode) => {
+                let context = self.context(source_context)?;
+                unicast_address(reader, mode, Some(context), link.source)?
+            }
+        };
+
+        let destination_mode = second & 0b11;
+        let mut destination = [0u8; 16];
+        match (second & 0x08 != 0, second & 0x04 != 0, destination_mode) {
+            (false, false, 0) | (true, false, 0) => {
+                destination.copy_from_slice(reader.take(16)?);
+            }
+            (false, false, mode) => {
+                destination = unicast_address(reader, mode, None, link.destination)?;
+            }
+            (false, true, 0) => return Err(DecompressError::Malformed),
+            (false, true, mode) => {
+                let context = self.context(destination_context)?;
+                destination = unicast_address(reader, mode, Some(context), link.destination)?;
+            }
+            // ffXX::00XX:XXXX:XXXX
+            (true, false, 1) => {
+                let b = reader.take(6)?;
+                destination[0] = 0xff;
+                destination[1] = b[0];
+                destination[11..].copy_from_slice(&b[1..]);
+            }
+            // ffXX::00XX:XXXX
+            (true, false, 2) => {
+                let b = reader.take(4)?;
+                destination[0] = 0xff;
+                destination[1] = b[0];
+                destination[13..].copy_from_slice(&b[1..]);
+            }
+            // ff02::00XX
+            (true, false, _) => {
+                destination[..2].copy_from_slice(&[0xff, 0x02]);
+                destination[15] = reader.u8()?;
+            }
+            // Unicast prefix based ffXX:XXLL:PPPP:PPPP:PPPP:PPPP:XXXX:XXXX
+            (true, true, 0) => {
+                let context = self.context(destination_context)?;
+                let b = reader.take(6)?;
+                destination[0] = 0xff;
+                destination[1..3].copy_from_slice(&b[..2]);
+                destination[3] = context.length;
+                destination[4..12].copy_from_slice(&context.prefix.octets()[..8]);
+                destination[12..].copy_from_slice(&b[2..]);
+            }
+            (true, true, _) => return Err(DecompressError::Malformed),
+        }
+
+        let start = packet.len();
+        let version = 6 << 28 | u32::from(traffic_class) << 20 | flow_label;
+        packet.extend_from_slice(&version.to_be_bytes());
+        // The payload length is filled in when the datagram is complete
+        packet.extend_from_slice(&[0, 0, next_header.unwrap_or_default(), hop_limit]);
+        packet.extend_from_slice(&source);
+        packet.extend_from_slice(&destination);
+        fixups.push(Fixup::Ipv6 { start });
+        if next_header.is_none() {
+            packet[start + 6] = self.next_header(reader, link, packet, fixups, start)?;
+        }
+        Ok(())
+    }
+
+    /// Decompress an NHC compressed header, returns its next header value
+    fn next_header(
+        &self,
+        reader: &mut Reader,
+        link: Link,
+        packet: &mut Vec<u8>,
+        fixups: &mut Vec<Fixup>,
+        ipv6: usize,
+    ) -> Result<u8, DecompressError> {
+        let dispatch = reader.u8()?;
+        if dispatch & 0xf8 == 0xf0 {
+            let (source, destination) = match dispatch & 0b11 {
+                0b00 => (reader.u16()?, reader.u16()?),
+                0b01 => (reader.u16()?, 0xf000 | u16::from(reader.u8()?)),
+                0b10 => (0xf000 | u16::from(reader.u8()?), reader.u16()?),
+                _ => {
+                    let ports = reader.u8()?;
+                    (
+                        0xf0b0 | u16::from(ports >> 4),
+                        0xf0b0 | u16::from(ports & 0x0f),
+                    )
+                }
+            };
+            let checksum = match dispatch & 0x04 != 0 {
+                true => None,
+                false => Some(reader.u16()?),
+            };
+            let start = packet.len();
+            packet.extend_from_slice(&source.to_be_bytes());
+            packet.extend_from_slice(&destination.to_be_bytes());
+            // The length is always elided
+            packet.extend_from_slice(&[0, 0]);
+            packet.extend_from_slice(&checksum.unwrap_or_default().to_be_bytes());
+            fixups.push(Fixup::Udp {
+                start,
+                ipv6,
+                checksum: checksum.is_none(),
+            });
+            return Ok(NEXT_HEADER_UDP);
+        }
+        if dispatch & 0xf0 != 0xe0 {
+            return Err(DecompressError::UnsupportedNextHeader);
+        }
+        let protocol = match (dispatch >> 1) & 0b111 {
+            0 => NEXT_HEADER_HOP_BY_HOP,
+            1 => NEXT_HEADER_ROUTING,
+            2 => NEXT_HEADER_FRAGMENT,
+            3 => NEXT_HEADER_DESTINATION_OPTIONS,
+            4 => NEXT_HEADER_MOBILITY,
+            7 => {
+                self.iphc(reader, link, packet, fixups)?;
+                return Ok(NEXT_HEADER_IPV6);
+            }
+            _ => return Err(DecompressError::UnsupportedNextHeader),
+        };
+        let compressed_next = dispatch & 0x01 != 0;
+        let next = match compressed_next {
+            true => 0,
+            false => reader.u8()?,
+        };
+        let length = reader.u8()?;
+        let data = reader.take(usize::from(length))?;
+        let start = packet.len();
+        packet.extend_from_slice(&[next, 0]);
+        packet.extend_from_slice(data);
+        if protocol != NEXT_HEADER_FRAGMENT {
+            // Extension headers are a multiple of 8 octets, the padding is elided
+            let padding = (8 - (packet.len() - start) % 8) % 8;
+            let options =
+                protocol == NEXT_HEADER_HOP_BY_HOP || protocol == NEXT_HEADER_DESTINATION_OPTIONS;
+            match padding {
+                0 => {}
+                1 if options => packet.push(OPTION_PAD1),
+                _ if options => {
+                    packet.extend_from_slice(&[OPTION_PADN, padding as u8 - 2]);
+                    packet.resize(packet.len() + padding - 2, 0);
+                }
+                _ => packet.resize(packet.len() + padding, 0),
+            }
+            packet[start + 1] = ((packet.len() - start) / 8 - 1) as u8;
+        }
+        if compressed_next {
+            packet[start] = self.next_header(reader, link, packet, fixups, ipv6)?;
+        }
+        Ok(protocol)
+    }
+
+    /// Decompress the headers of an unfragmented datagram, or of a first fragment
+    fn datagram(
+        &self,
+        reader: &mut Reader,
+        link: Link,
+    ) -> Result<(Vec<u8>, Vec<Fixup>), DecompressError> {
+        let mut packet = Vec::new();
+        let mut fixups = Vec::new();
+        let dispatch = reader.peek()?;
+        if dispatch == DISPATCH_IPV6 {
+            reader.u8()?;
+        } else if dispatch & 0xe0 == 0x60 {
+            self.iphc(reader, link, &mut packet, &mut fixups)?;
+        } else {
+            return Err(DecompressError::NotLowpan);
+        }
+        packet.extend_from_slice(reader.rest());
+        Ok((packet, fixups))
+    }
+
+    /// Process a frame without FCS received at `timestamp`, returns an IPv6 packet when a
+    /// datagram is complete and `None` while waiting for more fragments
+    pub fn process(
+        &mut self,
+        frame: &[u8],
+        timestamp: Duration,
+    ) -> Result<Option<Vec<u8>>, DecompressError> {
+        let (header, mut offset) = mac::Header::parse(frame).ok_or(DecompressError::Malformed)?;
+        if header.frame_type != FrameType::Data {
+            return Err(DecompressError::NotData);
+        }
+        if header.security_enabled {
+            return Err(DecompressError::Secured);
+        }
+        if header.information_elements {
+            offset += mac::header_ies_length(&frame[offset..]).ok_or(DecompressError::Malformed)?;
+        }
+        self.reassembly.retain(|_, reassembly| {
+            timestamp.saturating_sub(reassembly.started) < REASSEMBLY_TIMEOUT
+        });
+
+        let mut reader = Reader {
+            data: &frame[offset..],
+            offset: 0,
+        };
+        let mut link = Link {
+            source: header.source,
+            destination: header.destination,
+        };
+        let mut dispatch = reader.peek().map_err(|_| DecompressError::NotLowpan)?;
+        if dispatch & 0xc0 == 0x80 {
+            reader.u8()?;
+            if dispatch & 0x0f == 0x0f {
+                // Hops left above 14
+                reader.u8()?;
+            }
+            link.source = Some(mesh_address(&mut reader, dispatch & 0x20 != 0)?);
+            link.destination = Some(mesh_address(&mut reader, dispatch & 0x10 != 0)?);
+            dispatch = reader.peek()?;
+        }
+        if dispatch == DISPATCH_BROADCAST {
+            reader.take(2)?;
+            dispatch = reader.peek()?;
+        }
+
+        let (key, offset, data, fixups) = match dispatch & 0xf8 {
+            0xc0 | 0xe0 => {
+                let b = reader.take(4)?;
+                let size = u16::from_be_bytes([b[0] & 0x07, b[1]]);
+                let tag = u16::from_be_bytes([b[2], b[3]]);
+                let key = FragmentKey { link, size, tag };
+                match dispatch & 0xf8 {
+                    0xc0 => {
+                        let (data, fixups) = self.datagram(&mut reader, link)?;
+                        (key, 0, data, Some(fixups))
+                    }
+                    _ => {
+                        let offset = usize::from(reader.u8()?) * 8;
+                        (key, offset, reader.rest().to_vec(), None)
+                    }
+                }
+            }
+            _ => {
+                let (packet, fixups) = self.datagram(&mut reader, link)?;
+                return complete(packet, &fixups).map(Some);
+            }
+        };
+
+        let size = usize::from(key.size);
+        if offset + data.len() > size {
+            return Err(DecompressError::Malformed);
+        }
+        let reassembly = self.reassembly.entry(key).or_insert_with(|| Reassembly {
+            data: vec![0; size],
+            received: vec![false; size],
+            fixups: None,
+            started: timestamp,
+        });
+        reassembly.data[offset..offset + data.len()].copy_from_slice(&data);
+        reassembly.received[offset..offset + data.len()].fill(true);
+        if fixups.is_some() {
+            reassembly.fixups = fixups;
+        }
+        if reassembly.fixups.is_none() || !reassembly.received.iter().all(|&received| received) {
+            return Ok(None);
+        }
+        let reassembly = self.reassembly.remove(&key).unwrap();
+        complete(reassembly.data, &reassembly.fixups.unwrap_or_default()).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(text: &str) -> [u8; 16] {
+        text.parse::<Ipv6Addr>().unwrap().octets()
+    }
+
+    /// Decompress a datagram from short address 0x0002 to 0x0003, with context 0
+    /// 2001:db8:1:2::/64 and context 1 fd00:1:2:3::/64
+    fn decompress(lowpan: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x41, 0x88, 0x01, 0xcd, 0xab, 0x03, 0x00, 0x02, 0x00];
+        frame.extend_from_slice(lowpan);
+        let mut decompressor = Decompressor::new();
+        decompressor.set_context(0, "2001:db8:1:2::/64".parse().unwrap());
+        decompressor.set_context(1, "fd00:1:2:3::/64".parse().unwrap());
+        decompressor
+            .process(&frame, Duration::ZERO)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn link_local_udp() {
+        // IPHC with all fields elided and addresses derived from the extended addresses,
+        // UDP with 4-bit ports and an elided checksum
+        let frame = [
+            0x41, 0xcc, 0x01, 0x34, 0x12, 0xff, 0xee, 0xdd, 0xcc, 0xbb, 0xaa, 0x99, 0x88, 0x77,
+            0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00, 0x7f, 0x33, 0xf7, 0x12, b'h', b'e', b'l',
+            b'l', b'o',
+        ];
+        let packet = Decompressor::new()
+            .process(&frame, Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&packet[..8], &[0x60, 0, 0, 0, 0x00, 0x0d, 17, 255]);
+        assert_eq!(packet[8..24], address("fe80::211:2233:4455:6677"));
+        assert_eq!(packet[24..40], address("fe80::8a99:aabb:ccdd:eeff"));
+        assert_eq!(
+            &packet[40..],
+            &[0xf0, 0xb1, 0xf0, 0xb2, 0x00, 0x0d, 0x1d, 0x58, b'h', b'e', b'l', b'l', b'o']
+        );
+    }
+
+    #[test]
+    fn stateful_and_multicast() {
+        // Source from context 0 and the short address, ff02::1, inline traffic class, flow
+        // label, next header and hop limit
+        let frame = [
+            0x41, 0x88, 0x02, 0xcd, 0xab, 0xff, 0xff, 0x01, 0x00, 0x60, 0xfb, 0x00, 0x81, 0x0a,
+            0xbc, 0xde, 0x3a, 0x40, 0x01, 0x80, 0x00, 0x12, 0x34,
+        ];
+        let mut decompressor = Decompressor::new();
+        assert_eq!(
+            decompressor.process(&frame, Duration::ZERO),
+            Err(DecompressError::UnknownContext(0))
+        );
+        decompressor.set_context(0, "fd00:db8::/64".parse().unwrap());
+        let packet = decompressor
+            .process(&frame, Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&packet[..8], &[0x60, 0x6a, 0xbc, 0xde, 0x00, 0x04, 58, 64]);
+        assert_eq!(packet[8..24], address("fd00:db8::ff:fe00:1"));
+        assert_eq!(packet[24..40], address("ff02::1"));
+        assert_eq!(&packet[40..], &[0x80, 0x00, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn fragments() {
+        let payload: Vec<u8> = (0..40).collect();
+        // Datagram of 88 octets, the first fragment carries the headers and 16 octets
+        let mut first = vec![
+            0x41, 0x88, 0x03, 0xcd, 0xab, 0x03, 0x00, 0x02, 0x00, 0xc0, 0x58, 0x00, 0x07, 0x7e,
+            0x33, 0xf0, 0x16, 0x33, 0x16, 0x33, 0xab, 0xcd,
+        ];
+        first.extend_from_slice(&payload[..16]);
+        let mut second = vec![
+            0x41, 0x88, 0x04, 0xcd, 0xab, 0x03, 0x00, 0x02, 0x00, 0xe0, 0x58, 0x00, 0x07, 0x08,
+        ];
+        second.extend_from_slice(&payload[16..]);
+
+        let mut decompressor = Decompressor::new();
+        assert_eq!(decompressor.process(&second, Duration::ZERO), Ok(None));
+        let packet = decompressor
+            .process(&first, Duration::from_millis(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.len(), 88);
+        assert_eq!(&packet[4..8], &[0x00, 0x30, 17, 64]);
+        assert_eq!(packet[8..24], address("fe80::ff:fe00:2"));
+        assert_eq!(packet[24..40], address("fe80::ff:fe00:3"));
+        assert_eq!(
+            &packet[40..48],
+            &[0x16, 0x33, 0x16, 0x33, 0x00, 0x30, 0xab, 0xcd]
+        );
+        assert_eq!(&packet[48..], &payload[..]);
+
+        // Fragments of an incomplete datagram are dropped after the timeout
+        assert_eq!(decompressor.process(&second, Duration::ZERO), Ok(None));
+        assert_eq!(decompressor.process(&first, REASSEMBLY_TIMEOUT), Ok(None));
+    }
+
+    #[test]
+    fn extension_header() {
+        // Hop-by-hop options header with a compressed next header, followed by UDP
+        let frame = [
+            0x41, 0x88, 0x05, 0xcd, 0xab, 0x03, 0x00, 0x02, 0x00, 0x7f, 0x33, 0xe1, 0x05, 0x3e,
+            0x03, 0x01, 0x02, 0x03, 0xf3, 0x12, 0x55, 0xaa,
+        ];
+        let packet = Decompressor::new()
+            .process(&frame, Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&packet[4..8], &[0x00, 0x10, NEXT_HEADER_HOP_BY_HOP, 255]);
+        assert_eq!(
+            &packet[40..48],
+            &[17, 0x00, 0x3e, 0x03, 0x01, 0x02, 0x03, OPTION_PAD1]
+        );
+        assert_eq!(
+            &packet[48..],
+            &[0xf0, 0xb1, 0xf0, 0xb2, 0x00, 0x08, 0x55, 0xaa]
+        );
+    }
+
+    #[test]
+    fn mesh_and_broadcast_headers() {
+        // Addresses are derived from the originator and final addresses of the mesh header
+        let frame = [
+            0x41, 0x88, 0x06, 0xcd, 0xab, 0x0a, 0x00, 0x09, 0x00, 0xb5, 0x00, 0x01, 0x00, 0x02,
+            0x50, 0x07, 0x7b, 0x33, 0x3b,
+        ];
+        let packet = Decompressor::new()
+            .process(&frame, Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&packet[4..8], &[0x00, 0x00, 59, 255]);
+        assert_eq!(packet[8..24], address("fe80::ff:fe00:1"));
+        assert_eq!(packet[24..40], address("fe80::ff:fe00:2"));
+    }
+
+    #[test]
+    fn traffic_class_and_flow_label() {
+        // RFC 6282 section 3.1.1, TF 00 to 11: ECN, DSCP and flow label inline, ECN and flow
+        // label, ECN and DSCP, all elided. Inline the ECN comes before the DSCP.
+        let cases: [(u8, &[u8], [u8; 4]); 4] = [
+            (0x63, &[0xae, 0x01, 0x23, 0x45], [0x6b, 0xa1, 0x23, 0x45]),
+            (0x6b, &[0x81, 0x23, 0x45], [0x60, 0x21, 0x23, 0x45]),
+            (0x73, &[0xae], [0x6b, 0xa0, 0x00, 0x00]),
+            (0x7b, &[], [0x60, 0x00, 0x00, 0x00]),
+        ];
+        for (first, inline, version) in cases {
+            let mut lowpan = vec![first, 0x33];
+            lowpan.extend_from_slice(inline);
+            lowpan.push(59);
+            let packet = decompress(&lowpan);
+            assert_eq!(packet[..4], version);
+            assert_eq!(&packet[4..8], &[0x00, 0x00, 59, 255]);
+        }
+    }
+
+    #[test]
+    fn hop_limit() {
+        // RFC 6282 section 3.1.1, HLIM 00 inline, then 1, 64 and 255
+        assert_eq!(decompress(&[0x78, 0x33, 59, 0x20])[7], 0x20);
+        assert_eq!(decompress(&[0x79, 0x33, 59])[7], 1);
+        assert_eq!(decompress(&[0x7a, 0x33, 59])[7], 64);
+        assert_eq!(decompress(&[0x7b, 0x33, 59])[7], 255);
+    }
+
+    #[test]
+    fn source_addresses() {
+        // RFC 6282 section 3.1.1, SAC and SAM, stateless then from context 0
+        let identifier = [0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77];
+        let cases: [(u8, &[u8], &str); 8] = [
+            (0x03, &address("2001:db8::1"), "2001:db8::1"),
+            (0x13, &identifier, "fe80::211:2233:4455:6677"),
+            (0x23, &[0x12, 0x34], "fe80::ff:fe00:1234"),
+            (0x33, &[], "fe80::ff:fe00:2"),
+            (0x43, &[], "::"),
+            (0x53, &identifier, "2001:db8:1:2:211:2233:4455:6677"),
+            (0x63, &[0x12, 0x34], "2001:db8:1:2:0:ff:fe00:1234"),
+            (0x73, &[], "2001:db8:1:2:0:ff:fe00:2"),
+        ];
+        for (second, inline, source) in cases {
+            let mut lowpan = vec![0x7b, second, 59];
+            lowpan.extend_from_slice(inline);
+            let packet = decompress(&lowpan);
+            assert_eq!(packet[8..24], address(source), "{}", source);
+            assert_eq!(packet[24..40], address("fe80::ff:fe00:3"));
+        }
+
+        // CID set, the source from context 1 and the destination from context 0
+        let packet = decompress(&[0x7b, 0xf7, 0x10, 59]);
+        assert_eq!(packet[8..24], address("fd00:1:2:3:0:ff:fe00:2"));
+        assert_eq!(packet[24..40], address("2001:db8:1:2:0:ff:fe00:3"));
+    }
+
+    #[test]
+    fn destination_addresses() {
+        // RFC 6282 section 3.1.1, M, DAC and DAM, stateless unicast, from context 0 and
+        // multicast
+        let cases: [(u8, &[u8], &str); 10] = [
+            (0x30, &address("2001:db8::3"), "2001:db8::3"),
+            (
+                0x31,
+                &[0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77],
+                "fe80::211:2233:4455:6677",
+            ),
+            (0x32, &[0x12, 0x34], "fe80::ff:fe00:1234"),
+            (
+                0x35,
+                &[0x02, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77],
+                "2001:db8:1:2:211:2233:4455:6677",
+            ),
+            (0x37, &[], "2001:db8:1:2:0:ff:fe00:3"),
+            (0x38, &address("ff05::1:3"), "ff05::1:3"),
+            (0x39, &[0x05, 0x00, 0x00, 0x01, 0x00, 0x03], "ff05::1:3"),
+            (0x3a, &[0x05, 0x01, 0x00, 0x03], "ff05::1:3"),
+            (0x3b, &[0x01], "ff02::1"),
+            // Unicast prefix based multicast, RFC 3306, with the prefix of context 0
+            (
+                0x3c,
+                &[0x3e, 0x00, 0x12, 0x34, 0x56, 0x78],
+                "ff3e:40:2001:db8:1:2:1234:5678",
+            ),
+        ];
+        for (second, inline, destination) in cases {
+            let mut lowpan = vec![0x7b, second, 59];
+            lowpan.extend_from_slice(inline);
+            let packet = decompress(&lowpan);
+            assert_eq!(packet[24..40], address(destination), "{}", destination);
+        }
+    }
+
+    #[test]
+    fn udp_ports_and_checksum() {
+        // RFC 6282 section 4.3.3, ports inline, the destination or the source port as
+        // 0xf0XX, both ports as 0xf0bX, then an elided checksum
+        let cases: [(&[u8], [u8; 8]); 5] = [
+            (
+                &[0xf0, 0x12, 0x34, 0x56, 0x78, 0xab, 0xcd],
+                [0x12, 0x34, 0x56, 0x78, 0x00, 0x08, 0xab, 0xcd],
+            ),
+            (
+                &[0xf1, 0x12, 0x34, 0x56, 0xab, 0xcd],
+                [0x12, 0x34, 0xf0, 0x56, 0x00, 0x08, 0xab, 0xcd],
+            ),
+            (
+                &[0xf2, 0x56, 0x12, 0x34, 0xab, 0xcd],
+                [0xf0, 0x56, 0x12, 0x34, 0x00, 0x08, 0xab, 0xcd],
+            ),
+            (
+                &[0xf3, 0x5a, 0xab, 0xcd],
+                [0xf0, 0xb5, 0xf0, 0xba, 0x00, 0x08, 0xab, 0xcd],
+            ),
+            (
+                &[0xf7, 0x5a],
+                [0xf0, 0xb5, 0xf0, 0xba, 0x00, 0x08, 0x00, 0x00],
+            ),
+        ];
+        for (udp, header) in cases {
+            let mut lowpan = vec![0x7f, 0x33];
+            lowpan.extend_from_slice(udp);
+            let packet = decompress(&lowpan);
+            assert_eq!(&packet[4..8], &[0x00, 0x08, NEXT_HEADER_UDP, 255]);
+            match udp[0] & 0x04 != 0 {
+                false => assert_eq!(packet[40..], header),
+                true => {
+                    assert_eq!(packet[40..46], header[..6]);
+                    // The pseudo header and UDP header, with the checksum, sum to 0xffff
+                    let mut sum = checksum_sum(&packet[8..40])
+                        + 8
+                        + u32::from(NEXT_HEADER_UDP)
+                        + checksum_sum(&packet[40..]);
+                    while sum > 0xffff {
+                        sum = (sum & 0xffff) + (sum >> 16);
+                    }
+                    assert_eq!(sum, 0xffff);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn not_lowpan() {
+        let mut decompressor = Decompressor::new();
+        assert_eq!(
+            decompressor.process(&[0x02, 0x00, 0x01], Duration::ZERO),
+            Err(DecompressError::NotData)
+        );
+        assert_eq!(
+            decompressor.process(
+                &[0x41, 0x88, 0x01, 0xcd, 0xab, 0x03, 0x00, 0x02, 0x00, 0x3f],
+                Duration::ZERO
+            ),
+            Err(DecompressError::NotLowpan)
+        );
+        assert_eq!(
+            "fd00:db8::/129".parse::<Context>(),
+            Err(String::from("invalid prefix \"fd00:db8::/129\""))
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Traffic summary of captured frames
+//!
+//! A [`Summary`] counts frames by type, by PAN and by source, and keeps the range of the
+//! signal strength and link quality of each source. Frames are added as they are captured or
+//! read from a capture file.
+use crate::{
+    mac::{self, Address, FrameType},
+    wire_format,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    time::Duration,
+};
+
+/// Period over which [`Summary::rate`] counts frames
+pub const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Minimum, maximum and mean of a series of values
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub min: i32,
+    pub max: i32,
+    sum: i64,
+    count: u64,
+}
+
+impl Range {
+    fn new(value: i32) -> Self {
+        Range {
+            min: value,
+            max: value,
+            sum: i64::from(value),
+            count: 1,
+        }
+    }
+
+    /// Extend `range` with `value`, if any
+    pub(crate) fn add(range: &mut Option<Range>, value: Option<i32>) {
+        match (range.as_mut(), value) {
+            (Some(range), Some(value)) => {
+                range.min = range.min.min(value);
+                range.max = range.max.max(value);
+                range.sum += i64::from(value);
+                range.count += 1;
+            }
+            (None, Some(value)) => *range = Some(Range::new(value)),
+            (_, None) => (),
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum as f64 / self.count as f64
+    }
+}
+
+/// Device sending frames, short addresses are only unique within a PAN
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Device {
+    pub pan_id: Option<u16>,
+    pub address: Address,
+}
+
+/// Frames sent by a device
+#[derive(Clone, Debug, PartialEq)]
+pub struct Source {
+    pub frames: u64,
+    /// Received signal strength in dBm / 1000
+    pub rssi: Option<Range>,
+    pub lqi: Option<Range>,
+    pub last_seen: Duration,
+}
+
+/// Frames within a PAN
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pan {
+    pub frames: u64,
+    pub beacons: u64,
+    /// Source and destination addresses, broadcasts excluded
+    pub devices: BTreeSet<Address>,
+}
+
+/// Counts of captured frames
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    pub frames: u64,
+    /// Frames too short for a MAC header
+    pub malformed: u64,
+    pub frame_types: HashMap<FrameType, u64>,
+    pub pans: BTreeMap<u16, Pan>,
+    pub sources: BTreeMap<Device, Source>,
+    /// Time of the first and the last frame
+    pub first: Option<Duration>,
+    pub last: Option<Duration>,
+    recent: VecDeque<Duration>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a frame without FCS captured at `timestamp`
+    pub fn add(&mut self, frame: &wire_format::Frame, timestamp: Duration) {
+        self.frames += 1;
+        self.first.get_or_insert(timestamp);
+        self.last = Some(timestamp);
+        self.recent.push_back(timestamp);
+        while self
+            .recent
+            .front()
+            .is_some_and(|&first| timestamp.saturating_sub(first) > RATE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+
+        let header = match mac::Header::parse(&frame.payload) {
+            Some((header, _)) => header,
+            None => {
+                self.malformed += 1;
+                return;
+            }
+        };
+        *self.frame_types.entry(header.frame_type).or_default() += 1;
+        let pan_id = header.source_pan_or_destination();
+        if let Some(pan_id) = pan_id {
+            let pan = self.pans.entry(pan_id).or_default();
+            pan.frames += 1;
+            if header.frame_type == FrameType::Beacon {
+                pan.beacons += 1;
+            }
+            pan.devices.extend(
+                [header.source, header.destination]
+                    .into_iter()
+                    .flatten()
+                    .filter(|&address| address != Address::BROADCAST),
+            );
+        }
+        if let Some(address) = header.source {
+            let source = self
+                .sources
+                .entry(Device { pan_id, address })
+                .or_insert(Source {
+                    frames: 0,
+                    rssi: None,
+                    lqi: None,
+                    last_seen: timestamp,
+                });
+            source.frames += 1;
+            source.last_seen = timestamp;
+            Range::add(&mut source.rssi, frame.received_signal_strength_indicator);
+            Range::add(&mut source.lqi, frame.link_quality_index.map(i32::from));
+        }
+    }
+
+    /// Frames per second over the [`RATE_WINDOW`] before `now`
+    pub fn rate(&self, now: Duration) -> f64 {
+        let frames = self
+            .recent
+            .iter()
+            .filter(|&&timestamp| now.saturating_sub(timestamp) <= RATE_WINDOW)
+            .count();
+        frames as f64 / RATE_WINDOW.as_secs_f64()
+    }
+
+    /// Mean frames per second from the first to the last frame
+    pub fn mean_rate(&self) -> f64 {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) if last > first => {
+                (self.frames - 1) as f64 / (last - first).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(payload: &[u8], rssi: i32, lqi: u8) -> wire_format::Frame {
+        wire_format::Frame {
+            channel: 15,
+            received_signal_strength_indicator: Some(rssi),
+            link_quality_index: Some(lqi),
+            payload: wire_format::Payload::from_slice(payload).unwrap(),
+        }
+    }
+
+    #[test]
+    fn count_frames() {
+        let mut summary = Summary::new();
+        let beacon = [
+            0x00, 0x80, 0x01, 0x62, 0x1a, 0x00, 0x00, 0xff, 0xcf, 0x00, 0x00,
+        ];
+        let data = [0x41, 0x88, 0x02, 0x62, 0x1a, 0x00, 0x00, 0x01, 0x00, 0xaa];
+        summary.add(&frame(&beacon, -40_000, 255), Duration::from_secs(10));
+        summary.add(&frame(&data, -60_000, 200), Duration::from_secs(11));
+        summary.add(&frame(&data, -50_000, 210), Duration::from_secs(12));
+        summary.add(
+            &frame(&[0x02, 0x00, 0x02], -45_000, 230),
+            Duration::from_secs(12),
+        );
+        summary.add(&frame(&[0x41], -45_000, 230), Duration::from_secs(14));
+
+        assert_eq!(summary.frames, 5);
+        assert_eq!(summary.malformed, 1);
+        assert_eq!(summary.frame_types[&FrameType::Data], 2);
+        assert_eq!(summary.frame_types[&FrameType::Acknowledgement], 1);
+        let pan = &summary.pans[&0x1a62];
+        assert_eq!((pan.frames, pan.beacons), (3, 1));
+        assert_eq!(
+            pan.devices.iter().copied().collect::<Vec<_>>(),
+            vec![Address::Short(0x0000), Address::Short(0x0001)]
+        );
+        let source = &summary.sources[&Device {
+            pan_id: Some(0x1a62),
+            address: Address::Short(0x0001),
+        }];
+        assert_eq!(source.frames, 2);
+        assert_eq!(source.last_seen, Duration::from_secs(12));
+        let rssi = source.rssi.unwrap();
+        assert_eq!(
+            (rssi.min, rssi.max, rssi.mean()),
+            (-60_000, -50_000, -55_000.0)
+        );
+        let lqi = source.lqi.unwrap();
+        assert_eq!((lqi.min, lqi.max, lqi.mean()), (200, 210, 205.0));
+
+        assert_eq!(summary.mean_rate(), 1.0);
+        assert_eq!(summary.rate(Duration::from_secs(14)), 1.0);
+        assert_eq!(summary.rate(Duration::from_secs(30)), 0.0);
+    }
+}
@@ -0,0 +1,225 @@
+//! IEEE 802.15.4 TAP encoding and decoding
+//!
+//! See <https://github.com/jkcko/ieee802.15.4-tap> for the format specification.
+use byteorder::{ByteOrder, LittleEndian};
+use ieee802154_sniffer_wire_format as wire_format;
+use std::time::Duration;
+
+/// Size of a buffer large enough for any TAP encoded frame
+pub const BUFFER_SIZE: usize = 512;
+
+const TLV_FCS_TYPE: u16 = 0;
+const TLV_RSSI: u16 = 1;
+const TLV_CHANNEL_PLAN: u16 = 3;
+const TLV_START_OF_FRAME: u16 = 5;
+const TLV_LQI: u16 = 10;
+
+/// Write a TLV header and a 32-bit value, returns the TLV size
+fn write_tlv(buffer: &mut [u8], tlv_type: u16, length: u16, value: u32) -> usize {
+    LittleEndian::write_u16(&mut buffer[0..2], tlv_type);
+    LittleEndian::write_u16(&mut buffer[2..4], length);
+    LittleEndian::write_u32(&mut buffer[4..8], value);
+    8
+}
+
+/// Encode a captured frame with a TAP header, returns the length of the encoded data
+pub fn encode(frame: &wire_format::Frame, buffer: &mut [u8; BUFFER_SIZE]) -> usize {
+    let mut offset = 0;
+    buffer[0] = 0; // version
+    buffer[1] = 0; // reserved
+    offset += 4; // header length is written last
+    offset += write_tlv(&mut buffer[offset..], TLV_FCS_TYPE, 1, 0); // None
+    offset += write_tlv(
+        &mut buffer[offset..],
+        TLV_CHANNEL_PLAN,
+        3,
+        u32::from(frame.channel),
+    );
+    if let Some(rssi) = frame.received_signal_strength_indicator {
+        let rssi = (rssi as f32) / 1000.0f32;
+        offset += write_tlv(&mut buffer[offset..], TLV_RSSI, 4, rssi.to_bits());
+    }
+    if let Some(lqi) = frame.link_quality_index {
+        offset += write_tlv(&mut buffer[offset..], TLV_LQI, 1, u32::from(lqi));
+    }
+    LittleEndian::write_u16(&mut buffer[2..4], offset as u16);
+    let length = frame.payload.len();
+    buffer[offset..offset + length].copy_from_slice(&frame.payload);
+    offset + length
+}
+
+/// Type and value of a TLV
+type Tlv<'a> = (u16, &'a [u8]);
+
+/// Header length and the TLVs of a TAP header
+fn header(data: &[u8]) -> Option<(usize, Vec<Tlv<'_>>)> {
+    if data.len() < 4 || data[0] != 0 {
+        return None;
+    }
+    let header_length = usize::from(LittleEndian::read_u16(&data[2..4]));
+    if header_length < 4 || header_length > data.len() {
+        return None;
+    }
+    let mut tlvs = Vec::new();
+    let mut offset = 4;
+    while offset + 4 <= header_length {
+        let tlv_type = LittleEndian::read_u16(&data[offset..offset + 2]);
+        let length = usize::from(LittleEndian::read_u16(&data[offset + 2..offset + 4]));
+        tlvs.push((tlv_type, data.get(offset + 4..offset + 4 + length)?));
+        // TLV values are padded to 32-bit alignment
+        offset += 4 + ((length + 3) & !3);
+    }
+    Some((header_length, tlvs))
+}
+
+/// Decode a TAP encoded frame, the FCS is stripped when the header says it is present
+///
+/// Returns `None` when the header is invalid or the frame does not fit a payload. The channel
+/// is 0 when the header has no channel TLV.
+pub fn decode(data: &[u8]) -> Option<wire_format::Frame> {
+    let (header_length, tlvs) = header(data)?;
+    let mut frame = wire_format::Frame {
+        channel: 0,
+        received_signal_strength_indicator: None,
+        link_quality_index: None,
+        payload: wire_format::Payload::new(),
+    };
+    let mut fcs_length = 0;
+    for (tlv_type, value) in tlvs {
+        match (tlv_type, value.len()) {
+            (TLV_FCS_TYPE, 1) => {
+                fcs_length = match value[0] {
+                    1 => 2,
+                    2 => 4,
+                    _ => 0,
+                }
+            }
+            (TLV_RSSI, 4) => {
+                let rssi = LittleEndian::read_f32(value);
+                frame.received_signal_strength_indicator = Some((rssi * 1000.0).round() as i32);
+            }
+            (TLV_CHANNEL_PLAN, 3) => {
+                frame.channel = u8::try_from(LittleEndian::read_u16(value)).ok()?;
+            }
+            (TLV_LQI, 1) => frame.link_quality_index = Some(value[0]),
+            _ => (),
+        }
+    }
+    let payload = &data[header_length..];
+    let payload = &payload[..payload.len().checked_sub(fcs_length)?];
+    frame.payload = wire_format::Payload::from_slice(payload).ok()?;
+    Some(frame)
+}
+
+/// Start of frame time of a TAP encoded frame, from the clock of the capturing device
+///
+/// `None` when the header has no start of frame timestamp TLV.
+pub fn start_of_frame(data: &[u8]) -> Option<Duration> {
+    let (_, tlvs) = header(data)?;
+    tlvs.into_iter().find_map(|(tlv_type, value)| {
+        (tlv_type == TLV_START_OF_FRAME && value.len() == 8)
+            .then(|| Duration::from_nanos(LittleEndian::read_u64(value)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_frame() {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let frame = wire_format::Frame {
+            channel: 15,
+            received_signal_strength_indicator: Some(-60_000),
+            link_quality_index: Some(0xd4),
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        };
+        let length = encode(&frame, &mut buffer);
+        assert_eq!(
+            &buffer[..length],
+            &[
+                0x00, 0x00, 0x24, 0x00, // header
+                0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, // FCS type
+                0x03, 0x00, 0x03, 0x00, 0x0f, 0x00, 0x00, 0x00, // channel
+                0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x70, 0xc2, // RSSI
+                0x0a, 0x00, 0x01, 0x00, 0xd4, 0x00, 0x00, 0x00, // LQI
+                0x02, 0x00, 0x04, // payload
+            ]
+        );
+    }
+
+    #[test]
+    fn encode_frame_without_metadata() {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let frame = wire_format::Frame {
+            channel: 26,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        };
+        let length = encode(&frame, &mut buffer);
+        assert_eq!(
+            &buffer[..length],
+            &[
+                0x00, 0x00, 0x14, 0x00, // header
+                0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, // FCS type
+                0x03, 0x00, 0x03, 0x00, 0x1a, 0x00, 0x00, 0x00, // channel
+                0x02, 0x00, 0x04, // payload
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_frame() {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let frame = wire_format::Frame {
+            channel: 15,
+            received_signal_strength_indicator: Some(-60_000),
+            link_quality_index: Some(0xd4),
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        };
+        let length = encode(&frame, &mut buffer);
+        assert_eq!(decode(&buffer[..length]), Some(frame));
+    }
+
+    #[test]
+    fn decode_frame_with_fcs() {
+        let data = [
+            0x00, 0x00, 0x0c, 0x00, // header
+            0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, // FCS type, 16-bit
+            0x02, 0x00, 0x04, 0x9c, 0xf3, // payload and FCS
+        ];
+        let frame = decode(&data).unwrap();
+        assert_eq!(frame.channel, 0);
+        assert_eq!(frame.received_signal_strength_indicator, None);
+        assert_eq!(&frame.payload[..], &[0x02, 0x00, 0x04]);
+    }
+
+    #[test]
+    fn start_of_frame_timestamp() {
+        let data = [
+            0x00, 0x00, 0x10, 0x00, // header
+            0x05, 0x00, 0x08, 0x00, 0x78, 0xe0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, // SOF
+            0x02, 0x00, 0x04, // payload
+        ];
+        assert_eq!(start_of_frame(&data), Some(Duration::from_micros(123)));
+        assert_eq!(&decode(&data).unwrap().payload[..], &[0x02, 0x00, 0x04]);
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let frame = decode(&data).unwrap();
+        let length = encode(&frame, &mut buffer);
+        assert_eq!(start_of_frame(&buffer[..length]), None);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert_eq!(decode(&[0x00, 0x00]), None);
+        // Header longer than the data
+        assert_eq!(decode(&[0x00, 0x00, 0x10, 0x00, 0x02, 0x00, 0x04]), None);
+        // Truncated TLV
+        assert_eq!(
+            decode(&[0x00, 0x00, 0x08, 0x00, 0x03, 0x00, 0x03, 0x00]),
+            None
+        );
+    }
+}
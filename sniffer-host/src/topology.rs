@@ -0,0 +1,281 @@
+//! Network topology and device inventory
+//!
+//! A [`Topology`] is built from captured frames. It lists the PANs with their coordinators
+//! and channels, the devices with the extended addresses learned for their short addresses,
+//! and the links between devices with the signal strength and link quality of the frames
+//! received on each link.
+//!
+//! Short addresses are mapped to extended addresses from successful association responses
+//! and from the IEEE addresses carried in Zigbee network headers of data frames. The PAN
+//! coordinator is the source of beacons with the PAN coordinator bit set.
+use crate::{
+    mac::{self, Address, FrameType},
+    summary::{Device, Range},
+    wire_format,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+/// MAC command identifier of association responses
+const ASSOCIATION_RESPONSE: u8 = 0x02;
+/// Superframe specification bit set in beacons of the PAN coordinator
+const PAN_COORDINATOR: u16 = 1 << 14;
+
+/// Zigbee network header frame control fields
+const ZIGBEE_PROTOCOL_VERSION: u16 = 2;
+const ZIGBEE_DESTINATION_IEEE: u16 = 1 << 11;
+const ZIGBEE_SOURCE_IEEE: u16 = 1 << 12;
+
+/// A PAN seen in the capture
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pan {
+    pub frames: u64,
+    pub beacons: u64,
+    pub coordinator: Option<Address>,
+    /// Channels the PAN was seen on, from captures that record the channel
+    pub channels: BTreeSet<u8>,
+}
+
+/// A device seen as a source or a destination
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Node {
+    /// Frames sent by the device
+    pub frames: u64,
+    /// Extended address learned for a short address
+    pub extended: Option<u64>,
+    pub first_seen: Option<Duration>,
+    pub last_seen: Option<Duration>,
+}
+
+/// Frames from one device to another
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Link {
+    pub frames: u64,
+    /// Received signal strength in dBm / 1000
+    pub rssi: Option<Range>,
+    pub lqi: Option<Range>,
+}
+
+/// Topology of the observed networks
+#[derive(Clone, Debug, Default)]
+pub struct Topology {
+    pub pans: BTreeMap<u16, Pan>,
+    pub nodes: BTreeMap<Device, Node>,
+    /// Links by source and destination, broadcasts excluded
+    pub links: BTreeMap<(Device, Device), Link>,
+}
+
+/// Short addresses and IEEE addresses of a Zigbee network header
+fn zigbee_addresses(payload: &[u8]) -> Vec<(u16, u64)> {
+    let mut addresses = Vec::new();
+    if payload.len() < 8 {
+        return addresses;
+    }
+    let control = u16::from_le_bytes([payload[0], payload[1]]);
+    if control & 0b11 > 1 || (control >> 2) & 0x0f != ZIGBEE_PROTOCOL_VERSION {
+        return addresses;
+    }
+    let destination = u16::from_le_bytes([payload[2], payload[3]]);
+    let source = u16::from_le_bytes([payload[4], payload[5]]);
+    let mut ieee = payload[8..].chunks_exact(8).map(|octets| {
+        let mut address = [0u8; 8];
+        address.copy_from_slice(octets);
+        u64::from_le_bytes(address)
+    });
+    if control & ZIGBEE_DESTINATION_IEEE != 0 {
+        match ieee.next() {
+            Some(address) => addresses.push((destination, address)),
+            None => return addresses,
+        }
+    }
+    if control & ZIGBEE_SOURCE_IEEE != 0 {
+        if let Some(address) = ieee.next() {
+            addresses.push((source, address));
+        }
+    }
+    addresses
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn node(&mut self, device: Device) -> &mut Node {
+        self.nodes.entry(device).or_default()
+    }
+
+    /// Record the extended address of a short address in a PAN
+    fn learn(&mut self, pan_id: u16, short: u16, extended: u64) {
+        // Broadcast and "no short address"
+        if short < 0xfffe {
+            self.node(Device {
+                pan_id: Some(pan_id),
+                address: Address::Short(short),
+            })
+            .extended = Some(extended);
+        }
+    }
+
+    /// Add a frame without FCS captured at `timestamp`
+    pub fn add(&mut self, frame: &wire_format::Frame, timestamp: Duration) {
+        let Some((header, length)) = mac::Header::parse(&frame.payload) else {
+            return;
+        };
+        let payload = &frame.payload[length..];
+        let source_pan = header.source_pan_or_destination();
+        let destination_pan = header.destination_pan.or(header.source_pan);
+
+        if let Some(pan_id) = source_pan {
+            let pan = self.pans.entry(pan_id).or_default();
+            pan.frames += 1;
+            if frame.channel != 0 {
+                pan.channels.insert(frame.channel);
+            }
+            if header.frame_type == FrameType::Beacon {
+                pan.beacons += 1;
+                let superframe = payload
+                    .get(..2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                    .unwrap_or_default();
+                if !header.security_enabled && superframe & PAN_COORDINATOR != 0 {
+                    pan.coordinator = header.source;
+                }
+            }
+        }
+
+        if !header.security_enabled && !header.information_elements {
+            match (header.frame_type, header.destination, destination_pan) {
+                // Short address followed by the association status, 0 for success
+                (FrameType::Command, Some(Address::Extended(device)), Some(pan_id))
+                    if payload.len() >= 4
+                        && payload[0] == ASSOCIATION_RESPONSE
+                        && payload[3] == 0 =>
+                {
+                    let short = u16::from_le_bytes([payload[1], payload[2]]);
+                    self.learn(pan_id, short, device);
+                }
+                (FrameType::Data, _, Some(pan_id)) => {
+                    for (short, extended) in zigbee_addresses(payload) {
+                        self.learn(pan_id, short, extended);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let source = header.source.map(|address| Device {
+            pan_id: source_pan,
+            address,
+        });
+        let destination = header
+            .destination
+            .filter(|&address| address != Address::BROADCAST)
+            .map(|address| Device {
+                pan_id: destination_pan,
+                address,
+            });
+        for device in [source, destination].into_iter().flatten() {
+            let node = self.node(device);
+            node.first_seen.get_or_insert(timestamp);
+            node.last_seen = Some(timestamp);
+        }
+        if let Some(source) = source {
+            self.node(source).frames += 1;
+            if let Some(destination) = destination {
+                let link = self.links.entry((source, destination)).or_default();
+                link.frames += 1;
+                Range::add(&mut link.rssi, frame.received_signal_strength_indicator);
+                Range::add(&mut link.lqi, frame.link_quality_index.map(i32::from));
+            }
+        }
+    }
+
+    /// Extended address of a device, its own or the one learned for its short address
+    pub fn extended_address(&self, device: &Device) -> Option<u64> {
+        match device.address {
+            Address::Extended(address) => Some(address),
+            Address::Short(_) => self.nodes.get(device).and_then(|node| node.extended),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(payload: &[u8], rssi: i32) -> wire_format::Frame {
+        wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: Some(rssi),
+            link_quality_index: Some(255),
+            payload: wire_format::Payload::from_slice(payload).unwrap(),
+        }
+    }
+
+    fn short(address: u16) -> Device {
+        Device {
+            pan_id: Some(0x1a62),
+            address: Address::Short(address),
+        }
+    }
+
+    #[test]
+    fn coordinator_and_association() {
+        let mut topology = Topology::new();
+        // Beacon from the PAN coordinator
+        let beacon = [
+            0x00, 0x80, 0x01, 0x62, 0x1a, 0x00, 0x00, 0xff, 0xcf, 0x00, 0x00,
+        ];
+        topology.add(&frame(&beacon, -40_000), Duration::from_secs(1));
+        // Association response from the coordinator, short address 0x3c1f
+        let response = [
+            0x63, 0xdc, 0x02, 0x62, 0x1a, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0x11,
+            0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x02, 0x1f, 0x3c, 0x00,
+        ];
+        topology.add(&frame(&response, -41_000), Duration::from_secs(2));
+
+        let pan = &topology.pans[&0x1a62];
+        assert_eq!(pan.coordinator, Some(Address::Short(0x0000)));
+        assert_eq!(pan.channels, BTreeSet::from([20]));
+        assert_eq!((pan.frames, pan.beacons), (2, 1));
+        assert_eq!(
+            topology.extended_address(&short(0x3c1f)),
+            Some(0x0102030405060708)
+        );
+    }
+
+    #[test]
+    fn zigbee_network_header() {
+        let mut topology = Topology::new();
+        // Data from 0x3c1f to 0x0000, network header with the source IEEE address
+        let data = [
+            0x41, 0x88, 0x05, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x08, 0x10, 0x00, 0x00, 0x1f,
+            0x3c, 0x1e, 0x42, 0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01, 0xaa,
+        ];
+        topology.add(&frame(&data, -70_000), Duration::from_secs(1));
+        topology.add(&frame(&data, -60_000), Duration::from_secs(2));
+        // Acknowledgements have no addresses
+        topology.add(&frame(&[0x02, 0x00, 0x05], -40_000), Duration::from_secs(2));
+
+        assert_eq!(
+            topology.extended_address(&short(0x3c1f)),
+            Some(0x0123456789abcdef)
+        );
+        assert_eq!(topology.extended_address(&short(0x0000)), None);
+        let source = &topology.nodes[&short(0x3c1f)];
+        assert_eq!(source.frames, 2);
+        assert_eq!(source.first_seen, Some(Duration::from_secs(1)));
+        assert_eq!(topology.nodes[&short(0x0000)].frames, 0);
+        let link = &topology.links[&(short(0x3c1f), short(0x0000))];
+        assert_eq!(link.frames, 2);
+        let rssi = link.rssi.unwrap();
+        assert_eq!(
+            (rssi.min, rssi.max, rssi.mean()),
+            (-70_000, -60_000, -65_000.0)
+        );
+        assert_eq!(topology.links.len(), 1);
+    }
+}
@@ -0,0 +1,173 @@
+//! Byte transports for talking to a sniffer device
+//!
+//! A sniffer is either connected through a local serial port or through a TCP socket, for
+//! example a serial-to-TCP bridge on a remote host.
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Maximum time to wait for a TCP connection to be established
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// URI prefix selecting the TCP transport
+pub const TCP_PREFIX: &str = "tcp://";
+
+/// Bidirectional byte stream to a sniffer device
+///
+/// Reads are expected to block for at most the timeout given when the transport was
+/// opened and then fail with [`std::io::ErrorKind::TimedOut`] or
+/// [`std::io::ErrorKind::WouldBlock`].
+pub trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+/// Errors where a read is retried, a timeout or a read interrupted by a signal
+pub fn is_transient(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::TimedOut
+            | std::io::ErrorKind::WouldBlock
+            | std::io::ErrorKind::Interrupted
+    )
+}
+
+/// Open a serial port transport, sniffers use [`wire_format::BAUD_RATE`] unless reconfigured
+pub fn open_serial(
+    name: &str,
+    baud_rate: u32,
+    timeout: Duration,
+) -> Result<Box<dyn serialport::SerialPort>, serialport::Error> {
+    let port = serialport::new(name, baud_rate).timeout(timeout).open()?;
+    let _ = port.clear(serialport::ClearBuffer::All);
+    Ok(port)
+}
+
+/// Open a TCP transport, `address` is on the form `host:port`
+pub fn open_tcp(address: &str, timeout: Duration) -> std::io::Result<TcpStream> {
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or(std::io::ErrorKind::AddrNotAvailable)?;
+    let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+/// Open a transport by URI, either `tcp://host:port` or a serial port name
+pub fn open(uri: &str, timeout: Duration) -> Result<Box<dyn Transport>, crate::Error> {
+    open_with_baud_rate(uri, wire_format::BAUD_RATE, timeout)
+}
+
+/// Open a transport by URI, a serial port is opened with `baud_rate`
+pub fn open_with_baud_rate(
+    uri: &str,
+    baud_rate: u32,
+    timeout: Duration,
+) -> Result<Box<dyn Transport>, crate::Error> {
+    match uri.strip_prefix(TCP_PREFIX) {
+        Some(address) => Ok(Box::new(open_tcp(address, timeout)?)),
+        None => Ok(Box::new(open_serial(uri, baud_rate, timeout)?)),
+    }
+}
+
+/// USB serial number of the named serial port, if it is a USB port
+pub fn usb_serial_number(name: &str) -> Option<String> {
+    serialport::available_ports()
+        .ok()?
+        .into_iter()
+        .find(|port| port.port_name == name)
+        .and_then(|port| match port.port_type {
+            serialport::SerialPortType::UsbPort(usb_port) => usb_port.serial_number,
+            _ => None,
+        })
+}
+
+#[cfg(any(test, feature = "mock"))]
+pub mod mock {
+    //! In-memory transport for testing without hardware, enabled with the `mock` feature
+    use std::{
+        collections::VecDeque,
+        io::{Read, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    /// Transport backed by shared in-memory buffers
+    ///
+    /// Data pushed with [`MockTransport::push_rx`] is returned by reads, data written is
+    /// collected and returned by [`MockTransport::take_tx`]. Reads with no data pending
+    /// fail with a timeout like a serial port would.
+    #[derive(Clone, Default)]
+    pub struct MockTransport {
+        rx: Arc<Mutex<VecDeque<u8>>>,
+        stream: Arc<Mutex<Vec<u8>>>,
+        tx: Arc<Mutex<Vec<u8>>>,
+        disconnected: Arc<AtomicBool>,
+    }
+
+    impl MockTransport {
+        /// Queue data to be read from the transport
+        pub fn push_rx(&self, data: &[u8]) {
+            self.rx.lock().unwrap().extend(data);
+        }
+
+        /// Read `data` over and over once the queued data has been read, like a device that
+        /// never stops sending
+        pub fn stream_rx(&self, data: &[u8]) {
+            *self.stream.lock().unwrap() = data.to_vec();
+        }
+
+        /// Take the data written to the transport
+        pub fn take_tx(&self) -> Vec<u8> {
+            std::mem::take(&mut *self.tx.lock().unwrap())
+        }
+
+        /// Make all following reads and writes fail as if the device was unplugged
+        pub fn disconnect(&self) {
+            self.disconnected.store(true, Ordering::Relaxed);
+        }
+
+        fn check_connected(&self) -> std::io::Result<()> {
+            if self.disconnected.load(Ordering::Relaxed) {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for MockTransport {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            self.check_connected()?;
+            let mut rx = self.rx.lock().unwrap();
+            if rx.is_empty() {
+                rx.extend(self.stream.lock().unwrap().iter());
+            }
+            if rx.is_empty() {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+            let length = rx.len().min(buffer.len());
+            for (target, source) in buffer.iter_mut().zip(rx.drain(..length)) {
+                *target = source;
+            }
+            Ok(length)
+        }
+    }
+
+    impl Write for MockTransport {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.check_connected()?;
+            self.tx.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! ZigBee Encapsulation Protocol (ZEP) version 2 output
+//!
+//! Captured frames are sent as ZEP data packets over UDP, the format understood by Wireshark's
+//! ZEP dissector. The frame is sent with a computed FCS and the header in LQI mode.
+use crate::fcs;
+use byteorder::{BigEndian, ByteOrder};
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+/// UDP port assigned to ZEP
+pub const DEFAULT_PORT: u16 = 17754;
+
+const HEADER_SIZE: usize = 32;
+
+/// Size of a buffer large enough for any ZEP encoded frame
+pub const BUFFER_SIZE: usize = 512;
+
+const PREAMBLE: &[u8; 2] = b"EX";
+const VERSION: u8 = 2;
+const TYPE_DATA: u8 = 1;
+const MODE_LQI: u8 = 0;
+
+/// Seconds between the NTP epoch, 1900-01-01, and the Unix epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Encode a captured frame with a ZEP header, returns the length of the encoded data
+///
+/// `timestamp` is the time since the Unix epoch, it is encoded as a NTP timestamp.
+pub fn encode(
+    frame: &wire_format::Frame,
+    device_id: u16,
+    sequence: u32,
+    timestamp: Duration,
+    buffer: &mut [u8; BUFFER_SIZE],
+) -> usize {
+    buffer[0..2].copy_from_slice(PREAMBLE);
+    buffer[2] = VERSION;
+    buffer[3] = TYPE_DATA;
+    buffer[4] = frame.channel;
+    BigEndian::write_u16(&mut buffer[5..7], device_id);
+    buffer[7] = MODE_LQI;
+    buffer[8] = frame.link_quality_index.unwrap_or(0);
+    let seconds = timestamp.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (u64::from(timestamp.subsec_nanos()) << 32) / 1_000_000_000;
+    BigEndian::write_u32(&mut buffer[9..13], seconds as u32);
+    BigEndian::write_u32(&mut buffer[13..17], fraction as u32);
+    BigEndian::write_u32(&mut buffer[17..21], sequence);
+    buffer[21..31].fill(0); // reserved
+    let length = frame.payload.len();
+    buffer[HEADER_SIZE..HEADER_SIZE + length].copy_from_slice(&frame.payload);
+    let length = fcs::append(&mut buffer[HEADER_SIZE..], length);
+    buffer[31] = length as u8;
+    HEADER_SIZE + length
+}
+
+/// Parse a UDP destination, using the ZEP port if none is given
+fn destination(address: &str) -> std::io::Result<SocketAddr> {
+    let with_port = match address.parse::<std::net::IpAddr>() {
+        Ok(ip) => return Ok(SocketAddr::new(ip, DEFAULT_PORT)),
+        Err(_) if !address.contains(':') => format!("{}:{}", address, DEFAULT_PORT),
+        Err(_) => address.to_string(),
+    };
+    with_port
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::ErrorKind::AddrNotAvailable.into())
+}
+
+/// Sends captured frames as ZEP packets to a UDP destination
+pub struct ZepSender {
+    socket: UdpSocket,
+    device_id: u16,
+    sequence: u32,
+    buffer: [u8; BUFFER_SIZE],
+}
+
+impl ZepSender {
+    /// Create a sender for `address`, as `host` or `host:port`
+    pub fn new(address: &str, device_id: u16) -> std::io::Result<Self> {
+        let destination = destination(address)?;
+        let local: SocketAddr = if destination.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(destination)?;
+        Ok(Self {
+            socket,
+            device_id,
+            sequence: 0,
+            buffer: [0u8; BUFFER_SIZE],
+        })
+    }
+
+    /// Send a captured frame received at `timestamp`
+    pub fn send(&mut self, frame: &wire_format::Frame, timestamp: Duration) -> std::io::Result<()> {
+        self.sequence = self.sequence.wrapping_add(1);
+        let length = encode(
+            frame,
+            self.device_id,
+            self.sequence,
+            timestamp,
+            &mut self.buffer,
+        );
+        self.socket.send(&self.buffer[..length])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> wire_format::Frame {
+        wire_format::Frame {
+            channel: 15,
+            received_signal_strength_indicator: Some(-60_000),
+            link_quality_index: Some(0xd4),
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        }
+    }
+
+    const SAMPLE: [u8; 37] = [
+        0x45, 0x58, 0x02, 0x01, // preamble, version, type
+        0x0f, 0x12, 0x34, 0x00, 0xd4, // channel, device id, mode, LQI
+        0xe8, 0xfe, 0x6f, 0x80, 0x80, 0x00, 0x00, 0x00, // timestamp
+        0x00, 0x00, 0x00, 0x07, // sequence
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // reserved
+        0x05, // length
+        0x02, 0x00, 0x04, 0x9c, 0xf3, // frame with FCS
+    ];
+
+    #[test]
+    fn encode_frame() {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let timestamp = Duration::new(1_700_000_000, 500_000_000);
+        let length = encode(&frame(), 0x1234, 7, timestamp, &mut buffer);
+        assert_eq!(&buffer[..length], &SAMPLE[..]);
+    }
+
+    #[test]
+    fn encode_frame_without_lqi() {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let frame = wire_format::Frame {
+            channel: 26,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap(),
+        };
+        let length = encode(&frame, 0x0001, 1, Duration::ZERO, &mut buffer);
+        assert_eq!(
+            &buffer[..HEADER_SIZE],
+            &[
+                0x45, 0x58, 0x02, 0x01, 0x1a, 0x00, 0x01, 0x00, 0x00, // header start
+                0x83, 0xaa, 0x7e, 0x80, 0x00, 0x00, 0x00, 0x00, // Unix epoch
+                0x00, 0x00, 0x00, 0x01, // sequence
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // reserved
+                0x05, // length
+            ]
+        );
+        assert_eq!(length, HEADER_SIZE + 5);
+    }
+
+    #[test]
+    fn send_frames() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let address = receiver.local_addr().unwrap().to_string();
+        let mut sender = ZepSender::new(&address, 0x1234).unwrap();
+        let timestamp = Duration::new(1_700_000_000, 500_000_000);
+        for _ in 0..7 {
+            sender.send(&frame(), timestamp).unwrap();
+        }
+        let mut buffer = [0u8; 128];
+        for sequence in 1..=7u8 {
+            let length = receiver.recv(&mut buffer).unwrap();
+            let mut expected = SAMPLE;
+            expected[20] = sequence;
+            assert_eq!(&buffer[..length], &expected[..]);
+        }
+    }
+
+    #[test]
+    fn destination_port() {
+        assert_eq!(
+            destination("192.168.1.2").unwrap(),
+            "192.168.1.2:17754".parse().unwrap()
+        );
+        assert_eq!(
+            destination("192.168.1.2:4000").unwrap(),
+            "192.168.1.2:4000".parse().unwrap()
+        );
+        assert_eq!(destination("::1").unwrap(), "[::1]:17754".parse().unwrap());
+    }
+}
@@ -0,0 +1,297 @@
+//! Remote sniffer server
+//!
+//! Relays the serial wire format between a local sniffer and any number of TCP clients.
+//! Captured frames are sent to every client while only one client at a time, the first to
+//! send a command, controls the sniffer.
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Read timeout for the serial port and client sockets, bounds the time to notice a stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Clients that do not accept data within this time are disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    SerialPort(serialport::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<serialport::Error> for Error {
+    fn from(error: serialport::Error) -> Self {
+        Error::SerialPort(error)
+    }
+}
+
+/// Local sniffer connection, split into a reading and a writing half
+pub struct Sniffer {
+    pub reader: Box<dyn Read + Send>,
+    pub writer: Box<dyn Write + Send>,
+}
+
+impl Sniffer {
+    /// Open a sniffer on a serial port
+    pub fn open_serial(name: &str) -> Result<Self, Error> {
+        let port = serialport::new(name, wire_format::BAUD_RATE)
+            .timeout(POLL_INTERVAL)
+            .open()?;
+        let _ = port.clear(serialport::ClearBuffer::All);
+        let writer = port.try_clone()?;
+        Ok(Self {
+            reader: Box::new(port),
+            writer: Box::new(writer),
+        })
+    }
+}
+
+struct Client {
+    id: u64,
+    stream: TcpStream,
+}
+
+struct Shared {
+    name: String,
+    device: Mutex<Box<dyn Write + Send>>,
+    clients: Mutex<Vec<Client>>,
+    controller: Mutex<Option<u64>>,
+    next_id: AtomicU64,
+}
+
+impl Shared {
+    /// Send encoded data to every client, disconnecting clients that fail
+    fn broadcast(&self, data: &[u8]) {
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| match client.stream.write_all(data) {
+            Ok(()) => true,
+            Err(error) => {
+                eprintln!("{}: Client {} dropped, {}", self.name, client.id, error);
+                let _ = client.stream.shutdown(std::net::Shutdown::Both);
+                false
+            }
+        });
+    }
+
+    /// Send encoded data to one client
+    fn send(&self, id: u64, data: &[u8]) -> std::io::Result<()> {
+        let mut clients = self.clients.lock().unwrap();
+        match clients.iter_mut().find(|client| client.id == id) {
+            Some(client) => client.stream.write_all(data),
+            None => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+
+    /// Send encoded data to the client in control, if any
+    fn send_controller(&self, data: &[u8]) {
+        let controller = *self.controller.lock().unwrap();
+        if let Some(id) = controller {
+            if let Err(error) = self.send(id, data) {
+                eprintln!("{}: Client {} failed, {}", self.name, id, error);
+            }
+        }
+    }
+
+    /// Forward a command from a client, if the client has or can take control
+    fn command(&self, id: u64, data: &[u8]) -> std::io::Result<()> {
+        let mut controller = self.controller.lock().unwrap();
+        match *controller {
+            Some(owner) if owner != id => {
+                eprintln!("{}: Ignored command from client {}", self.name, id);
+                Ok(())
+            }
+            _ => {
+                if controller.is_none() {
+                    eprintln!("{}: Client {} has control", self.name, id);
+                    *controller = Some(id);
+                }
+                self.device.lock().unwrap().write_all(data)
+            }
+        }
+    }
+
+    fn disconnect(&self, id: u64) {
+        let remaining = {
+            let mut clients = self.clients.lock().unwrap();
+            clients.retain(|client| client.id != id);
+            clients.len()
+        };
+        let mut controller = self.controller.lock().unwrap();
+        if *controller == Some(id) {
+            *controller = None;
+        }
+        if remaining == 0 {
+            let mut buffer = [0u8; 16];
+            if let Ok(data) = wire_format::Packet::CaptureStop.encode(&mut buffer) {
+                let _ = self.device.lock().unwrap().write_all(data);
+            }
+        }
+        eprintln!("{}: Client {} disconnected", self.name, id);
+    }
+}
+
+/// Take the complete packets from the front of the buffer
+fn split_packets(buffer: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    while let Some(end) = buffer.iter().position(|&b| b == 0x00) {
+        packets.push(buffer.drain(..=end).collect());
+    }
+    packets
+}
+
+fn decode(data: &[u8]) -> Option<wire_format::Packet> {
+    let mut work_buffer = data.to_vec();
+    wire_format::Packet::decode(&mut work_buffer)
+        .ok()
+        .map(|(packet, _)| packet)
+}
+
+fn is_timeout(error: &std::io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+}
+
+fn device_reader(shared: Arc<Shared>, mut reader: Box<dyn Read + Send>, stop: Arc<AtomicBool>) {
+    let mut buffer = Vec::new();
+    let mut read_buffer = [0u8; 1024];
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read(&mut read_buffer) {
+            Ok(length) => buffer.extend_from_slice(&read_buffer[..length]),
+            Err(ref error) if is_timeout(error) => continue,
+            Err(error) => {
+                eprintln!("{}: Failed to read sniffer, {}", shared.name, error);
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+        for data in split_packets(&mut buffer) {
+            // Only captured frames are of interest to the clients, probe replies are
+            // answered by the server and transmit results go to the client in control.
+            // Undecodable data is forwarded so that clients can account for lost frames.
+            match decode(&data) {
+                Some(wire_format::Packet::CaptureFrame(_)) | None => shared.broadcast(&data),
+                Some(wire_format::Packet::TransmitResult(_)) => shared.send_controller(&data),
+                Some(_) => (),
+            }
+        }
+    }
+}
+
+fn client_packet(shared: &Shared, id: u64, data: &[u8]) -> std::io::Result<()> {
+    match decode(data) {
+        Some(wire_format::Packet::Probe(wire_format::PROBE_HOST)) => {
+            let mut reply = [0u8; 16];
+            let reply = wire_format::Packet::Probe(wire_format::PROBE_DEVICE)
+                .encode(&mut reply)
+                .map_err(|_| std::io::Error::from(ErrorKind::InvalidData))?;
+            shared.send(id, reply)
+        }
+        Some(wire_format::Packet::Channel(_))
+        | Some(wire_format::Packet::Power(_))
+        | Some(wire_format::Packet::CaptureStart)
+        | Some(wire_format::Packet::CaptureStop)
+        | Some(wire_format::Packet::Transmit(_))
+        | Some(wire_format::Packet::Reset) => shared.command(id, data),
+        _ => Ok(()),
+    }
+}
+
+fn client_reader(shared: Arc<Shared>, id: u64, mut stream: TcpStream, stop: Arc<AtomicBool>) {
+    let mut buffer = Vec::new();
+    let mut read_buffer = [0u8; 512];
+    while !stop.load(Ordering::Relaxed) {
+        match stream.read(&mut read_buffer) {
+            Ok(0) => break,
+            Ok(length) => buffer.extend_from_slice(&read_buffer[..length]),
+            Err(ref error) if is_timeout(error) => continue,
+            Err(_) => break,
+        }
+        let result = split_packets(&mut buffer)
+            .iter()
+            .try_for_each(|data| client_packet(&shared, id, data));
+        if let Err(error) = result {
+            eprintln!("{}: Client {} failed, {}", shared.name, id, error);
+            break;
+        }
+    }
+    shared.disconnect(id);
+}
+
+/// Serve a sniffer to clients connecting to `listener` until `stop` is set
+pub fn serve(
+    name: &str,
+    listener: TcpListener,
+    sniffer: Sniffer,
+    stop: Arc<AtomicBool>,
+) -> Result<(), Error> {
+    let shared = Arc::new(Shared {
+        name: name.to_string(),
+        device: Mutex::new(sniffer.writer),
+        clients: Mutex::new(Vec::new()),
+        controller: Mutex::new(None),
+        next_id: AtomicU64::new(1),
+    });
+    let reader = {
+        let shared = Arc::clone(&shared);
+        let stop = Arc::clone(&stop);
+        let reader = sniffer.reader;
+        thread::spawn(move || device_reader(shared, reader, stop))
+    };
+    listener.set_nonblocking(true)?;
+    let mut client_threads = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, address)) => {
+                let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+                eprintln!("{}: Client {} connected from {}", name, id, address);
+                stream.set_nonblocking(false)?;
+                stream.set_nodelay(true)?;
+                stream.set_read_timeout(Some(POLL_INTERVAL))?;
+                stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                shared.clients.lock().unwrap().push(Client {
+                    id,
+                    stream: stream.try_clone()?,
+                });
+                let shared = Arc::clone(&shared);
+                let stop = Arc::clone(&stop);
+                client_threads.push(thread::spawn(move || {
+                    client_reader(shared, id, stream, stop)
+                }));
+            }
+            Err(ref error) if is_timeout(error) => thread::sleep(POLL_INTERVAL / 10),
+            Err(error) => return Err(error.into()),
+        }
+        client_threads.retain(|thread| !thread.is_finished());
+    }
+    for thread in client_threads {
+        let _ = thread.join();
+    }
+    let _ = reader.join();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_complete_packets() {
+        let mut buffer = vec![0x01, 0x02, 0x00, 0x03, 0x00, 0x04, 0x05];
+        let packets = split_packets(&mut buffer);
+        assert_eq!(packets, vec![vec![0x01, 0x02, 0x00], vec![0x03, 0x00]]);
+        assert_eq!(buffer, vec![0x04, 0x05]);
+    }
+}
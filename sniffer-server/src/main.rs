@@ -0,0 +1,45 @@
+use clap::Parser;
+use ieee802154_sniffer_server::{serve, Error, Sniffer};
+use std::{
+    net::{IpAddr, TcpListener},
+    sync::{atomic::AtomicBool, Arc},
+    thread,
+};
+
+#[derive(Debug, Parser)]
+#[command(about = "Share IEEE 802.15.4 sniffers over TCP")]
+pub struct AppArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "0.0.0.0")]
+    address: IpAddr,
+    /// TCP port of the first sniffer, following sniffers use the following ports
+    #[arg(long, default_value = "4000")]
+    port: u16,
+    /// Serial ports of the sniffers to serve
+    #[arg(required = true)]
+    sniffers: Vec<String>,
+}
+
+fn main() -> Result<(), Error> {
+    let args = AppArgs::parse();
+
+    let term = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&term))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&term))?;
+
+    let mut servers = Vec::new();
+    for (n, name) in args.sniffers.iter().enumerate() {
+        let sniffer = Sniffer::open_serial(name)?;
+        let listener = TcpListener::bind((args.address, args.port + n as u16))?;
+        eprintln!("{}: Serving on {}", name, listener.local_addr()?);
+        let name = name.clone();
+        let stop = Arc::clone(&term);
+        servers.push(thread::spawn(move || serve(&name, listener, sniffer, stop)));
+    }
+    for server in servers {
+        if let Ok(Err(error)) = server.join() {
+            eprintln!("Server failed, {:?}", error);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Serve the virtual sniffer to several clients
+#![cfg(unix)]
+
+use ieee802154_sniffer_server::{serve, Sniffer};
+use ieee802154_sniffer_simulator::{Config, Pty, Simulator};
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+fn send(stream: &mut TcpStream, packet: wire_format::Packet) {
+    let mut buffer = [0u8; 64];
+    stream
+        .write_all(packet.encode(&mut buffer).unwrap())
+        .unwrap();
+}
+
+/// Read packets for a while
+fn receive(stream: &mut TcpStream, duration: Duration) -> Vec<wire_format::Packet> {
+    stream
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let start = std::time::Instant::now();
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while start.elapsed() < duration {
+        if let Ok(length) = stream.read(&mut buffer) {
+            data.extend_from_slice(&buffer[..length]);
+        }
+    }
+    data.split_inclusive(|&b| b == 0x00)
+        .filter_map(|packet| {
+            let mut packet = packet.to_vec();
+            wire_format::Packet::decode(&mut packet)
+                .ok()
+                .map(|(packet, _)| packet)
+        })
+        .collect()
+}
+
+fn frame_channels(packets: &[wire_format::Packet]) -> Vec<u8> {
+    packets
+        .iter()
+        .filter_map(|packet| match packet {
+            wire_format::Packet::CaptureFrame(frame) => Some(frame.channel),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn fan_out_and_exclusive_control() {
+    let pty = Pty::open().unwrap();
+    let path = pty.path().to_string_lossy().to_string();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator = {
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || {
+            let config = Config {
+                rate: 100.0,
+                ..Config::default()
+            };
+            let mut simulator = Simulator::new(pty, config);
+            simulator.run(&stop).unwrap();
+            simulator
+        })
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let sniffer = Sniffer::open_serial(&path).unwrap();
+    let server = {
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || serve(&path, listener, sniffer, stop))
+    };
+
+    let mut first = TcpStream::connect(address).unwrap();
+    let mut second = TcpStream::connect(address).unwrap();
+
+    send(
+        &mut second,
+        wire_format::Packet::Probe(wire_format::PROBE_HOST),
+    );
+    assert_eq!(
+        receive(&mut second, Duration::from_millis(200)),
+        vec![wire_format::Packet::Probe(wire_format::PROBE_DEVICE)]
+    );
+
+    // The first client to send a command takes control
+    send(&mut first, wire_format::Packet::Channel(20));
+    send(&mut first, wire_format::Packet::CaptureStart);
+    thread::sleep(Duration::from_millis(100));
+    send(&mut second, wire_format::Packet::Channel(12));
+
+    let (first_channels, second_channels) = thread::scope(|scope| {
+        let first =
+            scope.spawn(|| frame_channels(&receive(&mut first, Duration::from_millis(500))));
+        let second = frame_channels(&receive(&mut second, Duration::from_millis(500)));
+        (first.join().unwrap(), second)
+    });
+    assert!(first_channels.len() > 20);
+    assert!(second_channels.len() > 20);
+    assert!(first_channels
+        .iter()
+        .chain(second_channels.iter())
+        .all(|&channel| channel == 20));
+
+    // Control is released when the controlling client disconnects
+    drop(first);
+    thread::sleep(Duration::from_millis(300));
+    send(&mut second, wire_format::Packet::CaptureStop);
+    thread::sleep(Duration::from_millis(100));
+    let _ = receive(&mut second, Duration::from_millis(100));
+    assert!(receive(&mut second, Duration::from_millis(200)).is_empty());
+
+    // Transmit results go to the client in control
+    let payload = wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap();
+    send(
+        &mut second,
+        wire_format::Packet::Transmit(wire_format::Transmit { id: 3, payload }),
+    );
+    assert_eq!(
+        receive(&mut second, Duration::from_millis(200)),
+        vec![wire_format::Packet::TransmitResult(
+            wire_format::TransmitResult {
+                id: 3,
+                status: wire_format::TransmitStatus::Sent,
+            }
+        )]
+    );
+
+    drop(second);
+    stop.store(true, Ordering::Relaxed);
+    server.join().unwrap().unwrap();
+    let simulator = simulator.join().unwrap();
+    assert_eq!(simulator.channel(), 20);
+    assert!(!simulator.capturing());
+    assert_eq!(simulator.statistics().transmitted, 1);
+}
@@ -0,0 +1,513 @@
+//! Virtual IEEE 802.15.4 sniffer
+//!
+//! Speaks the serial wire format like the sniffer firmware, answering probes and following
+//! channel and capture commands, while emitting synthesised or replayed frames.
+use ieee802154_sniffer_wire_format as wire_format;
+use std::{
+    io::{ErrorKind, Read, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+mod pty;
+mod traffic;
+
+#[cfg(unix)]
+pub use pty::Pty;
+pub use traffic::Source;
+use traffic::{Random, Synthetic};
+
+/// Channel used by the firmware after reset
+const DEFAULT_CHANNEL: u8 = 11;
+
+/// Frames are not emitted in bursts longer than this after a stall
+const MAXIMUM_LAG: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Pcap(pcap_file::PcapError),
+    UnsupportedLinkType(pcap_file::DataLink),
+    #[cfg(unix)]
+    Nix(nix::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<pcap_file::PcapError> for Error {
+    fn from(error: pcap_file::PcapError) -> Self {
+        Error::Pcap(error)
+    }
+}
+
+#[cfg(unix)]
+impl From<nix::Error> for Error {
+    fn from(error: nix::Error) -> Self {
+        Error::Nix(error)
+    }
+}
+
+/// Simulator configuration
+pub struct Config {
+    /// Where captured frames come from
+    pub source: Source,
+    /// Frames per second while capturing
+    pub rate: f64,
+    /// Probability that a frame is lost
+    pub loss: f64,
+    /// Probability that an emitted frame is truncated so that it fails to decode
+    pub corruption: f64,
+    /// Seed for the pseudo random number generator
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            source: Source::Synthetic,
+            rate: 10.0,
+            loss: 0.0,
+            corruption: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+/// Counters for emitted frames
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Statistics {
+    /// Frames written to the host, including corrupted frames
+    pub sent: u64,
+    /// Frames dropped before being written
+    pub lost: u64,
+    /// Frames written truncated
+    pub corrupted: u64,
+    /// Frames the host asked to transmit
+    pub transmitted: u64,
+}
+
+/// Simulated sniffer device on a byte stream
+///
+/// Reads from the stream are expected to be non-blocking, returning
+/// [`ErrorKind::WouldBlock`] when no data is available.
+pub struct Simulator<S: Read + Write> {
+    stream: S,
+    source: Source,
+    interval: Option<Duration>,
+    loss: f64,
+    corruption: f64,
+    random: Random,
+    synthetic: Synthetic,
+    replay_index: usize,
+    channel: u8,
+    power: i32,
+    capturing: bool,
+    next_frame: Instant,
+    read_buffer: Vec<u8>,
+    statistics: Statistics,
+}
+
+impl<S: Read + Write> Simulator<S> {
+    pub fn new(stream: S, config: Config) -> Self {
+        let interval = if config.rate > 0.0 {
+            Some(Duration::from_secs_f64(1.0 / config.rate))
+        } else {
+            None
+        };
+        Self {
+            stream,
+            source: config.source,
+            interval,
+            loss: config.loss,
+            corruption: config.corruption,
+            random: Random::new(config.seed),
+            synthetic: Synthetic::new(),
+            replay_index: 0,
+            channel: DEFAULT_CHANNEL,
+            power: 0,
+            capturing: false,
+            next_frame: Instant::now(),
+            read_buffer: Vec::new(),
+            statistics: Statistics::default(),
+        }
+    }
+
+    /// Current radio channel
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// Last transmit power requested by the host, in dBm
+    pub fn power(&self) -> i32 {
+        self.power
+    }
+
+    /// Whether the host has started the capture
+    pub fn capturing(&self) -> bool {
+        self.capturing
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Handle pending host commands and emit any frames that are due
+    pub fn poll(&mut self) -> Result<(), Error> {
+        self.read_commands()?;
+        if let (true, Some(interval)) = (self.capturing, self.interval) {
+            let now = Instant::now();
+            if now.duration_since(self.next_frame) > MAXIMUM_LAG {
+                self.next_frame = now;
+            }
+            while self.next_frame <= now {
+                self.emit_frame()?;
+                self.next_frame += interval;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run until `stop` is set
+    pub fn run(&mut self, stop: &AtomicBool) -> Result<(), Error> {
+        while !stop.load(Ordering::Relaxed) {
+            self.poll()?;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+
+    fn read_commands(&mut self) -> Result<(), Error> {
+        let mut buffer = [0u8; 512];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(length) => self.read_buffer.extend_from_slice(&buffer[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error.into()),
+            }
+        }
+        while let Some(end) = self.read_buffer.iter().position(|&b| b == 0x00) {
+            let mut packet_data: Vec<u8> = self.read_buffer.drain(..=end).collect();
+            // Packets that fail to decode are ignored, like the firmware does
+            if let Ok((packet, _)) = wire_format::Packet::decode(&mut packet_data) {
+                self.handle(packet)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: wire_format::Packet) -> Result<(), Error> {
+        match packet {
+            wire_format::Packet::Probe(magic) => {
+                if magic == wire_format::PROBE_HOST {
+                    self.write_packet(&wire_format::Packet::Probe(wire_format::PROBE_DEVICE))?;
+                    self.write_packet(&wire_format::Packet::State(wire_format::State {
+                        channel: self.channel,
+                        capturing: self.capturing,
+                    }))?;
+                }
+            }
+            wire_format::Packet::Channel(channel) => {
+                self.channel = channel;
+            }
+            wire_format::Packet::Power(power) => {
+                self.power = power;
+            }
+            wire_format::Packet::CaptureStart => {
+                self.capturing = true;
+                self.next_frame = Instant::now();
+            }
+            wire_format::Packet::CaptureStop => {
+                self.capturing = false;
+            }
+            wire_format::Packet::Transmit(transmit) => {
+                self.statistics.transmitted += 1;
+                // Like the firmware, frames the radio cannot hold fail
+                let status = if transmit.payload.len() > wire_format::MAX_TRANSMIT_LENGTH {
+                    wire_format::TransmitStatus::Failed
+                } else {
+                    wire_format::TransmitStatus::Sent
+                };
+                let result = wire_format::TransmitResult {
+                    id: transmit.id,
+                    status,
+                };
+                self.write_packet(&wire_format::Packet::TransmitResult(result))?;
+            }
+            wire_format::Packet::NoOperation
+            | wire_format::Packet::Reset
+            | wire_format::Packet::CaptureFrame(_)
+            | wire_format::Packet::TransmitResult(_)
+            | wire_format::Packet::State(_) => (),
+        }
+        Ok(())
+    }
+
+    fn next_payload(&mut self) -> Option<wire_format::Payload> {
+        match &self.source {
+            Source::Synthetic => Some(self.synthetic.next(&mut self.random)),
+            Source::Replay(frames) => {
+                if frames.is_empty() {
+                    return None;
+                }
+                let payload = frames[self.replay_index % frames.len()].clone();
+                self.replay_index += 1;
+                Some(payload)
+            }
+        }
+    }
+
+    fn emit_frame(&mut self) -> Result<(), Error> {
+        let payload = match self.next_payload() {
+            Some(payload) => payload,
+            None => return Ok(()),
+        };
+        if self.random.next_f64() < self.loss {
+            self.statistics.lost += 1;
+            return Ok(());
+        }
+        let rssi = -30 - self.random.below(60) as i32;
+        let frame = wire_format::Frame {
+            channel: self.channel,
+            received_signal_strength_indicator: Some(rssi * 1_000),
+            link_quality_index: Some(self.random.below(256) as u8),
+            payload,
+        };
+        let mut buffer = [0u8; 512];
+        let data = wire_format::Packet::CaptureFrame(frame)
+            .encode(&mut buffer)
+            .map_err(|_| std::io::Error::from(ErrorKind::InvalidData))?;
+        let mut length = data.len();
+        if self.random.next_f64() < self.corruption {
+            // Lose the tail of the packet but keep the end marker, like lost UART bytes
+            let keep = 1 + self.random.below(length as u64 - 2) as usize;
+            data[keep] = 0x00;
+            length = keep + 1;
+            self.statistics.corrupted += 1;
+        }
+        match self.stream.write_all(&data[..length]) {
+            Ok(()) => {
+                self.statistics.sent += 1;
+                Ok(())
+            }
+            // The host is not reading, the frame is lost like on a full UART
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                self.statistics.lost += 1;
+                Ok(())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn write_packet(&mut self, packet: &wire_format::Packet) -> Result<(), Error> {
+        let mut buffer = [0u8; 512];
+        let data = packet
+            .encode(&mut buffer)
+            .map_err(|_| std::io::Error::from(ErrorKind::InvalidData))?;
+        self.stream.write_all(data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Non-blocking in-memory stream
+    #[derive(Default)]
+    struct Stream {
+        rx: VecDeque<u8>,
+        tx: Vec<u8>,
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            if self.rx.is_empty() {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let length = self.rx.len().min(buffer.len());
+            for (target, source) in buffer.iter_mut().zip(self.rx.drain(..length)) {
+                *target = source;
+            }
+            Ok(length)
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.tx.extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn send(simulator: &mut Simulator<Stream>, packet: wire_format::Packet) {
+        let mut buffer = [0u8; 512];
+        let data = packet.encode(&mut buffer).unwrap();
+        simulator.stream.rx.extend(data.iter());
+    }
+
+    fn received(simulator: &mut Simulator<Stream>) -> Vec<Result<wire_format::Packet, ()>> {
+        let data = std::mem::take(&mut simulator.stream.tx);
+        data.split_inclusive(|&b| b == 0x00)
+            .map(|packet| {
+                let mut packet = packet.to_vec();
+                wire_format::Packet::decode(&mut packet)
+                    .map(|(packet, _)| packet)
+                    .map_err(|_| ())
+            })
+            .collect()
+    }
+
+    fn capture(config: Config) -> Simulator<Stream> {
+        let mut simulator = Simulator::new(Stream::default(), config);
+        send(&mut simulator, wire_format::Packet::Channel(20));
+        send(&mut simulator, wire_format::Packet::CaptureStart);
+        simulator.poll().unwrap();
+        simulator.next_frame = Instant::now() - Duration::from_millis(95);
+        simulator.poll().unwrap();
+        simulator
+    }
+
+    #[test]
+    fn probe() {
+        let mut simulator = Simulator::new(Stream::default(), Config::default());
+        send(
+            &mut simulator,
+            wire_format::Packet::Probe(wire_format::PROBE_HOST),
+        );
+        simulator.poll().unwrap();
+        assert_eq!(
+            received(&mut simulator),
+            vec![
+                Ok(wire_format::Packet::Probe(wire_format::PROBE_DEVICE)),
+                Ok(wire_format::Packet::State(wire_format::State {
+                    channel: 11,
+                    capturing: false
+                }))
+            ]
+        );
+    }
+
+    #[test]
+    fn transmit() {
+        let mut simulator = Simulator::new(Stream::default(), Config::default());
+        let payload = wire_format::Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap();
+        send(
+            &mut simulator,
+            wire_format::Packet::Transmit(wire_format::Transmit { id: 7, payload }),
+        );
+        simulator.poll().unwrap();
+        assert_eq!(
+            received(&mut simulator),
+            vec![Ok(wire_format::Packet::TransmitResult(
+                wire_format::TransmitResult {
+                    id: 7,
+                    status: wire_format::TransmitStatus::Sent,
+                }
+            ))]
+        );
+        assert_eq!(simulator.statistics().transmitted, 1);
+    }
+
+    #[test]
+    fn transmit_too_long() {
+        let mut simulator = Simulator::new(Stream::default(), Config::default());
+        let payload = wire_format::Payload::from_slice(&[0; 126]).unwrap();
+        send(
+            &mut simulator,
+            wire_format::Packet::Transmit(wire_format::Transmit { id: 8, payload }),
+        );
+        simulator.poll().unwrap();
+        assert_eq!(
+            received(&mut simulator),
+            vec![Ok(wire_format::Packet::TransmitResult(
+                wire_format::TransmitResult {
+                    id: 8,
+                    status: wire_format::TransmitStatus::Failed,
+                }
+            ))]
+        );
+        assert_eq!(simulator.statistics().transmitted, 1);
+    }
+
+    #[test]
+    fn synthetic_capture() {
+        let mut simulator = capture(Config {
+            rate: 100.0,
+            ..Config::default()
+        });
+        assert!(simulator.capturing());
+        let packets = received(&mut simulator);
+        assert!(packets.len() >= 10);
+        for packet in packets {
+            match packet {
+                Ok(wire_format::Packet::CaptureFrame(frame)) => {
+                    assert_eq!(frame.channel, 20);
+                    assert_eq!(frame.payload.len(), 16);
+                }
+                _ => panic!("Expected capture frame"),
+            }
+        }
+        send(&mut simulator, wire_format::Packet::CaptureStop);
+        simulator.poll().unwrap();
+        simulator.poll().unwrap();
+        assert!(!simulator.capturing());
+        assert!(received(&mut simulator).is_empty());
+    }
+
+    #[test]
+    fn replay() {
+        let frames = vec![
+            wire_format::Payload::from_slice(&[0x02, 0x00, 0x01]).unwrap(),
+            wire_format::Payload::from_slice(&[0x02, 0x00, 0x02]).unwrap(),
+        ];
+        let mut simulator = capture(Config {
+            source: Source::Replay(frames.clone()),
+            rate: 30.0,
+            ..Config::default()
+        });
+        let packets = received(&mut simulator);
+        assert!(packets.len() >= 2);
+        for (n, packet) in packets.into_iter().enumerate() {
+            match packet {
+                Ok(wire_format::Packet::CaptureFrame(frame)) => {
+                    assert_eq!(frame.payload, frames[n % frames.len()]);
+                }
+                _ => panic!("Expected capture frame"),
+            }
+        }
+    }
+
+    #[test]
+    fn losses_and_corruption() {
+        let mut simulator = capture(Config {
+            rate: 100.0,
+            loss: 1.0,
+            ..Config::default()
+        });
+        assert!(received(&mut simulator).is_empty());
+        assert!(simulator.statistics().lost >= 10);
+
+        let mut simulator = capture(Config {
+            rate: 100.0,
+            corruption: 1.0,
+            ..Config::default()
+        });
+        let statistics = simulator.statistics().clone();
+        assert_eq!(statistics.sent, statistics.corrupted);
+        let packets = received(&mut simulator);
+        assert_eq!(packets.len() as u64, statistics.sent);
+        assert!(packets.iter().all(|packet| packet.is_err()));
+    }
+}
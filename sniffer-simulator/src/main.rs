@@ -0,0 +1,70 @@
+use clap::Parser;
+use ieee802154_sniffer_simulator::{Config, Error, Source};
+use std::{path::PathBuf, sync::atomic::AtomicBool, sync::Arc};
+
+#[derive(Debug, Parser)]
+#[command(about = "Virtual IEEE 802.15.4 sniffer on a pseudo-terminal")]
+pub struct AppArgs {
+    /// Replay frames from a pcap or pcapng file instead of synthesising traffic
+    #[arg(long)]
+    pcap: Option<PathBuf>,
+    /// Frames per second while capturing
+    #[arg(long, default_value = "10")]
+    rate: f64,
+    /// Probability that a frame is lost
+    #[arg(long, default_value = "0")]
+    loss: f64,
+    /// Probability that a frame is corrupted
+    #[arg(long, default_value = "0")]
+    corruption: f64,
+    /// Seed for the pseudo random number generator
+    #[arg(long, default_value = "1")]
+    seed: u64,
+    /// Create a symbolic link to the pseudo-terminal
+    #[arg(long)]
+    link: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+fn main() -> Result<(), Error> {
+    let args = AppArgs::parse();
+
+    let source = match args.pcap {
+        Some(path) => Source::from_file(path)?,
+        None => Source::Synthetic,
+    };
+    let config = Config {
+        source,
+        rate: args.rate,
+        loss: args.loss,
+        corruption: args.corruption,
+        seed: args.seed,
+    };
+
+    let pty = ieee802154_sniffer_simulator::Pty::open()?;
+    println!("{}", pty.path().display());
+    if let Some(link) = &args.link {
+        let _ = std::fs::remove_file(link);
+        std::os::unix::fs::symlink(pty.path(), link)?;
+    }
+
+    let term = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&term))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&term))?;
+
+    let mut simulator = ieee802154_sniffer_simulator::Simulator::new(pty, config);
+    let result = simulator.run(&term);
+
+    if let Some(link) = &args.link {
+        let _ = std::fs::remove_file(link);
+    }
+    eprintln!("{:?}", simulator.statistics());
+    result
+}
+
+#[cfg(not(unix))]
+fn main() -> Result<(), Error> {
+    let _args = AppArgs::parse();
+    eprintln!("The simulator requires pseudo-terminal support");
+    std::process::exit(1);
+}
@@ -0,0 +1,65 @@
+//! Pseudo-terminal acting as the sniffer serial port
+use crate::Error;
+use nix::{
+    fcntl::{fcntl, FcntlArg, OFlag},
+    pty::openpty,
+    sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg},
+    unistd::ttyname,
+};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    os::unix::io::FromRawFd,
+    path::PathBuf,
+};
+
+/// Pseudo-terminal pair, the simulator uses the master side
+///
+/// The slave side is kept open so that the master does not report errors while no host is
+/// connected.
+pub struct Pty {
+    master: File,
+    _slave: File,
+    path: PathBuf,
+}
+
+impl Pty {
+    /// Create a pseudo-terminal in raw mode with a non-blocking master
+    pub fn open() -> Result<Self, Error> {
+        let pty = openpty(None, None)?;
+        // Both file descriptors are newly created and owned by us
+        let (master, slave) =
+            unsafe { (File::from_raw_fd(pty.master), File::from_raw_fd(pty.slave)) };
+        let mut termios = tcgetattr(pty.slave)?;
+        cfmakeraw(&mut termios);
+        tcsetattr(pty.slave, SetArg::TCSANOW, &termios)?;
+        fcntl(pty.master, FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+        let path = ttyname(pty.slave)?;
+        Ok(Self {
+            master,
+            _slave: slave,
+            path,
+        })
+    }
+
+    /// Path of the serial port device for the host to open
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl Read for Pty {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.master.read(buffer)
+    }
+}
+
+impl Write for Pty {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.master.write(data)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.master.flush()
+    }
+}
@@ -0,0 +1,205 @@
+//! Frame sources for the simulator
+use crate::Error;
+use byteorder::{ByteOrder, LittleEndian};
+use ieee802154_sniffer_wire_format as wire_format;
+use pcap_file::{
+    pcap::PcapReader,
+    pcapng::{Block, PcapNgReader},
+    DataLink,
+};
+use std::path::Path;
+
+/// pcapng section header block type, also the first four bytes of a pcapng file
+const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+
+/// Frame check sequence length
+const FCS_LENGTH: usize = 2;
+
+/// Small deterministic pseudo random number generator (xorshift64*)
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform value in `0.0..1.0`
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform value in `0..bound`
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound.max(1)
+    }
+}
+
+/// Source of frame payloads, without FCS
+pub enum Source {
+    /// Synthesised data frames from a handful of nodes
+    Synthetic,
+    /// Frames replayed in order, repeating when the end is reached
+    Replay(Vec<wire_format::Payload>),
+}
+
+impl Source {
+    /// Load frames to replay from a pcap or pcapng file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let data = std::fs::read(path)?;
+        let frames = if data.starts_with(&PCAPNG_MAGIC) {
+            read_pcapng(&data)?
+        } else {
+            read_pcap(&data)?
+        };
+        Ok(Source::Replay(frames))
+    }
+}
+
+fn read_pcap(data: &[u8]) -> Result<Vec<wire_format::Payload>, Error> {
+    let mut reader = PcapReader::new(data)?;
+    let datalink = reader.header().datalink;
+    let mut frames = Vec::new();
+    while let Some(packet) = reader.next_packet() {
+        if let Some(frame) = strip(datalink, &packet?.data)? {
+            frames.push(frame);
+        }
+    }
+    Ok(frames)
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<wire_format::Payload>, Error> {
+    let mut reader = PcapNgReader::new(data)?;
+    let mut datalinks = Vec::new();
+    let mut frames = Vec::new();
+    while let Some(block) = reader.next_block() {
+        let (interface_id, data) = match block? {
+            Block::InterfaceDescription(interface) => {
+                datalinks.push(interface.linktype);
+                continue;
+            }
+            Block::EnhancedPacket(packet) => (packet.interface_id, packet.data),
+            Block::SimplePacket(packet) => (0, packet.data),
+            _ => continue,
+        };
+        let datalink = datalinks
+            .get(interface_id as usize)
+            .copied()
+            .unwrap_or(DataLink::IEEE802_15_4_NOFCS);
+        if let Some(frame) = strip(datalink, &data)? {
+            frames.push(frame);
+        }
+    }
+    Ok(frames)
+}
+
+/// Strip link-layer headers and FCS, returns `None` for frames too large for the wire format
+fn strip(datalink: DataLink, data: &[u8]) -> Result<Option<wire_format::Payload>, Error> {
+    let frame = match datalink {
+        DataLink::IEEE802_15_4_NOFCS => data,
+        DataLink::IEEE802_15_4 => &data[..data.len().saturating_sub(FCS_LENGTH)],
+        DataLink::IEEE802_15_4_TAP => strip_tap(data),
+        datalink => return Err(Error::UnsupportedLinkType(datalink)),
+    };
+    Ok(wire_format::Payload::from_slice(frame).ok())
+}
+
+/// Strip a TAP header and any FCS indicated by it
+fn strip_tap(data: &[u8]) -> &[u8] {
+    if data.len() < 4 {
+        return &[];
+    }
+    let header_length = usize::from(LittleEndian::read_u16(&data[2..4])).min(data.len());
+    let mut fcs_length = 0;
+    let mut offset = 4;
+    while offset + 4 <= header_length {
+        let tlv_type = LittleEndian::read_u16(&data[offset..offset + 2]);
+        let tlv_length = usize::from(LittleEndian::read_u16(&data[offset + 2..offset + 4]));
+        if tlv_type == 0 && offset + 5 <= header_length {
+            fcs_length = match data[offset + 4] {
+                1 => 2,
+                2 => 4,
+                _ => 0,
+            };
+        }
+        // TLV values are padded to 32-bit alignment
+        offset += 4 + ((tlv_length + 3) & !3);
+    }
+    let frame = &data[header_length..];
+    &frame[..frame.len().saturating_sub(fcs_length)]
+}
+
+/// Generator for synthesised traffic
+pub(crate) struct Synthetic {
+    sequence: u8,
+}
+
+impl Synthetic {
+    const PAN_ID: u16 = 0x1a62;
+    const NODES: u16 = 4;
+
+    pub(crate) fn new() -> Self {
+        Self { sequence: 0 }
+    }
+
+    /// Data frame with short addressing from a random node to the coordinator
+    pub(crate) fn next(&mut self, random: &mut Random) -> wire_format::Payload {
+        let mut frame = [0u8; 16];
+        let source = 0x0001 + random.below(u64::from(Self::NODES)) as u16;
+        // Data frame, PAN ID compression, short destination and source addresses
+        LittleEndian::write_u16(&mut frame[0..2], 0x8841);
+        frame[2] = self.sequence;
+        LittleEndian::write_u16(&mut frame[3..5], Self::PAN_ID);
+        LittleEndian::write_u16(&mut frame[5..7], 0x0000);
+        LittleEndian::write_u16(&mut frame[7..9], source);
+        for (n, octet) in frame[9..].iter_mut().enumerate() {
+            *octet = self.sequence.wrapping_add(n as u8);
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+        // The frame is shorter than the payload capacity
+        wire_format::Payload::from_slice(&frame).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_link_types() {
+        let frame = [0x02, 0x00, 0x04];
+        let with_fcs = [0x02, 0x00, 0x04, 0xaa, 0xbb];
+        let tap = [
+            0x00, 0x00, 0x14, 0x00, // header
+            0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, // FCS type, 16-bit
+            0x03, 0x00, 0x03, 0x00, 0x0f, 0x00, 0x00, 0x00, // channel
+            0x02, 0x00, 0x04, 0xaa, 0xbb, // payload with FCS
+        ];
+        for (datalink, data) in [
+            (DataLink::IEEE802_15_4_NOFCS, &frame[..]),
+            (DataLink::IEEE802_15_4, &with_fcs[..]),
+            (DataLink::IEEE802_15_4_TAP, &tap[..]),
+        ] {
+            let payload = strip(datalink, data).unwrap().unwrap();
+            assert_eq!(&payload[..], &frame[..]);
+        }
+        assert!(strip(DataLink::ETHERNET, &frame).is_err());
+    }
+
+    #[test]
+    fn random_is_deterministic() {
+        let mut first = Random::new(7);
+        let mut second = Random::new(7);
+        for _ in 0..16 {
+            let value = first.next_f64();
+            assert!((0.0..1.0).contains(&value));
+            assert_eq!(value, second.next_f64());
+        }
+    }
+}
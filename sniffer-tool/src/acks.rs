@@ -0,0 +1,103 @@
+//! Acknowledgements and retransmissions per link of a capture file
+use crate::Error;
+use ieee802154_sniffer_host::{
+    acknowledgement::{self, Analyzer},
+    capture_file,
+    summary::Device,
+};
+use std::{fmt::Write, path::PathBuf, time::Duration};
+
+/// Default window for capture times taken by the host, which include USB and scheduling delays
+const HOST_WINDOW: Duration = Duration::from_millis(20);
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// pcap or pcapng file with IEEE 802.15.4 or IEEE 802.15.4 TAP link type
+    file: PathBuf,
+    /// Microseconds after the end of a frame within which its acknowledgement must start,
+    /// 864 with device timestamps and 20000 with capture timestamps by default. Only
+    /// captures from other sniffers carry device timestamps
+    #[arg(long)]
+    window: Option<u64>,
+}
+
+fn device(device: &Device) -> String {
+    match device.pan_id {
+        Some(pan_id) => format!("0x{:04x} {}", pan_id, device.address),
+        None => device.address.to_string(),
+    }
+}
+
+fn render(analyzer: &Analyzer, source: &str, window: Duration) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "{} timestamps, {} µs window",
+        source,
+        window.as_micros()
+    );
+    let _ = writeln!(
+        text,
+        "{} acknowledgements matched, {} unmatched",
+        analyzer.matched, analyzer.unmatched
+    );
+    let _ = writeln!(
+        text,
+        "\n{:<30} {:<30} {:>8} {:>8} {:>9} {:>7}  Retries",
+        "Source", "Destination", "Frames", "Retrans", "Delivered", "Ratio"
+    );
+    for ((source, destination), link) in &analyzer.links {
+        let ratio = link.delivery_ratio().map_or_else(
+            || String::from("-"),
+            |ratio| format!("{:.1}%", ratio * 100.0),
+        );
+        let retries: Vec<String> = link
+            .retries
+            .iter()
+            .map(|(retries, frames)| format!("{}:{}", retries, frames))
+            .collect();
+        let _ = writeln!(
+            text,
+            "{:<30} {:<30} {:>8} {:>8} {:>9} {:>7}  {}",
+            device(source),
+            device(destination),
+            link.frames,
+            link.retransmissions,
+            link.delivered,
+            ratio,
+            retries.join(" ")
+        );
+    }
+    text
+}
+
+pub(crate) fn run(args: &Args) -> Result<(), Error> {
+    let records = capture_file::read_file(&args.file)?;
+    // Mixing clocks would make the times between frames meaningless
+    let device_timing = !records.is_empty()
+        && records
+            .iter()
+            .all(|record| record.device_timestamp.is_some());
+    let (source, default_window) = if device_timing {
+        ("Device", acknowledgement::ACK_WAIT)
+    } else {
+        eprintln!(
+            "No device timestamps in the capture, acknowledgements are paired by host capture \
+             times and late acknowledgements are not told apart. Only captures from sniffers \
+             that record start of frame times in the TAP header carry device timestamps."
+        );
+        ("Capture", HOST_WINDOW)
+    };
+    let window = args.window.map_or(default_window, Duration::from_micros);
+    let mut analyzer = Analyzer::new(window);
+    for record in &records {
+        let timestamp = match record.device_timestamp {
+            Some(timestamp) if device_timing => timestamp,
+            _ => record.timestamp,
+        };
+        analyzer.add(&record.frame, timestamp);
+    }
+    analyzer.finish();
+    print!("{}", render(&analyzer, source, window));
+    Ok(())
+}
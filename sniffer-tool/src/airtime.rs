@@ -0,0 +1,194 @@
+//! Airtime, duty cycle and frame sizes per channel, live or from a capture file
+use crate::Error;
+use ieee802154_sniffer_host::{
+    airtime::{Utilisation, OQPSK_BITRATE, SIZE_BUCKET, SIZE_BUCKETS},
+    capture_file,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::PathBuf,
+    sync::atomic::AtomicBool,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Analyse a pcap or pcapng file instead of capturing
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Sniffer to capture with, a serial port or tcp://host:port, the first sniffer found
+    /// by default
+    #[arg(long)]
+    device: Option<String>,
+    /// Channel to capture on
+    #[arg(long, default_value = "11")]
+    channel: u8,
+    /// Seconds between summaries and time series samples
+    #[arg(long, default_value = "1")]
+    interval: f64,
+    /// Seconds of the sliding window for the duty cycle and frame rate
+    #[arg(long, default_value = "10")]
+    window: f64,
+    /// PHY bit rate in bits per second
+    #[arg(long, default_value_t = OQPSK_BITRATE)]
+    bitrate: u32,
+    /// CSV file to write the time series to
+    #[arg(long)]
+    csv: Option<PathBuf>,
+}
+
+/// Periodic summaries and time series samples
+struct Report {
+    interval: Duration,
+    /// Time of the next sample, from the start of the capture
+    next: Duration,
+    csv: Option<BufWriter<File>>,
+    /// Frames and airtime of each channel at the previous sample
+    previous: BTreeMap<u8, (u64, Duration)>,
+}
+
+impl Report {
+    fn new(args: &Args) -> Result<Self, Error> {
+        let csv = match args.csv.as_ref() {
+            Some(path) => {
+                let mut csv = BufWriter::new(File::create(path)?);
+                writeln!(
+                    csv,
+                    "time,channel,frames,airtime_us,frames_per_second,duty_cycle"
+                )?;
+                Some(csv)
+            }
+            None => None,
+        };
+        let interval = Duration::from_secs_f64(args.interval.max(0.1));
+        Ok(Report {
+            interval,
+            next: interval,
+            csv,
+            previous: BTreeMap::new(),
+        })
+    }
+
+    /// Write a sample of each channel at `time` from the start of the capture, with the
+    /// frames and airtime since the previous sample
+    fn sample(&mut self, utilisation: &Utilisation, time: Duration) -> Result<(), Error> {
+        for (&number, channel) in &utilisation.channels {
+            let (frames, airtime) = self.previous.get(&number).copied().unwrap_or_default();
+            let frames = channel.frames - frames;
+            let airtime = channel.airtime - airtime;
+            let rate = utilisation.rate(number, time);
+            let duty_cycle = utilisation.duty_cycle(number, time);
+            println!(
+                "{:>8.1} s  channel {:>2}  {:>6} frames  {:>7.1} frames/s  {:>5.1}% duty cycle",
+                time.as_secs_f64(),
+                number,
+                frames,
+                rate,
+                duty_cycle * 100.0
+            );
+            if let Some(csv) = self.csv.as_mut() {
+                writeln!(
+                    csv,
+                    "{:.3},{},{},{},{:.3},{:.6}",
+                    time.as_secs_f64(),
+                    number,
+                    frames,
+                    airtime.as_micros(),
+                    rate,
+                    duty_cycle
+                )?;
+            }
+            self.previous
+                .insert(number, (channel.frames, channel.airtime));
+        }
+        Ok(())
+    }
+
+    /// Write the samples due before `time`
+    fn advance(&mut self, utilisation: &Utilisation, time: Duration) -> Result<(), Error> {
+        while self.next <= time {
+            self.sample(utilisation, self.next)?;
+            self.next += self.interval;
+        }
+        Ok(())
+    }
+
+    /// Write the last sample and the totals of each channel over `duration`
+    fn finish(mut self, utilisation: &Utilisation, duration: Duration) -> Result<(), Error> {
+        self.sample(utilisation, duration)?;
+        if let Some(mut csv) = self.csv.take() {
+            csv.flush()?;
+        }
+        print!("{}", totals(utilisation, duration));
+        Ok(())
+    }
+}
+
+/// Frame counts, airtime and frame size histogram of each channel
+fn totals(utilisation: &Utilisation, duration: Duration) -> String {
+    let mut text = String::new();
+    for (number, channel) in &utilisation.channels {
+        let duty_cycle = if duration.is_zero() {
+            0.0
+        } else {
+            channel.airtime.as_secs_f64() / duration.as_secs_f64()
+        };
+        let _ = writeln!(
+            text,
+            "\nChannel {}: {} frames, {:.3} s airtime, {:.1}% duty cycle",
+            number,
+            channel.frames,
+            channel.airtime.as_secs_f64(),
+            duty_cycle * 100.0
+        );
+        let _ = writeln!(text, "{:>9} {:>8}", "Octets", "Frames");
+        for (bucket, frames) in channel.sizes.iter().enumerate() {
+            let first = bucket * SIZE_BUCKET;
+            let octets = if bucket + 1 == SIZE_BUCKETS {
+                format!("{}+", first)
+            } else {
+                format!("{}-{}", first, first + SIZE_BUCKET - 1)
+            };
+            let _ = writeln!(text, "{:>9} {:>8}", octets, frames);
+        }
+    }
+    text
+}
+
+fn capture(args: &Args, utilisation: &mut Utilisation, stop: &AtomicBool) -> Result<(), Error> {
+    let mut report = Report::new(args)?;
+    // Time from the start of the capture, after the sniffer was found and configured
+    let mut started = None;
+    crate::capture(args.device.as_deref(), args.channel, stop, |frame| {
+        let start = *started.get_or_insert_with(Instant::now);
+        if let Some(frame) = frame {
+            utilisation.add(&frame, start.elapsed());
+        }
+        report.advance(utilisation, start.elapsed())
+    })?;
+    let elapsed = started.map(|start| start.elapsed()).unwrap_or_default();
+    report.finish(utilisation, elapsed)
+}
+
+pub(crate) fn run(args: &Args, stop: &AtomicBool) -> Result<(), Error> {
+    let window = Duration::from_secs_f64(args.window.max(0.001));
+    let mut utilisation = Utilisation::new(window, args.bitrate.max(1));
+    match args.file.as_ref() {
+        Some(file) => {
+            let mut report = Report::new(args)?;
+            let records = capture_file::read_file(file)?;
+            let start = records.first().map(|record| record.timestamp);
+            let mut time = Duration::ZERO;
+            for record in &records {
+                time = record.timestamp.saturating_sub(start.unwrap_or_default());
+                report.advance(&utilisation, time)?;
+                utilisation.add(&record.frame, time);
+            }
+            report.finish(&utilisation, time)
+        }
+        None => capture(args, &mut utilisation, stop),
+    }
+}
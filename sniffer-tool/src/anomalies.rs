@@ -0,0 +1,186 @@
+//! Alerts for suspicious traffic, live or from a capture file
+use crate::{json::Value, Error};
+use ieee802154_sniffer_host::{
+    anomaly::{Alert, Anomaly, Detector, Rules},
+    capture_file,
+    mac::Address,
+};
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    sync::atomic::AtomicBool,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Check a pcap or pcapng file instead of capturing
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Sniffer to capture with, a serial port or tcp://host:port, the first sniffer found
+    /// by default
+    #[arg(long)]
+    device: Option<String>,
+    /// Channel to capture on
+    #[arg(long, default_value = "11")]
+    channel: u8,
+    /// Write alerts as JSON Lines to standard output instead of text to standard error
+    #[arg(long)]
+    json: bool,
+    /// Known device of a PAN as `<pan>=<extended address>`, such as
+    /// `0x1a62=01:02:03:04:05:06:07:08`, frames from other extended addresses in the PAN are
+    /// reported
+    #[arg(long, value_parser = parse_known)]
+    known: Vec<(u16, u64)>,
+    /// Learn the devices sending within this many seconds from the first frame as known
+    /// devices
+    #[arg(long)]
+    learn: Option<f64>,
+    /// Beacon requests allowed within the beacon request window
+    #[arg(long, default_value = "10")]
+    beacon_requests: usize,
+    /// Beacon request window in seconds
+    #[arg(long, default_value = "10")]
+    beacon_request_window: f64,
+}
+
+fn parse_known(text: &str) -> Result<(u16, u64), String> {
+    let invalid = || {
+        format!(
+            "invalid device \"{}\", expected <pan>=<extended address>",
+            text
+        )
+    };
+    let (pan_id, address) = text.split_once('=').ok_or_else(invalid)?;
+    let pan_id = pan_id
+        .strip_prefix("0x")
+        .and_then(|pan_id| u16::from_str_radix(pan_id, 16).ok())
+        .ok_or_else(|| format!("invalid PAN identifier \"{}\"", pan_id))?;
+    match address.parse()? {
+        Address::Extended(address) => Ok((pan_id, address)),
+        Address::Short(_) => Err(invalid()),
+    }
+}
+
+fn rules(args: &Args) -> Rules {
+    let mut rules = Rules {
+        learning: args
+            .learn
+            .map(|learn| Duration::from_secs_f64(learn.max(0.0))),
+        beacon_requests: args.beacon_requests,
+        beacon_request_window: Duration::from_secs_f64(args.beacon_request_window.max(0.001)),
+        ..Rules::default()
+    };
+    for &(pan_id, address) in &args.known {
+        rules
+            .known_devices
+            .entry(pan_id)
+            .or_default()
+            .insert(address);
+    }
+    rules
+}
+
+fn pan(pan_id: Option<u16>) -> Value {
+    pan_id.map(|pan_id| format!("0x{:04x}", pan_id)).into()
+}
+
+fn address(address: Option<Address>) -> Value {
+    address.map(|address| address.to_string()).into()
+}
+
+/// JSON object of an alert, the fields after the message depend on the rule
+fn json(alert: &Alert) -> Value {
+    let mut members = vec![
+        ("timestamp", alert.timestamp.as_secs_f64().into()),
+        ("rule", String::from(alert.anomaly.rule()).into()),
+        ("message", alert.anomaly.to_string().into()),
+    ];
+    match alert.anomaly {
+        Anomaly::FrameCounter {
+            source,
+            key_index,
+            counter,
+            last,
+        } => members.extend([
+            ("pan", pan(source.pan_id)),
+            ("source", address(Some(source.address))),
+            ("key_index", key_index.into()),
+            ("counter", u64::from(counter).into()),
+            ("last", u64::from(last).into()),
+        ]),
+        Anomaly::UnknownDevice {
+            pan_id,
+            address: device,
+        } => members.extend([
+            ("pan", pan(Some(pan_id))),
+            ("source", address(Some(Address::Extended(device)))),
+        ]),
+        Anomaly::PanConflict {
+            pan_id,
+            coordinator,
+            other,
+        } => members.extend([
+            ("pan", pan(Some(pan_id))),
+            ("source", address(Some(other))),
+            ("coordinator", address(Some(coordinator))),
+        ]),
+        Anomaly::PanConflictNotification { pan_id, source } => {
+            members.extend([("pan", pan(pan_id)), ("source", address(source))])
+        }
+        Anomaly::BeaconRequests { count, window } => members.extend([
+            ("count", (count as u64).into()),
+            ("window", window.as_secs_f64().into()),
+        ]),
+    }
+    Value::Object(members)
+}
+
+fn report(args: &Args, alerts: Vec<Alert>) -> Result<(), Error> {
+    for alert in alerts {
+        if args.json {
+            let mut output = io::stdout().lock();
+            writeln!(output, "{}", json(&alert))?;
+            // Readers of a pipe see each alert as it is raised
+            output.flush()?;
+        } else {
+            eprintln!(
+                "{:.6} {}: {}",
+                alert.timestamp.as_secs_f64(),
+                alert.anomaly.rule(),
+                alert.anomaly
+            );
+        }
+    }
+    Ok(())
+}
+
+fn capture(args: &Args, detector: &mut Detector, stop: &AtomicBool) -> Result<(), Error> {
+    crate::capture(
+        args.device.as_deref(),
+        args.channel,
+        stop,
+        |frame| match frame {
+            Some(frame) => {
+                let timestamp = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default();
+                report(args, detector.add(&frame, timestamp))
+            }
+            None => Ok(()),
+        },
+    )
+}
+
+pub(crate) fn run(args: &Args, stop: &AtomicBool) -> Result<(), Error> {
+    let mut detector = Detector::new(rules(args));
+    match args.file.as_ref() {
+        Some(file) => {
+            for record in capture_file::read_file(file)? {
+                report(args, detector.add(&record.frame, record.timestamp))?;
+            }
+            Ok(())
+        }
+        None => capture(args, &mut detector, stop),
+    }
+}
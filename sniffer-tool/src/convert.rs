@@ -0,0 +1,117 @@
+//! Convert captures between IEEE 802.15.4 link types
+use crate::Error;
+use ieee802154_sniffer_host::{
+    capture_file,
+    link_type::LinkType,
+    pcapng::{CaptureWriter, InterfaceDescription, PacketAnnotation},
+    tap,
+};
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapWriter},
+    TsResolution,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// pcap or pcapng file with IEEE 802.15.4 or IEEE 802.15.4 TAP link type
+    input: PathBuf,
+    /// File to write, pcapng when the name ends with .pcapng and pcap otherwise
+    output: PathBuf,
+    /// Link type to write, tap, fcs or nofcs
+    #[arg(long, value_parser = parse_link_type)]
+    link_type: LinkType,
+    /// Channel of frames read without one, recorded in TAP headers
+    #[arg(long)]
+    channel: Option<u8>,
+}
+
+fn parse_link_type(name: &str) -> Result<LinkType, String> {
+    LinkType::from_name(name)
+        .ok_or_else(|| format!("invalid link type \"{}\", expected tap, fcs or nofcs", name))
+}
+
+pub(crate) fn run(args: &Args) -> Result<(), Error> {
+    let records = capture_file::read_file(&args.input)?;
+    let output = BufWriter::new(File::create(&args.output)?);
+    let pcapng = args
+        .output
+        .extension()
+        .is_some_and(|extension| extension == "pcapng");
+
+    let bad_fcs = records.iter().filter(|record| record.fcs_error).count();
+    let channel = |record: &capture_file::Record| match record.frame.channel {
+        0 => args.channel.unwrap_or_default(),
+        channel => channel,
+    };
+    let mut buffer = [0u8; tap::BUFFER_SIZE];
+    // pcap cannot flag a frame, frames with a bad FCS would be written with a valid one
+    let mut frames = records
+        .iter()
+        .filter(|record| pcapng || !record.fcs_error)
+        .map(|record| {
+            let mut frame = record.frame.clone();
+            frame.channel = channel(record);
+            let length = args.link_type.encode(&frame, &mut buffer);
+            (record, frame.channel, buffer[..length].to_vec())
+        });
+    let mut output = if pcapng {
+        let mut writer = CaptureWriter::new(output)?;
+        // The interface has a channel only when every frame was received on it
+        let first = records.first().map_or(0, channel);
+        let interface = InterfaceDescription {
+            name: args.input.display().to_string(),
+            channel: match records.iter().all(|record| channel(record) == first) {
+                true => first,
+                false => 0,
+            },
+            serial_number: None,
+        };
+        let interface_id = writer.add_interface(args.link_type.datalink(), &interface)?;
+        for (record, channel, data) in &mut frames {
+            let annotation = PacketAnnotation {
+                channel,
+                fcs_error: record.fcs_error,
+                ..PacketAnnotation::default()
+            };
+            writer.write_packet(interface_id, record.timestamp, &data, &annotation)?;
+        }
+        writer.finish()?
+    } else {
+        let header = PcapHeader {
+            datalink: args.link_type.datalink(),
+            ts_resolution: TsResolution::NanoSecond,
+            ..PcapHeader::default()
+        };
+        let mut writer = PcapWriter::with_header(output, header)?;
+        for (record, _, data) in &mut frames {
+            writer.write_packet(&PcapPacket::new(record.timestamp, data.len() as u32, &data))?;
+        }
+        writer.into_writer()
+    };
+    output.flush()?;
+    match (bad_fcs, pcapng) {
+        (0, _) => eprintln!(
+            "Wrote {} frames with link type {}",
+            records.len(),
+            args.link_type.name()
+        ),
+        (_, true) => eprintln!(
+            "Wrote {} frames with link type {}, {} flagged with a bad FCS",
+            records.len(),
+            args.link_type.name(),
+            bad_fcs
+        ),
+        (_, false) => eprintln!(
+            "Wrote {} frames with link type {}, skipped {} with a bad FCS",
+            records.len() - bad_fcs,
+            args.link_type.name(),
+            bad_fcs
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Export the IPv6 packets of a 6LoWPAN capture
+use crate::Error;
+use ieee802154_sniffer_host::{
+    capture_file, config_file,
+    sixlowpan::{Context, DecompressError, Decompressor},
+};
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapWriter},
+    DataLink,
+};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// pcap or pcapng file with IEEE 802.15.4 or IEEE 802.15.4 TAP link type
+    input: PathBuf,
+    /// pcap file to write, with IPv6 link type
+    output: PathBuf,
+    /// Configuration file with the `[sixlowpan]` contexts, instead of the configuration file
+    /// shared with the extcap
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Context for stateful address compression as `<id>=<prefix>`, such as
+    /// `0=fd11:22::/64` for the mesh-local prefix of a Thread network, overrides the
+    /// configuration file
+    #[arg(long, value_parser = parse_context)]
+    context: Vec<(u8, Context)>,
+}
+
+/// The section of the configuration file used here, the other sections are the extcap's
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Config {
+    sixlowpan: SixlowpanSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SixlowpanSettings {
+    contexts: Vec<ContextSettings>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContextSettings {
+    id: u8,
+    /// Prefix as `fd11:22::/64`
+    prefix: String,
+}
+
+fn context(id: u8, prefix: &str) -> Result<(u8, Context), String> {
+    if id >= 16 {
+        return Err(format!("invalid context identifier {}", id));
+    }
+    Ok((id, prefix.parse()?))
+}
+
+fn parse_context(text: &str) -> Result<(u8, Context), String> {
+    let (id, prefix) = text
+        .split_once('=')
+        .ok_or_else(|| format!("invalid context \"{}\", expected <id>=<prefix>", text))?;
+    let id = id
+        .parse()
+        .map_err(|_| format!("invalid context identifier \"{}\"", id))?;
+    context(id, prefix)
+}
+
+/// Contexts in the `[sixlowpan]` section of the configuration file
+fn configured_contexts(path: Option<&Path>) -> Result<Vec<(u8, Context)>, Error> {
+    let (path, text) = match config_file::read(path) {
+        Ok(Some(file)) => file,
+        Ok(None) => return Ok(Vec::new()),
+        Err((path, error)) => return Err(Error::Config(path, error.to_string())),
+    };
+    let config: Config = toml::from_str(&text)
+        .map_err(|error| Error::Config(path.clone(), error.message().to_string()))?;
+    config
+        .sixlowpan
+        .contexts
+        .iter()
+        .map(|settings| context(settings.id, &settings.prefix))
+        .collect::<Result<_, _>>()
+        .map_err(|error| Error::Config(path, error))
+}
+
+pub(crate) fn run(args: &Args) -> Result<(), Error> {
+    let contexts = configured_contexts(args.config.as_deref())?;
+    let records = capture_file::read_file(&args.input)?;
+    let mut decompressor = Decompressor::new();
+    for &(id, context) in contexts.iter().chain(&args.context) {
+        decompressor.set_context(id, context);
+    }
+
+    let header = PcapHeader {
+        datalink: DataLink::IPV6,
+        ..PcapHeader::default()
+    };
+    let output = BufWriter::new(File::create(&args.output)?);
+    let mut writer = PcapWriter::with_header(output, header)?;
+    let mut packets = 0;
+    let mut secured = 0;
+    let mut failed = 0;
+    for (index, record) in records.iter().enumerate() {
+        match decompressor.process(&record.frame.payload, record.timestamp) {
+            Ok(Some(packet)) => {
+                writer.write_packet(&PcapPacket::new(
+                    record.timestamp,
+                    packet.len() as u32,
+                    &packet,
+                ))?;
+                packets += 1;
+            }
+            Ok(None) | Err(DecompressError::NotData) | Err(DecompressError::NotLowpan) => {}
+            Err(DecompressError::Secured) => secured += 1,
+            Err(error) => {
+                eprintln!("Frame {}, {}", index + 1, error);
+                failed += 1;
+            }
+        }
+    }
+    writer
+        .into_writer()
+        .into_inner()
+        .map_err(|error| error.into_error())?;
+    eprintln!(
+        "Wrote {} IPv6 packets from {} frames, {} secured frames skipped, {} failed",
+        packets,
+        records.len(),
+        secured,
+        failed
+    );
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Frames as JSON Lines, live or from a capture file
+use crate::{json::Value, Error};
+use ieee802154_sniffer_host::{capture_file, mac, wire_format};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::atomic::AtomicBool,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Convert a pcap or pcapng file instead of capturing
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Sniffer to capture with, a serial port or tcp://host:port, the first sniffer found
+    /// by default
+    #[arg(long)]
+    device: Option<String>,
+    /// Channel to capture on
+    #[arg(long, default_value = "11")]
+    channel: u8,
+    /// File to write, standard output by default
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn pan(pan_id: Option<u16>) -> Value {
+    pan_id.map(|pan_id| format!("0x{:04x}", pan_id)).into()
+}
+
+fn address(address: Option<mac::Address>) -> Value {
+    address.map(|address| address.to_string()).into()
+}
+
+/// MAC header fields, `null` when the frame is too short for a MAC header
+fn header(payload: &[u8]) -> Value {
+    let Some((header, _)) = mac::Header::parse(payload) else {
+        return Value::Null;
+    };
+    Value::Object(vec![
+        ("frame_type", String::from(header.frame_type.name()).into()),
+        ("security_enabled", header.security_enabled.into()),
+        ("frame_pending", header.frame_pending.into()),
+        ("ack_request", header.ack_request.into()),
+        ("pan_id_compression", header.pan_id_compression.into()),
+        ("information_elements", header.information_elements.into()),
+        ("version", header.version.into()),
+        ("sequence", header.sequence.into()),
+        ("destination_pan", pan(header.destination_pan)),
+        ("destination", address(header.destination)),
+        ("source_pan", pan(header.source_pan)),
+        ("source", address(header.source)),
+    ])
+}
+
+/// JSON object of a frame without FCS captured at `timestamp` since the Unix epoch
+fn line(frame: &wire_format::Frame, timestamp: Duration) -> Value {
+    let payload: String = frame
+        .payload
+        .iter()
+        .map(|octet| format!("{:02x}", octet))
+        .collect();
+    Value::Object(vec![
+        ("timestamp", timestamp.as_secs_f64().into()),
+        (
+            "channel",
+            (frame.channel != 0).then_some(frame.channel).into(),
+        ),
+        (
+            "rssi",
+            frame
+                .received_signal_strength_indicator
+                .map(|rssi| f64::from(rssi) / 1000.0)
+                .into(),
+        ),
+        ("lqi", frame.link_quality_index.into()),
+        ("payload", payload.into()),
+        ("mac", header(&frame.payload)),
+    ])
+}
+
+fn capture(args: &Args, output: &mut dyn Write, stop: &AtomicBool) -> Result<(), Error> {
+    crate::capture(args.device.as_deref(), args.channel, stop, |frame| {
+        if let Some(frame) = frame {
+            let timestamp = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            writeln!(output, "{}", line(&frame, timestamp))?;
+            // Readers of a pipe see each frame as it is captured
+            output.flush()?;
+        }
+        Ok(())
+    })
+}
+
+pub(crate) fn run(args: &Args, stop: &AtomicBool) -> Result<(), Error> {
+    let mut output: Box<dyn Write> = match args.output.as_ref() {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    match args.file.as_ref() {
+        Some(file) => {
+            for record in capture_file::read_file(file)? {
+                writeln!(output, "{}", line(&record.frame, record.timestamp))?;
+            }
+        }
+        None => capture(args, &mut output, stop)?,
+    }
+    output.flush()?;
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use clap::{Parser, Subcommand};
+use ieee802154_sniffer_host::{self as host, discovery, transport, wire_format, Device};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+mod acks;
+mod airtime;
+mod anomalies;
+mod convert;
+mod ipv6;
+mod json;
+mod jsonl;
+mod replay;
+mod summary;
+mod topology;
+
+/// Time to wait for a sniffer to answer a probe or a command
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    Host(host::Error),
+    NoSniffer,
+    Config(std::path::PathBuf, String),
+}
+
+impl From<host::Error> for Error {
+    fn from(error: host::Error) -> Self {
+        Error::Host(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Host(error.into())
+    }
+}
+
+impl From<pcap_file::PcapError> for Error {
+    fn from(error: pcap_file::PcapError) -> Self {
+        Error::Host(error.into())
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Host(error) => write!(f, "{}", error),
+            Error::NoSniffer => write!(f, "No sniffer found"),
+            Error::Config(path, error) => write!(
+                f,
+                "Invalid configuration file {}, {}",
+                path.display(),
+                error
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Tools for IEEE 802.15.4 sniffers and their captures")]
+pub struct AppArgs {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Transmit the frames of a capture file through a sniffer
+    Replay(replay::Args),
+    /// Write the IPv6 packets of a 6LoWPAN capture to a pcap file
+    Ipv6(ipv6::Args),
+    /// Show the PANs, devices and frame counts on a channel, live or from a capture file
+    Summary(summary::Args),
+    /// Report the PANs, devices and links of a capture file as JSON or a Graphviz graph
+    Topology(topology::Args),
+    /// Pair acknowledgements with their frames and report retransmissions per link
+    Acks(acks::Args),
+    /// Measure airtime, duty cycle, frame rate and frame sizes per channel, live or from a
+    /// capture file
+    Airtime(airtime::Args),
+    /// Write frames as JSON Lines with their metadata and MAC header, live or from a capture
+    /// file
+    Jsonl(jsonl::Args),
+    /// Convert a capture file between the IEEE 802.15.4, without FCS and TAP link types
+    Convert(convert::Args),
+    /// Report replayed frame counters, unknown devices, PAN ID conflicts and bursts of beacon
+    /// requests, live or from a capture file
+    Anomalies(anomalies::Args),
+}
+
+/// Open the sniffer at `uri`, or the first sniffer found
+pub(crate) fn open_device(uri: Option<&str>) -> Result<Device, Error> {
+    let uri = match uri {
+        Some(uri) => uri.to_string(),
+        None => {
+            discovery::discover(TIMEOUT)?
+                .into_iter()
+                .next()
+                .ok_or(Error::NoSniffer)?
+                .uri
+        }
+    };
+    let mut device = Device::open(&uri, TIMEOUT)?;
+    device.probe()?;
+    Ok(device)
+}
+
+/// Capture on `channel` with the sniffer at `uri`, or the first sniffer found, until `stop`
+/// is set or `each` fails
+///
+/// `each` is called after every read with the captured frame, or `None` when no frame was
+/// read, so that it can also do periodic work. The capture is stopped also after an error.
+pub(crate) fn capture(
+    uri: Option<&str>,
+    channel: u8,
+    stop: &AtomicBool,
+    mut each: impl FnMut(Option<wire_format::Frame>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut device = open_device(uri)?;
+    device.set_channel(channel)?;
+    device.start_capture()?;
+    let result = receive(&mut device, stop, &mut each);
+    let stopped = device.stop_capture().map_err(Error::from);
+    result.and(stopped)
+}
+
+fn receive(
+    device: &mut Device,
+    stop: &AtomicBool,
+    each: &mut impl FnMut(Option<wire_format::Frame>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    while !stop.load(Ordering::Relaxed) {
+        let frame = match device.receive() {
+            Ok(Some(wire_format::Packet::CaptureFrame(frame))) => Some(frame),
+            Ok(_) | Err(host::Error::Wire(_)) => None,
+            Err(host::Error::Io(ref error)) if transport::is_transient(error) => None,
+            Err(error) => return Err(error.into()),
+        };
+        each(frame)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = AppArgs::parse();
+
+    let term = Arc::new(AtomicBool::new(false));
+    let registered = [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT]
+        .into_iter()
+        .try_for_each(|signal| signal_hook::flag::register(signal, Arc::clone(&term)).map(drop));
+
+    let result = registered
+        .map_err(Error::from)
+        .and_then(|()| match args.command {
+            Command::Replay(args) => replay::run(&args, &term),
+            Command::Ipv6(args) => ipv6::run(&args),
+            Command::Summary(args) => summary::run(&args, &term),
+            Command::Topology(args) => topology::run(&args),
+            Command::Acks(args) => acks::run(&args),
+            Command::Airtime(args) => airtime::run(&args, &term),
+            Command::Jsonl(args) => jsonl::run(&args, &term),
+            Command::Convert(args) => convert::run(&args),
+            Command::Anomalies(args) => anomalies::run(&args, &term),
+        });
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,92 @@
+//! Transmit a capture file through a sniffer
+use crate::{open_device, Error};
+use ieee802154_sniffer_host::{
+    capture_file::{self, Record},
+    replay, wire_format,
+};
+use std::{path::PathBuf, sync::atomic::AtomicBool};
+
+/// Channel used when neither the arguments nor the capture give one
+const DEFAULT_CHANNEL: u8 = 11;
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// pcap or pcapng file with IEEE 802.15.4 or IEEE 802.15.4 TAP link type
+    file: PathBuf,
+    /// Sniffer to transmit with, a serial port or tcp://host:port, the first sniffer found
+    /// by default
+    #[arg(long)]
+    device: Option<String>,
+    /// Channel to transmit on, by default the channel of the first frame in a TAP capture
+    /// or 11
+    #[arg(long)]
+    channel: Option<u8>,
+    /// Transmit power in dBm
+    #[arg(long)]
+    power: Option<i32>,
+    /// Replay speed relative to the capture, 0 sends the frames back to back
+    #[arg(long, default_value = "1")]
+    speed: f64,
+}
+
+fn status_name(record: &Record, status: Option<wire_format::TransmitStatus>) -> &'static str {
+    match status {
+        Some(wire_format::TransmitStatus::Sent) => "sent",
+        Some(wire_format::TransmitStatus::ChannelBusy) => "channel busy",
+        Some(wire_format::TransmitStatus::Failed) => "failed",
+        Some(wire_format::TransmitStatus::Unsupported) => "unsupported",
+        None if record.fcs_error => "bad FCS",
+        None => "too long",
+    }
+}
+
+pub(crate) fn run(args: &Args, stop: &AtomicBool) -> Result<(), Error> {
+    let records = capture_file::read_file(&args.file)?;
+    let channel = args
+        .channel
+        .or_else(|| {
+            records
+                .iter()
+                .map(|record| record.frame.channel)
+                .find(|&channel| channel != 0)
+        })
+        .unwrap_or(DEFAULT_CHANNEL);
+
+    let mut device = open_device(args.device.as_deref())?;
+    device.set_channel(channel)?;
+    if let Some(power) = args.power {
+        device.set_power(power)?;
+    }
+    eprintln!("Replaying {} frames on channel {}", records.len(), channel);
+
+    let first = records.first().map(|record| record.timestamp);
+    let summary = replay::replay(
+        &mut device,
+        &records,
+        args.speed,
+        stop,
+        |index, record, status| {
+            let offset = record.timestamp.saturating_sub(first.unwrap_or_default());
+            println!(
+                "{:6} {:12.6} {:4} {}",
+                index + 1,
+                offset.as_secs_f64(),
+                record.frame.payload.len(),
+                status_name(record, status)
+            );
+        },
+    )?;
+    eprintln!(
+        "Transmitted {} of {} frames, {} sent, {} channel busy, {} failed, {} unsupported, \
+         {} too long, {} bad FCS",
+        summary.total(),
+        records.len(),
+        summary.sent,
+        summary.channel_busy,
+        summary.failed,
+        summary.unsupported,
+        summary.too_long,
+        summary.bad_fcs
+    );
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Live or offline summary of the traffic on a channel
+use crate::Error;
+use ieee802154_sniffer_host::{
+    capture_file,
+    mac::FrameType,
+    summary::{Range, Summary},
+};
+use std::{
+    fmt::Write,
+    path::PathBuf,
+    sync::atomic::AtomicBool,
+    time::{Duration, Instant},
+};
+
+/// Clear the terminal and move the cursor to the top left corner
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Summarise a pcap or pcapng file instead of capturing
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Sniffer to capture with, a serial port or tcp://host:port, the first sniffer found
+    /// by default
+    #[arg(long)]
+    device: Option<String>,
+    /// Channel to capture on
+    #[arg(long, default_value = "11")]
+    channel: u8,
+    /// Seconds between screen updates
+    #[arg(long, default_value = "1")]
+    interval: f64,
+}
+
+fn range(range: Option<Range>, scale: f64, precision: usize) -> String {
+    match range {
+        Some(range) => format!(
+            "{:.*}/{:.*}/{:.*}",
+            precision,
+            f64::from(range.min) / scale,
+            precision,
+            range.mean() / scale,
+            precision,
+            f64::from(range.max) / scale
+        ),
+        None => String::from("-"),
+    }
+}
+
+fn pan(pan_id: Option<u16>) -> String {
+    pan_id.map_or_else(|| String::from("-"), |pan_id| format!("0x{:04x}", pan_id))
+}
+
+/// Format the summary as a table, with the frame rate given in frames per second
+fn render(summary: &Summary, rate: f64) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "{} frames, {:.1} frames/s, {} malformed",
+        summary.frames, rate, summary.malformed
+    );
+    let types: Vec<String> = FrameType::ALL
+        .iter()
+        .filter_map(|frame_type| {
+            summary
+                .frame_types
+                .get(frame_type)
+                .map(|count| format!("{} {}", frame_type.name(), count))
+        })
+        .collect();
+    let _ = writeln!(text, "Types: {}", types.join(", "));
+
+    let _ = writeln!(
+        text,
+        "\n{:<8} {:>8} {:>8} {:>8}",
+        "PAN", "Frames", "Beacons", "Devices"
+    );
+    for (pan_id, pan) in &summary.pans {
+        let _ = writeln!(
+            text,
+            "{:<8} {:>8} {:>8} {:>8}",
+            format!("0x{:04x}", pan_id),
+            pan.frames,
+            pan.beacons,
+            pan.devices.len()
+        );
+    }
+
+    let _ = writeln!(
+        text,
+        "\n{:<8} {:<23} {:>8} {:>23} {:>15}",
+        "PAN", "Source", "Frames", "RSSI min/avg/max dBm", "LQI min/avg/max"
+    );
+    for (device, source) in &summary.sources {
+        let _ = writeln!(
+            text,
+            "{:<8} {:<23} {:>8} {:>23} {:>15}",
+            pan(device.pan_id),
+            device.address.to_string(),
+            source.frames,
+            range(source.rssi, 1000.0, 1),
+            range(source.lqi, 1.0, 0)
+        );
+    }
+    text
+}
+
+fn capture(args: &Args, stop: &AtomicBool) -> Result<(), Error> {
+    let interval = Duration::from_secs_f64(args.interval.max(0.1));
+    let mut summary = Summary::new();
+    print!("{}{}", CLEAR_SCREEN, render(&summary, 0.0));
+    // Time from the start of the capture, after the sniffer was found and configured
+    let mut started = None;
+    let mut updated = Instant::now();
+    crate::capture(args.device.as_deref(), args.channel, stop, |frame| {
+        let start = *started.get_or_insert_with(Instant::now);
+        if let Some(frame) = frame {
+            summary.add(&frame, start.elapsed());
+        }
+        if updated.elapsed() >= interval {
+            updated = Instant::now();
+            let rate = summary.rate(start.elapsed());
+            print!("{}{}", CLEAR_SCREEN, render(&summary, rate));
+        }
+        Ok(())
+    })
+}
+
+pub(crate) fn run(args: &Args, stop: &AtomicBool) -> Result<(), Error> {
+    match args.file.as_ref() {
+        Some(file) => {
+            let mut summary = Summary::new();
+            for record in capture_file::read_file(file)? {
+                summary.add(&record.frame, record.timestamp);
+            }
+            print!("{}", render(&summary, summary.mean_rate()));
+            Ok(())
+        }
+        None => capture(args, stop),
+    }
+}
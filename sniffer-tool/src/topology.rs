@@ -0,0 +1,201 @@
+//! Network topology and device inventory of a capture file
+use crate::{json::Value, Error};
+use ieee802154_sniffer_host::{
+    capture_file,
+    mac::Address,
+    summary::{Device, Range},
+    topology::Topology,
+};
+use std::{fmt::Write, path::PathBuf};
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum Format {
+    /// Inventory of PANs, devices and links
+    Json,
+    /// Graphviz graph of the devices and links
+    Dot,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// pcap or pcapng file with IEEE 802.15.4 or IEEE 802.15.4 TAP link type
+    file: PathBuf,
+    #[arg(long, value_enum, default_value = "json")]
+    format: Format,
+    /// File to write, standard output by default
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn pan(pan_id: Option<u16>) -> String {
+    pan_id.map_or_else(|| String::from("-"), |pan_id| format!("0x{:04x}", pan_id))
+}
+
+fn extended(address: Option<u64>) -> Option<String> {
+    address.map(|address| Address::Extended(address).to_string())
+}
+
+/// Minimum, mean and maximum, divided by `scale`
+fn range(range: Option<Range>, scale: f64) -> Value {
+    match range {
+        Some(range) => Value::Object(vec![
+            ("min", (f64::from(range.min) / scale).into()),
+            ("mean", (range.mean() / scale).into()),
+            ("max", (f64::from(range.max) / scale).into()),
+        ]),
+        None => Value::Null,
+    }
+}
+
+fn device_members(device: &Device) -> Vec<(&'static str, Value)> {
+    vec![
+        (
+            "pan_id",
+            device
+                .pan_id
+                .map(|pan_id| format!("0x{:04x}", pan_id))
+                .into(),
+        ),
+        ("address", device.address.to_string().into()),
+    ]
+}
+
+fn json(topology: &Topology) -> String {
+    let pans = topology
+        .pans
+        .iter()
+        .map(|(pan_id, pan)| {
+            let channels: Vec<u8> = pan.channels.iter().copied().collect();
+            Value::Object(vec![
+                ("pan_id", format!("0x{:04x}", pan_id).into()),
+                (
+                    "coordinator",
+                    pan.coordinator.map(|address| address.to_string()).into(),
+                ),
+                ("channels", channels.into()),
+                ("frames", pan.frames.into()),
+                ("beacons", pan.beacons.into()),
+            ])
+        })
+        .collect();
+    let devices = topology
+        .nodes
+        .iter()
+        .map(|(key, node)| {
+            let mut members = device_members(key);
+            members.extend([
+                ("extended", extended(topology.extended_address(key)).into()),
+                ("frames", node.frames.into()),
+                (
+                    "first_seen",
+                    node.first_seen.map(|time| time.as_secs_f64()).into(),
+                ),
+                (
+                    "last_seen",
+                    node.last_seen.map(|time| time.as_secs_f64()).into(),
+                ),
+            ]);
+            Value::Object(members)
+        })
+        .collect();
+    let links = topology
+        .links
+        .iter()
+        .map(|((source, destination), link)| {
+            Value::Object(vec![
+                ("source", Value::Object(device_members(source))),
+                ("destination", Value::Object(device_members(destination))),
+                ("frames", link.frames.into()),
+                ("rssi", range(link.rssi, 1000.0)),
+                ("lqi", range(link.lqi, 1.0)),
+            ])
+        })
+        .collect();
+    let report = Value::Object(vec![
+        ("pans", Value::Array(pans)),
+        ("devices", Value::Array(devices)),
+        ("links", Value::Array(links)),
+    ]);
+    format!("{}\n", report)
+}
+
+/// Graphviz node identifier
+fn node_id(device: &Device) -> String {
+    format!("\"{} {}\"", pan(device.pan_id), device.address)
+}
+
+fn write_node(text: &mut String, topology: &Topology, indent: &str, key: &Device) {
+    let coordinator = key.pan_id.is_some_and(|pan_id| {
+        topology.pans.get(&pan_id).and_then(|pan| pan.coordinator) == Some(key.address)
+    });
+    let mut label = key.address.to_string();
+    if let (Address::Short(_), Some(address)) = (key.address, topology.extended_address(key)) {
+        label = format!("{}\\n{}", label, Address::Extended(address));
+    }
+    let shape = if coordinator {
+        "doublecircle"
+    } else {
+        "ellipse"
+    };
+    let _ = writeln!(
+        text,
+        "{}{} [label=\"{}\", shape={}];",
+        indent,
+        node_id(key),
+        label,
+        shape
+    );
+}
+
+fn dot(topology: &Topology) -> String {
+    let mut text = String::from("digraph topology {\n");
+    for pan_id in topology.pans.keys() {
+        let _ = writeln!(text, "    subgraph \"cluster_0x{:04x}\" {{", pan_id);
+        let _ = writeln!(text, "        label=\"PAN 0x{:04x}\";", pan_id);
+        for key in topology
+            .nodes
+            .keys()
+            .filter(|key| key.pan_id == Some(*pan_id))
+        {
+            write_node(&mut text, topology, "        ", key);
+        }
+        let _ = writeln!(text, "    }}");
+    }
+    for key in topology.nodes.keys().filter(|key| {
+        key.pan_id
+            .is_none_or(|pan_id| !topology.pans.contains_key(&pan_id))
+    }) {
+        write_node(&mut text, topology, "    ", key);
+    }
+    for ((source, destination), link) in &topology.links {
+        let mut label = format!("{} frames", link.frames);
+        if let Some(rssi) = link.rssi {
+            label = format!("{}\\n{:.1} dBm", label, rssi.mean() / 1000.0);
+        }
+        let _ = writeln!(
+            text,
+            "    {} -> {} [label=\"{}\"];",
+            node_id(source),
+            node_id(destination),
+            label
+        );
+    }
+    text.push_str("}\n");
+    text
+}
+
+pub(crate) fn run(args: &Args) -> Result<(), Error> {
+    let mut topology = Topology::new();
+    for record in capture_file::read_file(&args.file)? {
+        topology.add(&record.frame, record.timestamp);
+    }
+    let report = match args.format {
+        Format::Json => json(&topology),
+        Format::Dot => dot(&topology),
+    };
+    match args.output.as_ref() {
+        Some(output) => std::fs::write(output, report)?,
+        None => print!("{}", report),
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Acknowledgement and retransmission reports of capture files
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapWriter},
+    DataLink,
+};
+use std::{path::PathBuf, process::Command, time::Duration};
+
+/// Data from 0x3c1f to 0x0000 requesting an acknowledgement
+fn data(sequence: u8) -> Vec<u8> {
+    vec![
+        0x61, 0x88, sequence, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0xaa,
+    ]
+}
+
+fn ack(sequence: u8) -> Vec<u8> {
+    vec![0x02, 0x00, sequence]
+}
+
+/// Frames with their start of frame time in microseconds
+fn frames() -> Vec<(Vec<u8>, u64)> {
+    vec![
+        (data(5), 0),
+        // Retransmitted and acknowledged 192 µs after the 576 µs frame
+        (data(5), 2_000),
+        (ack(5), 2_768),
+        (data(6), 10_000),
+        (ack(6), 10_768),
+        // Acknowledged too late
+        (data(7), 20_000),
+        (ack(7), 22_000),
+    ]
+}
+
+/// IEEE 802.15.4 TAP header with no FCS and the start of frame time
+fn tap(frame: &[u8], start_of_frame: Duration) -> Vec<u8> {
+    let mut data = vec![0x00, 0x00, 0x18, 0x00];
+    data.extend([0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    data.extend([0x05, 0x00, 0x08, 0x00]);
+    data.extend((start_of_frame.as_nanos() as u64).to_le_bytes());
+    data.extend(frame);
+    data
+}
+
+fn capture(name: &str, device_timestamps: bool) -> PathBuf {
+    let datalink = if device_timestamps {
+        DataLink::IEEE802_15_4_TAP
+    } else {
+        DataLink::IEEE802_15_4_NOFCS
+    };
+    let header = PcapHeader {
+        datalink,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    for (n, (frame, micros)) in frames().into_iter().enumerate() {
+        let start_of_frame = Duration::from_micros(micros);
+        let data = if device_timestamps {
+            tap(&frame, start_of_frame)
+        } else {
+            frame
+        };
+        // The host receives the frames in bursts
+        let timestamp = Duration::from_secs(1_700_000_000) + Duration::from_millis(n as u64 / 2);
+        writer
+            .write_packet(&PcapPacket::new(timestamp, data.len() as u32, &data))
+            .unwrap();
+    }
+    let path = std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-acks-{}-{}.pcap",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, writer.into_writer()).unwrap();
+    path
+}
+
+/// Report and notes of the acks command
+fn acks(file: PathBuf) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("acks")
+        .arg(&file)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&file);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn device_timestamps() {
+    let (report, notes) = acks(capture("device", true));
+    assert_eq!(notes, "");
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "Device timestamps, 864 µs window");
+    assert_eq!(lines[1], "2 acknowledgements matched, 1 unmatched");
+    assert_eq!(
+        lines[4],
+        "0x1a62 0x3c1f                  0x1a62 0x0000                         3        1         2   66.7%  0:2 1:1"
+    );
+}
+
+#[test]
+fn capture_timestamps() {
+    let (report, notes) = acks(capture("capture", false));
+    assert!(notes.starts_with("No device timestamps in the capture"));
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "Capture timestamps, 20000 µs window");
+    assert_eq!(lines[1], "3 acknowledgements matched, 0 unmatched");
+    assert!(lines[4].ends_with("3        1         3  100.0%  0:2 1:1"));
+}
@@ -0,0 +1,84 @@
+//! Airtime and duty cycle of capture files
+use ieee802154_sniffer_host::{
+    pcapng::{CaptureWriter, InterfaceDescription, PacketAnnotation},
+    tap, wire_format,
+};
+use pcap_file::DataLink;
+use std::{process::Command, time::Duration};
+
+#[test]
+fn airtime_of_file() {
+    let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+    let interface = InterfaceDescription {
+        name: String::from("/dev/ttyACM0"),
+        channel: 20,
+        serial_number: None,
+    };
+    writer
+        .add_interface(DataLink::IEEE802_15_4_TAP, &interface)
+        .unwrap();
+    let mut buffer = [0u8; tap::BUFFER_SIZE];
+    let start = Duration::from_secs(1_700_000_000);
+    // Ten frames of the longest size, then five acknowledgements
+    for n in 0..15u64 {
+        let length = if n < 10 { 125 } else { 3 };
+        let frame = wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&vec![0x02; length]).unwrap(),
+        };
+        let length = tap::encode(&frame, &mut buffer);
+        writer
+            .write_packet(
+                0,
+                start + Duration::from_millis(100 * n),
+                &buffer[..length],
+                &PacketAnnotation::default(),
+            )
+            .unwrap();
+    }
+    let name = format!("ieee802154-sniffer-airtime-{}", std::process::id());
+    let file = std::env::temp_dir().join(format!("{}.pcapng", name));
+    let csv = std::env::temp_dir().join(format!("{}.csv", name));
+    std::fs::write(&file, writer.finish().unwrap()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("airtime")
+        .arg("--file")
+        .arg(&file)
+        .args(["--interval", "0.5", "--window", "1"])
+        .arg("--csv")
+        .arg(&csv)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&file);
+    let series = std::fs::read_to_string(&csv).unwrap_or_default();
+    let _ = std::fs::remove_file(&csv);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "     0.5 s  channel 20       5 frames      5.0 frames/s    2.1% duty cycle"
+    );
+    assert!(stdout.contains("Channel 20: 15 frames, 0.044 s airtime, 3.2% duty cycle\n"));
+    assert!(stdout.contains("     0-15        5\n"));
+    assert!(stdout.contains("     112+       10\n"));
+
+    let rows: Vec<&str> = series.lines().collect();
+    assert_eq!(
+        rows,
+        [
+            "time,channel,frames,airtime_us,frames_per_second,duty_cycle",
+            "0.500,20,5,21280,5.000,0.021280",
+            "1.000,20,5,21280,10.000,0.042560",
+            "1.400,20,5,1760,11.000,0.027296",
+        ]
+    );
+}
@@ -0,0 +1,195 @@
+//! Alerts raised for a corpus of crafted frames
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapWriter},
+    DataLink,
+};
+use std::{process::Command, time::Duration};
+
+/// Frames without FCS and the milliseconds at which they are captured
+const CORPUS: [(u64, &[u8]); 14] = [
+    // Beacon of PAN coordinator 0x0000 in PAN 0x1a62
+    (
+        0,
+        &[
+            0x00, 0x80, 0x01, 0x62, 0x1a, 0x00, 0x00, 0xff, 0xcf, 0x00, 0x00,
+        ],
+    ),
+    // Data from the known device 01:02:03:04:05:06:07:08
+    (
+        100,
+        &[
+            0x41, 0xc8, 0x02, 0x62, 0x1a, 0x00, 0x00, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02,
+            0x01, 0xaa,
+        ],
+    ),
+    // Secured data from 0x3c1f, frame counter 100, sent twice
+    (
+        200,
+        &[
+            0x69, 0x88, 0x03, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x09, 0x64, 0x00, 0x00, 0x00,
+            0x01, 0xaa, 0x01, 0x02, 0x03, 0x04,
+        ],
+    ),
+    (
+        205,
+        &[
+            0x69, 0x88, 0x03, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x09, 0x64, 0x00, 0x00, 0x00,
+            0x01, 0xaa, 0x01, 0x02, 0x03, 0x04,
+        ],
+    ),
+    // Frame counter 101, then 101 again and 99 with other sequence numbers
+    (
+        300,
+        &[
+            0x69, 0x88, 0x04, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x09, 0x65, 0x00, 0x00, 0x00,
+            0x01, 0xbb, 0x05, 0x06, 0x07, 0x08,
+        ],
+    ),
+    (
+        400,
+        &[
+            0x69, 0x88, 0x05, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x09, 0x65, 0x00, 0x00, 0x00,
+            0x01, 0xcc, 0x09, 0x0a, 0x0b, 0x0c,
+        ],
+    ),
+    (
+        500,
+        &[
+            0x69, 0x88, 0x06, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x09, 0x63, 0x00, 0x00, 0x00,
+            0x01, 0xdd, 0x0d, 0x0e, 0x0f, 0x10,
+        ],
+    ),
+    // Data from the unknown device 01:02:03:04:05:06:07:09
+    (
+        600,
+        &[
+            0x41, 0xc8, 0x07, 0x62, 0x1a, 0x00, 0x00, 0x09, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02,
+            0x01, 0xaa,
+        ],
+    ),
+    // Beacon of a second PAN coordinator 0x0001 in PAN 0x1a62
+    (
+        700,
+        &[
+            0x00, 0x80, 0x08, 0x62, 0x1a, 0x01, 0x00, 0xff, 0xcf, 0x00, 0x00,
+        ],
+    ),
+    // PAN ID conflict notification from the known device
+    (
+        800,
+        &[
+            0x63, 0xc8, 0x09, 0x62, 0x1a, 0x00, 0x00, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02,
+            0x01, 0x05,
+        ],
+    ),
+    // Beacon requests, the third within a second is one too many
+    (900, &[0x03, 0x08, 0x0a, 0xff, 0xff, 0xff, 0xff, 0x07]),
+    (1000, &[0x03, 0x08, 0x0b, 0xff, 0xff, 0xff, 0xff, 0x07]),
+    (1100, &[0x03, 0x08, 0x0c, 0xff, 0xff, 0xff, 0xff, 0x07]),
+    (1200, &[0x03, 0x08, 0x0d, 0xff, 0xff, 0xff, 0xff, 0x07]),
+];
+
+fn check(name: &str, args: &[&str]) -> (String, String) {
+    let header = PcapHeader {
+        datalink: DataLink::IEEE802_15_4_NOFCS,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    for (ms, frame) in CORPUS {
+        let timestamp = Duration::from_secs(1_700_000_000) + Duration::from_millis(ms);
+        writer
+            .write_packet(&PcapPacket::new(timestamp, frame.len() as u32, frame))
+            .unwrap();
+    }
+    let file = std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-anomalies-{}-{}.pcap",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&file, writer.into_writer()).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("anomalies")
+        .arg("--file")
+        .arg(&file)
+        .args(["--beacon-requests", "2", "--beacon-request-window", "1"])
+        .args(args)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&file);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn alerts_as_json() {
+    let (alerts, errors) = check(
+        "json",
+        &["--json", "--known", "0x1a62=01:02:03:04:05:06:07:08"],
+    );
+    assert_eq!(errors, "");
+    assert_eq!(
+        alerts,
+        concat!(
+            r#"{"timestamp":1700000000.4,"rule":"frame-counter","message":"Frame counter 101 reused by 0x1a62 0x3c1f, key index 1","#,
+            r#""pan":"0x1a62","source":"0x3c1f","key_index":1,"counter":101,"last":101}"#,
+            "\n",
+            r#"{"timestamp":1700000000.5,"rule":"frame-counter","message":"Frame counter 99 after 101 from 0x1a62 0x3c1f, key index 1","#,
+            r#""pan":"0x1a62","source":"0x3c1f","key_index":1,"counter":99,"last":101}"#,
+            "\n",
+            r#"{"timestamp":1700000000.6,"rule":"unknown-device","message":"Unknown device 01:02:03:04:05:06:07:09 in PAN 0x1a62","#,
+            r#""pan":"0x1a62","source":"01:02:03:04:05:06:07:09"}"#,
+            "\n",
+            r#"{"timestamp":1700000000.7,"rule":"pan-conflict","message":"Second PAN coordinator 0x0001 in PAN 0x1a62, coordinator 0x0000","#,
+            r#""pan":"0x1a62","source":"0x0001","coordinator":"0x0000"}"#,
+            "\n",
+            r#"{"timestamp":1700000000.8,"rule":"pan-conflict","message":"PAN ID conflict notification from 01:02:03:04:05:06:07:08 in PAN 0x1a62","#,
+            r#""pan":"0x1a62","source":"01:02:03:04:05:06:07:08"}"#,
+            "\n",
+            r#"{"timestamp":1700000001.1,"rule":"beacon-requests","message":"3 beacon requests within 1 s","count":3,"window":1}"#,
+            "\n",
+        )
+    );
+}
+
+#[test]
+fn alerts_as_text() {
+    let (alerts, errors) = check("text", &["--known", "0x1a62=01:02:03:04:05:06:07:08"]);
+    assert_eq!(alerts, "");
+    let lines: Vec<&str> = errors.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(
+        lines[2],
+        "1700000000.600000 unknown-device: Unknown device 01:02:03:04:05:06:07:09 in PAN 0x1a62"
+    );
+}
+
+#[test]
+fn devices_learned() {
+    let rules = |learn: &str| {
+        let (alerts, _) = check("learn", &["--json", "--learn", learn]);
+        alerts
+            .lines()
+            .map(|line| line.split('"').nth(5).unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    // The device sending at 0.6 s is unknown unless learned
+    assert_eq!(
+        rules("0.5"),
+        [
+            "frame-counter",
+            "frame-counter",
+            "unknown-device",
+            "pan-conflict",
+            "pan-conflict",
+            "beacon-requests"
+        ]
+    );
+    assert!(!rules("0.7").contains(&String::from("unknown-device")));
+}
@@ -0,0 +1,265 @@
+//! Conversion of captures between IEEE 802.15.4 link types
+use ieee802154_sniffer_host::{capture_file, link_type::LinkType, tap, wire_format};
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapReader, PcapWriter},
+    pcapng::{
+        blocks::{
+            enhanced_packet::EnhancedPacketOption,
+            interface_description::InterfaceDescriptionOption,
+        },
+        Block, PcapNgReader,
+    },
+    DataLink,
+};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+/// Frames with FCS
+const FRAMES: [&[u8]; 2] = [
+    &[0x02, 0x00, 0x04, 0x9c, 0xf3],
+    &[
+        0x41, 0x88, 0x02, 0x62, 0x1a, 0x00, 0x00, 0x01, 0x00, 0xaa, 0x22, 0x65,
+    ],
+];
+
+fn path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-convert-{}-{}",
+        std::process::id(),
+        name
+    ))
+}
+
+/// Capture time of a frame, in microseconds as other sniffers write pcap files
+fn timestamp(n: usize) -> Duration {
+    Duration::new(1_700_000_000 + n as u64, 123_456_000)
+}
+
+/// Convert a file, returns the report
+fn convert(input: &Path, output: &Path, link_type: &str, channel: Option<u8>) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"));
+    command
+        .arg("convert")
+        .arg(input)
+        .arg(output)
+        .args(["--link-type", link_type]);
+    if let Some(channel) = channel {
+        command.args(["--channel", &channel.to_string()]);
+    }
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stderr).unwrap()
+}
+
+fn read_pcap(path: &Path) -> (DataLink, Vec<(Duration, Vec<u8>)>) {
+    let data = std::fs::read(path).unwrap();
+    let mut reader = PcapReader::new(&data[..]).unwrap();
+    let datalink = reader.header().datalink;
+    let mut packets = Vec::new();
+    while let Some(packet) = reader.next_packet() {
+        let packet = packet.unwrap();
+        packets.push((packet.timestamp, packet.data.to_vec()));
+    }
+    (datalink, packets)
+}
+
+#[test]
+fn convert_between_link_types() {
+    let original = path("original.pcap");
+    let header = PcapHeader {
+        datalink: DataLink::IEEE802_15_4,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    for (n, frame) in FRAMES.into_iter().enumerate() {
+        writer
+            .write_packet(&PcapPacket::new(timestamp(n), frame.len() as u32, frame))
+            .unwrap();
+    }
+    std::fs::write(&original, writer.into_writer()).unwrap();
+
+    // Add TAP headers with the channel
+    let with_tap = path("tap.pcapng");
+    assert_eq!(
+        convert(&original, &with_tap, "tap", Some(15)),
+        "Wrote 2 frames with link type tap\n"
+    );
+    let data = std::fs::read(&with_tap).unwrap();
+    let mut reader = PcapNgReader::new(&data[..]).unwrap();
+    let mut linktype = None;
+    while let Some(block) = reader.next_block() {
+        if let Block::InterfaceDescription(interface) = block.unwrap() {
+            linktype = Some(interface.linktype);
+        }
+    }
+    assert_eq!(linktype, Some(DataLink::IEEE802_15_4_TAP));
+    let records = capture_file::read(&data).unwrap();
+    assert_eq!(records.len(), 2);
+    for (n, record) in records.iter().enumerate() {
+        assert_eq!(record.frame.channel, 15);
+        assert_eq!(&record.frame.payload[..], &FRAMES[n][..FRAMES[n].len() - 2]);
+        assert_eq!(record.timestamp, timestamp(n));
+    }
+
+    // Strip the TAP headers, then add the FCS again
+    let without_fcs = path("nofcs.pcap");
+    assert_eq!(
+        convert(&with_tap, &without_fcs, "nofcs", None),
+        "Wrote 2 frames with link type nofcs\n"
+    );
+    let (datalink, packets) = read_pcap(&without_fcs);
+    assert_eq!(datalink, DataLink::IEEE802_15_4_NOFCS);
+    assert_eq!(packets[1].1, &FRAMES[1][..FRAMES[1].len() - 2]);
+
+    let with_fcs = path("fcs.pcap");
+    assert_eq!(
+        convert(&without_fcs, &with_fcs, "fcs", None),
+        "Wrote 2 frames with link type fcs\n"
+    );
+    let (datalink, packets) = read_pcap(&with_fcs);
+    assert_eq!(datalink, DataLink::IEEE802_15_4);
+    for (n, packet) in packets.into_iter().enumerate() {
+        assert_eq!(packet, (timestamp(n), FRAMES[n].to_vec()));
+    }
+
+    for file in [original, with_tap, without_fcs, with_fcs] {
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+#[test]
+fn convert_bad_fcs() {
+    let original = path("bad.pcap");
+    let header = PcapHeader {
+        datalink: DataLink::IEEE802_15_4,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    let corrupted: &[u8] = &[0x02, 0x00, 0x04, 0x9c, 0xf4];
+    for (n, frame) in [FRAMES[0], corrupted].into_iter().enumerate() {
+        writer
+            .write_packet(&PcapPacket::new(timestamp(n), frame.len() as u32, frame))
+            .unwrap();
+    }
+    std::fs::write(&original, writer.into_writer()).unwrap();
+
+    // pcapng flags the frame, the flag is read back
+    let flagged = path("flagged.pcapng");
+    assert_eq!(
+        convert(&original, &flagged, "fcs", None),
+        "Wrote 2 frames with link type fcs, 1 flagged with a bad FCS\n"
+    );
+    let records = capture_file::read_file(&flagged).unwrap();
+    assert_eq!(
+        records
+            .iter()
+            .map(|record| record.fcs_error)
+            .collect::<Vec<_>>(),
+        [false, true]
+    );
+
+    // pcap cannot flag it, it is left out rather than written with a valid FCS
+    let skipped = path("skipped.pcap");
+    assert_eq!(
+        convert(&flagged, &skipped, "fcs", None),
+        "Wrote 1 frames with link type fcs, skipped 1 with a bad FCS\n"
+    );
+    let (_, packets) = read_pcap(&skipped);
+    assert_eq!(packets, [(timestamp(0), FRAMES[0].to_vec())]);
+
+    for file in [original, flagged, skipped] {
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+/// Interface descriptions and packet comments of a pcapng file
+fn descriptions_and_comments(path: &Path) -> (Vec<String>, Vec<String>) {
+    let data = std::fs::read(path).unwrap();
+    let mut reader = PcapNgReader::new(&data[..]).unwrap();
+    let mut descriptions = Vec::new();
+    let mut comments = Vec::new();
+    while let Some(block) = reader.next_block() {
+        match block.unwrap() {
+            Block::InterfaceDescription(interface) => {
+                for option in interface.options {
+                    if let InterfaceDescriptionOption::IfDescription(description) = option {
+                        descriptions.push(description.to_string());
+                    }
+                }
+            }
+            Block::EnhancedPacket(packet) => {
+                for option in packet.options {
+                    if let EnhancedPacketOption::Comment(comment) = option {
+                        comments.push(comment.to_string());
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    (descriptions, comments)
+}
+
+#[test]
+fn convert_channels() {
+    // TAP frames received on channel 11, then on channel 26
+    let original = path("channels.pcap");
+    let header = PcapHeader {
+        datalink: DataLink::IEEE802_15_4_TAP,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    let mut buffer = [0u8; tap::BUFFER_SIZE];
+    for (n, channel) in [11, 26].into_iter().enumerate() {
+        let frame = wire_format::Frame {
+            channel,
+            received_signal_strength_indicator: None,
+            link_quality_index: None,
+            payload: wire_format::Payload::from_slice(&FRAMES[n][..FRAMES[n].len() - 2]).unwrap(),
+        };
+        let length = LinkType::Tap.encode(&frame, &mut buffer);
+        writer
+            .write_packet(&PcapPacket::new(
+                timestamp(n),
+                length as u32,
+                &buffer[..length],
+            ))
+            .unwrap();
+    }
+    std::fs::write(&original, writer.into_writer()).unwrap();
+
+    // The interface has no single channel, each frame records its own
+    let converted = path("channels.pcapng");
+    convert(&original, &converted, "fcs", Some(15));
+    assert_eq!(
+        descriptions_and_comments(&converted),
+        (
+            vec![String::from("IEEE 802.15.4 Sniffer")],
+            vec![String::from("Channel 11"), String::from("Channel 26")]
+        )
+    );
+
+    // Frames without a channel are given the channel argument
+    let without_channel = path("channels-nofcs.pcap");
+    convert(&original, &without_channel, "nofcs", None);
+    let given = path("channels-given.pcapng");
+    convert(&without_channel, &given, "tap", Some(15));
+    assert_eq!(
+        descriptions_and_comments(&given),
+        (
+            vec![String::from("IEEE 802.15.4 Sniffer, channel 15")],
+            vec![String::from("Channel 15"), String::from("Channel 15")]
+        )
+    );
+
+    for file in [original, converted, without_channel, given] {
+        let _ = std::fs::remove_file(file);
+    }
+}
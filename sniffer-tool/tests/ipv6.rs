@@ -0,0 +1,176 @@
+//! Export IPv6 packets from 6LoWPAN captures
+use ieee802154_sniffer_host::fcs;
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapReader, PcapWriter},
+    DataLink,
+};
+use std::{path::PathBuf, process::Command, time::Duration};
+
+fn path(name: &str, extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-ipv6-{}-{}.{}",
+        name,
+        std::process::id(),
+        extension
+    ))
+}
+
+const START: Duration = Duration::from_secs(1_700_000_000);
+
+/// Capture with a beacon, a UDP datagram from a mesh-local source in two fragments and a
+/// secured data frame
+fn capture() -> Vec<u8> {
+    let frames: [&[u8]; 4] = [
+        // Beacon
+        &[
+            0x00, 0x80, 0x01, 0xcd, 0xab, 0x01, 0x00, 0xff, 0xcf, 0x00, 0x00,
+        ],
+        // Second fragment first, then the first fragment with the mesh-local source
+        &[
+            0x41, 0x88, 0x02, 0xcd, 0xab, 0x03, 0x00, 0x02, 0x00, 0xe0, 0x38, 0x00, 0x07, 0x06,
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+        ],
+        &[
+            0x41, 0x88, 0x03, 0xcd, 0xab, 0x03, 0x00, 0x02, 0x00, 0xc0, 0x38, 0x00, 0x07, 0x7e,
+            0xf3, 0x00, 0xf0, 0x16, 0x33, 0x16, 0x33, 0xab, 0xcd,
+        ],
+        // Secured data frame
+        &[
+            0x69, 0x88, 0x04, 0xcd, 0xab, 0x03, 0x00, 0x02, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x00,
+        ],
+    ];
+    let header = PcapHeader {
+        datalink: DataLink::IEEE802_15_4,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    for (n, frame) in frames.iter().enumerate() {
+        let mut data = frame.to_vec();
+        data.extend_from_slice(&fcs::compute(frame).to_le_bytes());
+        let timestamp = START + Duration::from_millis(10 * n as u64);
+        writer
+            .write_packet(&PcapPacket::new(timestamp, data.len() as u32, &data))
+            .unwrap();
+    }
+    writer.into_writer()
+}
+
+/// Export the capture with `args`, reading only the configuration file given in `args`,
+/// returning the result and the packets written
+fn export(name: &str, args: &[&str]) -> (std::process::Output, Vec<u8>) {
+    let input = path(&format!("{}-input", name), "pcap");
+    let output = path(&format!("{}-output", name), "pcap");
+    std::fs::write(&input, capture()).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("ipv6")
+        .arg(&input)
+        .arg(&output)
+        .args(args)
+        .env_remove("IEEE802154_SNIFFER_CONFIG")
+        .env("XDG_CONFIG_HOME", path(name, "config"))
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&input);
+    let data = std::fs::read(&output).unwrap_or_default();
+    let _ = std::fs::remove_file(&output);
+    (result, data)
+}
+
+/// Source address of the first packet written
+fn source(data: &[u8]) -> std::net::Ipv6Addr {
+    let mut reader = PcapReader::new(data).unwrap();
+    let packet = reader.next_packet().unwrap().unwrap();
+    <[u8; 16]>::try_from(&packet.data[8..24]).unwrap().into()
+}
+
+#[test]
+fn export_ipv6() {
+    let (result, data) = export("context", &["--context", "0=fd11:22::/64"]);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(result.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("Wrote 1 IPv6 packets from 4 frames, 1 secured frames skipped, 0 failed"),
+        "{}",
+        stderr
+    );
+
+    let mut reader = PcapReader::new(&data[..]).unwrap();
+    assert_eq!(reader.header().datalink, DataLink::IPV6);
+    let packet = reader.next_packet().unwrap().unwrap();
+    assert_eq!(packet.timestamp, START + Duration::from_millis(20));
+    assert_eq!(packet.data.len(), 56);
+    assert_eq!(&packet.data[..8], &[0x60, 0, 0, 0, 0x00, 0x10, 17, 64]);
+    assert_eq!(
+        &packet.data[8..24],
+        &"fd11:22::ff:fe00:2"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets()
+    );
+    assert_eq!(
+        &packet.data[40..],
+        &[
+            0x16, 0x33, 0x16, 0x33, 0x00, 0x10, 0xab, 0xcd, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
+            0x06, 0x07
+        ]
+    );
+    assert!(reader.next_packet().is_none());
+}
+
+#[test]
+fn configured_context() {
+    let config = path("configured", "toml");
+    std::fs::write(
+        &config,
+        "[capture]\nchannel = 15\n\n[[sixlowpan.contexts]]\nid = 0\nprefix = \"fd11:22::/64\"\n",
+    )
+    .unwrap();
+    let (result, data) = export("configured", &["--config", config.to_str().unwrap()]);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    assert_eq!(
+        source(&data),
+        "fd11:22::ff:fe00:2".parse::<std::net::Ipv6Addr>().unwrap()
+    );
+
+    // The argument overrides the file
+    let (result, data) = export(
+        "overridden",
+        &[
+            "--config",
+            config.to_str().unwrap(),
+            "--context",
+            "0=fd33:44::/64",
+        ],
+    );
+    let _ = std::fs::remove_file(&config);
+    assert!(result.status.success());
+    assert_eq!(
+        source(&data),
+        "fd33:44::ff:fe00:2".parse::<std::net::Ipv6Addr>().unwrap()
+    );
+}
+
+#[test]
+fn invalid_configured_context() {
+    let config = path("invalid", "toml");
+    std::fs::write(
+        &config,
+        "[[sixlowpan.contexts]]\nid = 16\nprefix = \"fd11:22::/64\"\n",
+    )
+    .unwrap();
+    let (result, _) = export("invalid", &["--config", config.to_str().unwrap()]);
+    let _ = std::fs::remove_file(&config);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(!result.status.success());
+    assert!(stderr.contains("Invalid configuration file"), "{}", stderr);
+    assert!(
+        stderr.contains("invalid context identifier 16"),
+        "{}",
+        stderr
+    );
+}
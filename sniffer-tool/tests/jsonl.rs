@@ -0,0 +1,148 @@
+//! Frames as JSON Lines from capture files and live captures
+use ieee802154_sniffer_host::{
+    pcapng::{CaptureWriter, InterfaceDescription, PacketAnnotation},
+    tap, wire_format,
+};
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapWriter},
+    DataLink,
+};
+use std::{process::Command, time::Duration};
+
+const DATA: [u8; 10] = [0x61, 0x88, 0x05, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0xaa];
+
+fn convert(name: &str, capture: Vec<u8>) -> String {
+    let file = std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-jsonl-{}-{}.pcapng",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&file, capture).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("jsonl")
+        .arg("--file")
+        .arg(&file)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&file);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn convert_tap_capture() {
+    let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+    let interface = InterfaceDescription {
+        name: String::from("/dev/ttyACM0"),
+        channel: 20,
+        serial_number: None,
+    };
+    writer
+        .add_interface(DataLink::IEEE802_15_4_TAP, &interface)
+        .unwrap();
+    let mut buffer = [0u8; tap::BUFFER_SIZE];
+    let frame = wire_format::Frame {
+        channel: 20,
+        received_signal_strength_indicator: Some(-70_500),
+        link_quality_index: Some(200),
+        payload: wire_format::Payload::from_slice(&DATA).unwrap(),
+    };
+    let length = tap::encode(&frame, &mut buffer);
+    let timestamp = Duration::new(1_700_000_000, 250_000_000);
+    writer
+        .write_packet(
+            0,
+            timestamp,
+            &buffer[..length],
+            &PacketAnnotation::default(),
+        )
+        .unwrap();
+
+    assert_eq!(
+        convert("tap", writer.finish().unwrap()),
+        concat!(
+            r#"{"timestamp":1700000000.25,"channel":20,"rssi":-70.5,"lqi":200,"payload":"618805621a00001f3caa","#,
+            r#""mac":{"frame_type":"data","security_enabled":false,"frame_pending":false,"ack_request":true,"#,
+            r#""pan_id_compression":true,"information_elements":false,"version":0,"sequence":5,"#,
+            r#""destination_pan":"0x1a62","destination":"0x0000","source_pan":null,"source":"0x3c1f"}}"#,
+            "\n"
+        )
+    );
+}
+
+#[test]
+fn convert_without_metadata() {
+    let header = PcapHeader {
+        datalink: DataLink::IEEE802_15_4_NOFCS,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    let timestamp = Duration::from_secs(1_700_000_000);
+    writer
+        .write_packet(&PcapPacket::new(timestamp, 1, &[0x02]))
+        .unwrap();
+
+    assert_eq!(
+        convert("nofcs", writer.into_writer()),
+        concat!(
+            r#"{"timestamp":1700000000,"channel":null,"rssi":null,"lqi":null,"payload":"02","mac":null}"#,
+            "\n"
+        )
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn live_capture() {
+    use ieee802154_sniffer_simulator::{Config, Pty, Simulator, Source};
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    let pty = Pty::open().unwrap();
+    let path = pty.path().clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator_stop = Arc::clone(&stop);
+    let simulator = thread::spawn(move || {
+        let config = Config {
+            source: Source::Replay(vec![wire_format::Payload::from_slice(&DATA).unwrap()]),
+            rate: 50.0,
+            ..Config::default()
+        };
+        Simulator::new(pty, config).run(&simulator_stop).unwrap();
+    });
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("jsonl")
+        .arg("--device")
+        .arg(&path)
+        .args(["--channel", "20"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    child.kill().unwrap();
+    child.wait().unwrap();
+    stop.store(true, Ordering::Relaxed);
+    simulator.join().unwrap();
+
+    assert!(line.starts_with(r#"{"timestamp":"#), "{}", line);
+    assert!(
+        line.contains(r#","channel":20,"#) && line.contains(r#""payload":"618805621a00001f3caa""#),
+        "{}",
+        line
+    );
+    assert!(line.ends_with("\"source\":\"0x3c1f\"}}\n"), "{}", line);
+}
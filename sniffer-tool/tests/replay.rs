@@ -0,0 +1,150 @@
+//! Replay capture files through the virtual sniffer
+#![cfg(unix)]
+
+use ieee802154_sniffer_host::{
+    fcs,
+    pcapng::{CaptureWriter, InterfaceDescription, PacketAnnotation},
+    tap, wire_format,
+};
+use ieee802154_sniffer_simulator::{Config, Pty, Simulator};
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapWriter},
+    DataLink,
+};
+use std::{
+    path::PathBuf,
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+fn input_path(name: &str, extension: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-replay-{}-{}.{}",
+        name,
+        std::process::id(),
+        extension
+    ))
+}
+
+/// Replay a file through a simulator, returns the output lines, the time taken and the
+/// simulator
+fn replay(file: &PathBuf, args: &[&str]) -> (Vec<String>, Duration, Simulator<Pty>) {
+    let pty = Pty::open().unwrap();
+    let path = pty.path().clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator_stop = Arc::clone(&stop);
+    let simulator = thread::spawn(move || {
+        let config = Config {
+            rate: 0.0,
+            ..Config::default()
+        };
+        let mut simulator = Simulator::new(pty, config);
+        simulator.run(&simulator_stop).unwrap();
+        simulator
+    });
+
+    let start = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("replay")
+        .arg(file)
+        .arg("--device")
+        .arg(&path)
+        .args(args)
+        .output()
+        .unwrap();
+    let elapsed = start.elapsed();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    stop.store(true, Ordering::Relaxed);
+    let simulator = simulator.join().unwrap();
+    let _ = std::fs::remove_file(file);
+    let lines = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    (lines, elapsed, simulator)
+}
+
+#[test]
+fn replay_pcap_with_fcs() {
+    let file = input_path("fcs", "pcap");
+    let header = PcapHeader {
+        datalink: DataLink::IEEE802_15_4,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    let start = Duration::from_secs(1_700_000_000);
+    for (n, milliseconds) in [0, 200, 400].into_iter().enumerate() {
+        let mut data = [0x02, 0x00, n as u8, 0x00, 0x00];
+        // The last frame keeps a bad FCS
+        if n < 2 {
+            fcs::append(&mut data, 3);
+        }
+        let timestamp = start + Duration::from_millis(milliseconds);
+        writer
+            .write_packet(&PcapPacket::new(timestamp, 5, &data))
+            .unwrap();
+    }
+    std::fs::write(&file, writer.into_writer()).unwrap();
+
+    let (lines, elapsed, simulator) = replay(&file, &["--channel", "15", "--speed", "2"]);
+    assert_eq!(lines.len(), 3);
+    assert!(lines[..2].iter().all(|line| line.ends_with(" 3 sent")));
+    assert!(lines[2].contains("0.400000"));
+    assert!(lines[2].ends_with(" 3 bad FCS"));
+    // The last frame is due 200 ms after the first at double speed
+    assert!(elapsed >= Duration::from_millis(200));
+    assert_eq!(simulator.statistics().transmitted, 2);
+    assert_eq!(simulator.channel(), 15);
+}
+
+#[test]
+fn replay_pcapng_tap_without_delay() {
+    let file = input_path("tap", "pcapng");
+    let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+    let interface = InterfaceDescription {
+        name: String::from("/dev/ttyACM0"),
+        channel: 25,
+        serial_number: None,
+    };
+    writer
+        .add_interface(DataLink::IEEE802_15_4_TAP, &interface)
+        .unwrap();
+    let mut buffer = [0u8; tap::BUFFER_SIZE];
+    for n in 0..5u8 {
+        let frame = wire_format::Frame {
+            channel: 25,
+            received_signal_strength_indicator: Some(-40_000),
+            link_quality_index: Some(255),
+            payload: wire_format::Payload::from_slice(&[0x41, 0x88, n, 0xcd, 0xab]).unwrap(),
+        };
+        let length = tap::encode(&frame, &mut buffer);
+        let timestamp = Duration::from_secs(1_700_000_000 + u64::from(n) * 60);
+        writer
+            .write_packet(
+                0,
+                timestamp,
+                &buffer[..length],
+                &PacketAnnotation::default(),
+            )
+            .unwrap();
+    }
+    std::fs::write(&file, writer.finish().unwrap()).unwrap();
+
+    let (lines, elapsed, simulator) = replay(&file, &["--speed", "0"]);
+    assert_eq!(lines.len(), 5);
+    assert!(lines.iter().all(|line| line.ends_with(" 5 sent")));
+    assert!(elapsed < Duration::from_secs(10));
+    assert_eq!(simulator.statistics().transmitted, 5);
+    assert_eq!(simulator.channel(), 25);
+}
@@ -0,0 +1,104 @@
+//! Summarise live traffic and capture files
+use pcap_file::{
+    pcap::{PcapHeader, PcapPacket, PcapWriter},
+    DataLink,
+};
+use std::{process::Command, time::Duration};
+
+const BEACON: [u8; 11] = [
+    0x00, 0x80, 0x01, 0x62, 0x1a, 0x00, 0x00, 0xff, 0xcf, 0x00, 0x00,
+];
+const DATA: [u8; 10] = [0x41, 0x88, 0x02, 0x62, 0x1a, 0x00, 0x00, 0x01, 0x00, 0xaa];
+
+#[test]
+fn summarise_file() {
+    let file = std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-summary-{}.pcap",
+        std::process::id()
+    ));
+    let header = PcapHeader {
+        datalink: DataLink::IEEE802_15_4_NOFCS,
+        ..PcapHeader::default()
+    };
+    let mut writer = PcapWriter::with_header(Vec::new(), header).unwrap();
+    let start = Duration::from_secs(1_700_000_000);
+    for n in 0..5u64 {
+        let frame: &[u8] = if n == 0 { &BEACON } else { &DATA };
+        let timestamp = start + Duration::from_millis(250 * n);
+        writer
+            .write_packet(&PcapPacket::new(timestamp, frame.len() as u32, frame))
+            .unwrap();
+    }
+    std::fs::write(&file, writer.into_writer()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("summary")
+        .arg("--file")
+        .arg(&file)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&file);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "5 frames, 4.0 frames/s, 0 malformed");
+    assert_eq!(lines[1], "Types: beacon 1, data 4");
+    assert!(stdout.contains("0x1a62          5        1        2\n"));
+    assert!(stdout.contains(
+        "0x1a62   0x0001                         4                       -               -\n"
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn summarise_live_capture() {
+    use ieee802154_sniffer_host::wire_format::Payload;
+    use ieee802154_sniffer_simulator::{Config, Pty, Simulator, Source};
+    use std::{
+        process::Stdio,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    let pty = Pty::open().unwrap();
+    let path = pty.path().clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let simulator_stop = Arc::clone(&stop);
+    let simulator = thread::spawn(move || {
+        let config = Config {
+            source: Source::Replay(vec![Payload::from_slice(&DATA).unwrap()]),
+            rate: 50.0,
+            ..Config::default()
+        };
+        Simulator::new(pty, config).run(&simulator_stop).unwrap();
+    });
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("summary")
+        .arg("--device")
+        .arg(&path)
+        .args(["--channel", "20", "--interval", "0.2"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    child.kill().unwrap();
+    let output = child.wait_with_output().unwrap();
+    stop.store(true, Ordering::Relaxed);
+    simulator.join().unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // Each update redraws the screen
+    let screens: Vec<&str> = stdout.split("\x1b[2J\x1b[H").skip(1).collect();
+    assert!(screens.len() > 2, "{}", stdout);
+    let last = screens.last().unwrap();
+    assert!(last.contains("0x1a62   0x0001"), "{}", last);
+    assert!(!last.starts_with("0 frames"), "{}", last);
+}
@@ -0,0 +1,118 @@
+//! Topology reports of capture files
+use ieee802154_sniffer_host::{
+    pcapng::{CaptureWriter, InterfaceDescription, PacketAnnotation},
+    tap, wire_format,
+};
+use pcap_file::DataLink;
+use std::{path::PathBuf, process::Command, time::Duration};
+
+fn capture(name: &str) -> PathBuf {
+    let frames: [(&[u8], i32); 3] = [
+        // Beacon from the PAN coordinator
+        (
+            &[
+                0x00, 0x80, 0x01, 0x62, 0x1a, 0x00, 0x00, 0xff, 0xcf, 0x00, 0x00,
+            ],
+            -40_000,
+        ),
+        // Data from 0x3c1f to 0x0000 with the Zigbee network header source IEEE address
+        (
+            &[
+                0x41, 0x88, 0x05, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x08, 0x10, 0x00, 0x00, 0x1f,
+                0x3c, 0x1e, 0x42, 0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01, 0xaa,
+            ],
+            -70_000,
+        ),
+        (
+            &[
+                0x41, 0x88, 0x06, 0x62, 0x1a, 0x00, 0x00, 0x1f, 0x3c, 0x08, 0x10, 0x00, 0x00, 0x1f,
+                0x3c, 0x1e, 0x43, 0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01, 0xbb,
+            ],
+            -60_000,
+        ),
+    ];
+    let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+    let interface = InterfaceDescription {
+        name: String::from("/dev/ttyACM0"),
+        channel: 20,
+        serial_number: None,
+    };
+    writer
+        .add_interface(DataLink::IEEE802_15_4_TAP, &interface)
+        .unwrap();
+    let mut buffer = [0u8; tap::BUFFER_SIZE];
+    for (n, (payload, rssi)) in frames.into_iter().enumerate() {
+        let frame = wire_format::Frame {
+            channel: 20,
+            received_signal_strength_indicator: Some(rssi),
+            link_quality_index: Some(200 + n as u8),
+            payload: wire_format::Payload::from_slice(payload).unwrap(),
+        };
+        let length = tap::encode(&frame, &mut buffer);
+        let timestamp = Duration::from_secs(1_700_000_000 + n as u64);
+        writer
+            .write_packet(
+                0,
+                timestamp,
+                &buffer[..length],
+                &PacketAnnotation::default(),
+            )
+            .unwrap();
+    }
+    let path = std::env::temp_dir().join(format!(
+        "ieee802154-sniffer-topology-{}-{}.pcapng",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, writer.finish().unwrap()).unwrap();
+    path
+}
+
+fn topology(name: &str, format: &str) -> String {
+    let file = capture(name);
+    let output = Command::new(env!("CARGO_BIN_EXE_ieee802154-sniffer-tool"))
+        .arg("topology")
+        .arg(&file)
+        .args(["--format", format])
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_file(&file);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn json_inventory() {
+    let json = topology("json", "json");
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"pans":[{"pan_id":"0x1a62","coordinator":"0x0000","channels":[20],"frames":3,"beacons":1}],"#,
+            r#""devices":["#,
+            r#"{"pan_id":"0x1a62","address":"0x0000","extended":null,"frames":1,"first_seen":1700000000,"last_seen":1700000002},"#,
+            r#"{"pan_id":"0x1a62","address":"0x3c1f","extended":"01:23:45:67:89:ab:cd:ef","frames":2,"first_seen":1700000001,"last_seen":1700000002}],"#,
+            r#""links":[{"source":{"pan_id":"0x1a62","address":"0x3c1f"},"destination":{"pan_id":"0x1a62","address":"0x0000"},"#,
+            r#""frames":2,"rssi":{"min":-70,"mean":-65,"max":-60},"lqi":{"min":201,"mean":201.5,"max":202}}]}"#,
+            "\n"
+        )
+    );
+}
+
+#[test]
+fn dot_graph() {
+    let dot = topology("dot", "dot");
+    assert!(dot.starts_with("digraph topology {\n"));
+    assert!(dot.contains("    subgraph \"cluster_0x1a62\" {\n        label=\"PAN 0x1a62\";\n"));
+    assert!(dot.contains("        \"0x1a62 0x0000\" [label=\"0x0000\", shape=doublecircle];\n"));
+    assert!(dot.contains(
+        "        \"0x1a62 0x3c1f\" [label=\"0x3c1f\\n01:23:45:67:89:ab:cd:ef\", shape=ellipse];\n"
+    ));
+    assert!(dot.contains(
+        "    \"0x1a62 0x3c1f\" -> \"0x1a62 0x0000\" [label=\"2 frames\\n-65.0 dBm\"];\n"
+    ));
+    assert!(dot.ends_with("}\n"));
+}
@@ -4,6 +4,9 @@
 use core::convert::From;
 use serde::{Deserialize, Serialize};
 
+/// Serial port baud rate between host and device
+pub const BAUD_RATE: u32 = 250_000;
+
 pub const PROBE_HOST: u32 = 0xfedcba98;
 pub const PROBE_DEVICE: u32 = 0x01234567;
 
@@ -19,6 +22,18 @@ impl From<postcard::Error> for Error {
     }
 }
 
+#[cfg(feature = "std")]
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::PostcardError(error) => write!(f, "Wire format error, {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
 #[cfg(feature = "defmt")]
 impl defmt::Format for Error {
     fn format(&self, fmt: defmt::Formatter) {
@@ -32,6 +47,9 @@ impl defmt::Format for Error {
 
 pub type Payload = heapless::Vec<u8, 256>;
 
+/// Longest frame that can be transmitted, 127 octets without the 2 octet FCS
+pub const MAX_TRANSMIT_LENGTH: usize = 125;
+
 #[derive(Clone, Deserialize, PartialEq, Serialize)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct Frame {
@@ -44,6 +62,51 @@ pub struct Frame {
     pub payload: Payload,
 }
 
+/// Frame to transmit, acknowledged by the device with a [`TransmitResult`]
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Transmit {
+    /// Identifier chosen by the host, echoed in the result
+    pub id: u32,
+    /// Frame without FCS, the radio appends the FCS
+    pub payload: Payload,
+}
+
+/// Outcome of a transmission
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum TransmitStatus {
+    /// The frame was sent
+    Sent,
+    /// The channel was busy, clear channel assessment failed
+    ChannelBusy,
+    /// The radio failed to send the frame
+    Failed,
+    /// The device cannot transmit
+    Unsupported,
+}
+
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct TransmitResult {
+    /// Identifier of the [`Transmit`] request
+    pub id: u32,
+    pub status: TransmitStatus,
+}
+
+/// Device state, sent by the device after its reply to a probe
+///
+/// A device that was reset has lost its configuration, the host compares the state with the
+/// configuration it applied to find out.
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct State {
+    /// Channel the radio is tuned to
+    pub channel: u8,
+    /// Captured frames are sent to the host
+    pub capturing: bool,
+}
+
 #[derive(Clone, Deserialize, PartialEq, Serialize)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub enum Packet {
@@ -55,6 +118,9 @@ pub enum Packet {
     CaptureStart,
     CaptureStop,
     CaptureFrame(Frame),
+    Transmit(Transmit),
+    TransmitResult(TransmitResult),
+    State(State),
 }
 
 impl Packet {
@@ -95,6 +161,27 @@ impl defmt::Format for Packet {
             Self::CaptureFrame(ref frame) => {
                 defmt::write!(fmt, "Capture Frame {}", frame.payload.len());
             }
+            Self::Transmit(ref transmit) => {
+                defmt::write!(fmt, "Transmit {} {}", transmit.id, transmit.payload.len());
+            }
+            Self::TransmitResult(ref result) => {
+                defmt::write!(fmt, "Transmit Result {} {}", result.id, result.status);
+            }
+            Self::State(ref state) => {
+                defmt::write!(fmt, "State {} {}", state.channel, state.capturing);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for TransmitStatus {
+    fn format(&self, fmt: defmt::Formatter) {
+        match *self {
+            Self::Sent => defmt::write!(fmt, "Sent"),
+            Self::ChannelBusy => defmt::write!(fmt, "Channel Busy"),
+            Self::Failed => defmt::write!(fmt, "Failed"),
+            Self::Unsupported => defmt::write!(fmt, "Unsupported"),
         }
     }
 }
@@ -166,4 +253,37 @@ mod tests {
             assert_eq!(&mut nothing, remainder);
         }
     }
+
+    #[test]
+    fn transmit() {
+        let buffer = &mut [0u8; 32];
+        let payload = Payload::from_slice(&[0x02, 0x00, 0x04]).unwrap();
+        let packet = Packet::Transmit(Transmit { id: 1, payload });
+        let output = packet.encode(buffer).unwrap();
+        assert_eq!(&[0x05, 0x08, 0x01, 0x03, 0x02, 0x02, 0x04, 0x00], output);
+        let (decoded, _) = Packet::decode(output).unwrap();
+        assert_eq!(packet, decoded);
+
+        let packet = Packet::TransmitResult(TransmitResult {
+            id: 300,
+            status: TransmitStatus::ChannelBusy,
+        });
+        let output = packet.encode(buffer).unwrap();
+        assert_eq!(&[0x05, 0x09, 0xac, 0x02, 0x01, 0x00], output);
+        let (decoded, _) = Packet::decode(output).unwrap();
+        assert_eq!(packet, decoded);
+    }
+
+    #[test]
+    fn state() {
+        let buffer = &mut [0u8; 32];
+        let packet = Packet::State(State {
+            channel: 15,
+            capturing: true,
+        });
+        let output = packet.encode(buffer).unwrap();
+        assert_eq!(&[0x04, 0x0a, 0x0f, 0x01, 0x00], output);
+        let (decoded, _) = Packet::decode(output).unwrap();
+        assert_eq!(packet, decoded);
+    }
 }